@@ -0,0 +1,379 @@
+use core::convert::TryFrom;
+
+use crate::registers::RegisterWidth;
+use crate::uart::{FrameFormat, MmioUart8250, Parity, StopBits};
+use uart_common::BaudRate;
+
+/// Length of the SPCR table up to and including the PCI segment, the fields every revision has
+const SPCR_MIN_LENGTH: usize = 80;
+
+/// An error encountered while parsing an SPCR table or setting up its UART
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpcrError {
+    /// The table doesn't start with the `SPCR` signature
+    BadSignature,
+    /// The table is shorter than its header says or than its revision requires
+    Truncated,
+    /// The bytes of the table don't sum to zero
+    BadChecksum,
+    /// A reserved value in the baud rate, parity or stop bits fields
+    Reserved,
+    /// The UART is not 8250 compatible
+    UnsupportedInterface(InterfaceType),
+    /// The registers are neither in system memory nor, on x86_64, in the I/O port space
+    NotMmio(AddressSpace),
+    /// The address doesn't fit in its address space, e.g. an I/O port above 0xffff
+    BadAddress,
+    /// The access size of the registers is not 1, 2 or 4 bytes
+    UnsupportedAccessWidth,
+}
+
+/// Interface Type field of the SPCR table, as listed in the DBG2 specification
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InterfaceType {
+    /// 0x00: Full 16550 interface
+    Full16550,
+    /// 0x01: 16550 subset interface
+    Subset16550,
+    /// 0x03: ARM PL011 UART
+    Pl011,
+    /// 0x06: NVIDIA 16550 UART
+    Nvidia16550,
+    /// 0x0E: ARM SBSA (32-bit only) generic UART
+    Sbsa32,
+    /// 0x0F: ARM SBSA generic UART
+    Sbsa,
+    /// 0x13: 16550 compatible with parameters defined in the generic address structure
+    Gas16550,
+    /// 0x15: Intel LPSS
+    IntelLpss,
+    /// Any other interface type
+    Other(u8),
+}
+
+impl InterfaceType {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0x00 => InterfaceType::Full16550,
+            0x01 => InterfaceType::Subset16550,
+            0x03 => InterfaceType::Pl011,
+            0x06 => InterfaceType::Nvidia16550,
+            0x0e => InterfaceType::Sbsa32,
+            0x0f => InterfaceType::Sbsa,
+            0x13 => InterfaceType::Gas16550,
+            0x15 => InterfaceType::IntelLpss,
+            other => InterfaceType::Other(other),
+        }
+    }
+
+    /// Whether `MmioUart8250` can drive this interface
+    pub fn is_8250_compatible(self) -> bool {
+        matches!(
+            self,
+            InterfaceType::Full16550
+                | InterfaceType::Subset16550
+                | InterfaceType::Nvidia16550
+                | InterfaceType::Gas16550
+                | InterfaceType::IntelLpss
+        )
+    }
+}
+
+/// Address Space ID of the generic address structure
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AddressSpace {
+    /// 0x00: System memory
+    Memory,
+    /// 0x01: System I/O
+    Io,
+    /// Any other address space, e.g. PCI configuration space
+    Other(u8),
+}
+
+/// # Serial Port Console Redirection table
+///
+/// Describes the UART the firmware uses as console. Only the fields needed to set up the UART are
+/// kept.
+///
+/// `MmioUart8250` drives registers in system memory, as on ARM and most RISC-V and x86 SoC
+/// consoles, and on x86_64 also legacy COM ports in the I/O port space. On other architectures
+/// [`into_uart`](Self::into_uart) returns [`SpcrError::NotMmio`] for I/O port tables.
+///
+/// ```ignore
+/// let spcr = Spcr::parse(table)?;
+/// let uart = unsafe { spcr.into_uart()? };
+/// ```
+///
+/// [REF: Microsoft Serial Port Console Redirection Table](https://learn.microsoft.com/en-us/windows-hardware/drivers/serports/serial-port-console-redirection-table)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Spcr {
+    /// Revision of the table
+    pub revision: u8,
+    pub interface_type: InterfaceType,
+    /// Address space of the registers
+    pub address_space: AddressSpace,
+    /// Address of the first register
+    pub address: u64,
+    /// Width of a register access, from the access size or, when that is undefined, the register
+    /// bit width of the generic address structure
+    pub access_width: Option<RegisterWidth>,
+    /// `None` when the UART should be used as the firmware configured it
+    pub baud_rate: Option<u32>,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// Frequency of the UART input clock, since revision 3
+    pub clock_frequency: Option<u32>,
+}
+
+impl Spcr {
+    /// Parse an SPCR table, including its ACPI header
+    pub fn parse(table: &[u8]) -> Result<Self, SpcrError> {
+        let u32_at = |offset: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&table[offset..offset + 4]);
+            u32::from_le_bytes(bytes)
+        };
+
+        if table.len() < 8 {
+            return Err(SpcrError::Truncated);
+        }
+        if &table[0..4] != b"SPCR" {
+            return Err(SpcrError::BadSignature);
+        }
+        let length = u32_at(4) as usize;
+        if length < SPCR_MIN_LENGTH || table.len() < length {
+            return Err(SpcrError::Truncated);
+        }
+        let table = &table[..length];
+        if table.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(SpcrError::BadChecksum);
+        }
+        let revision = table[8];
+
+        // Generic address structure at 40
+        let address_space = match table[40] {
+            0x00 => AddressSpace::Memory,
+            0x01 => AddressSpace::Io,
+            other => AddressSpace::Other(other),
+        };
+        let access_width = match (table[43], table[41]) {
+            (1, _) | (0, 8) => Some(RegisterWidth::U8),
+            (2, _) | (0, 16) => Some(RegisterWidth::U16),
+            (3, _) | (0, 32) => Some(RegisterWidth::U32),
+            _ => None,
+        };
+        let address = u32_at(44) as u64 | (u32_at(48) as u64) << 32;
+
+        let mut baud_rate = match table[58] {
+            0 => None,
+            3 => Some(9600),
+            4 => Some(19200),
+            6 => Some(57600),
+            7 => Some(115_200),
+            _ => return Err(SpcrError::Reserved),
+        };
+        let parity = match table[59] {
+            0 => Parity::No,
+            _ => return Err(SpcrError::Reserved),
+        };
+        let stop_bits = match table[60] {
+            1 => StopBits::One,
+            _ => return Err(SpcrError::Reserved),
+        };
+
+        let clock_frequency = match revision {
+            0..=2 => None,
+            _ => Some(u32_at(76)).filter(|clock| *clock != 0),
+        };
+        // Precise Baud Rate overrides the Configured Baud Rate when not zero
+        if revision >= 4 {
+            if length < 84 {
+                return Err(SpcrError::Truncated);
+            }
+            if u32_at(80) != 0 {
+                baud_rate = Some(u32_at(80));
+            }
+        }
+
+        Ok(Self {
+            revision,
+            interface_type: InterfaceType::from_byte(table[36]),
+            address_space,
+            address,
+            access_width,
+            baud_rate,
+            parity,
+            stop_bits,
+            clock_frequency,
+        })
+    }
+
+    /// Set up the UART described by the table
+    ///
+    /// UARTs in system memory are supported, and on x86_64 those in the I/O port space, see
+    /// [`Spcr`]. Registers are `access_width` apart. The line is only reprogrammed when both the
+    /// baud rate and the input clock are known, otherwise it is left as the firmware configured
+    /// it.
+    ///
+    /// # Safety
+    ///
+    /// The registers of the UART must be mapped at `address` as device memory and not have any
+    /// other aliases, or for I/O ports the process must be allowed to access them.
+    pub unsafe fn into_uart(self) -> Result<MmioUart8250<'static>, SpcrError> {
+        if !self.interface_type.is_8250_compatible() {
+            return Err(SpcrError::UnsupportedInterface(self.interface_type));
+        }
+        let width = self.access_width.ok_or(SpcrError::UnsupportedAccessWidth)?;
+        let reg_shift = match width {
+            RegisterWidth::U8 => 0,
+            RegisterWidth::U16 => 1,
+            RegisterWidth::U32 => 2,
+        };
+
+        let uart = match self.address_space {
+            AddressSpace::Memory => {
+                let address = usize::try_from(self.address).map_err(|_| SpcrError::BadAddress)?;
+                MmioUart8250::with_layout(address, reg_shift, width)
+            }
+            #[cfg(target_arch = "x86_64")]
+            AddressSpace::Io => {
+                let port = u16::try_from(self.address).map_err(|_| SpcrError::BadAddress)?;
+                MmioUart8250::with_port(port, reg_shift, width)
+            }
+            other => return Err(SpcrError::NotMmio(other)),
+        };
+        let baud_rate = self
+            .baud_rate
+            .and_then(|bps| BaudRate::from_bps(bps as usize));
+        if let (Some(baud_rate), Some(clock)) = (baud_rate, self.clock_frequency) {
+            uart.init_with_baud_rate(&(clock as usize), baud_rate);
+            // SPCR has no data bits, keep the 8 set by init
+            let format = FrameFormat {
+                parity: self.parity,
+                stop_bits: self.stop_bits,
+                ..uart.frame_format()
+            };
+            uart.set_frame_format(format)
+                .map_err(|_| SpcrError::Reserved)?;
+        }
+        Ok(uart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::DataBits;
+
+    const X86_COM1: &[u8] = include_bytes!("../tests/acpi/x86-com1.dat");
+    const ARM_MMIO32: &[u8] = include_bytes!("../tests/acpi/arm-mmio32.dat");
+    const GAS16550_REV4: &[u8] = include_bytes!("../tests/acpi/gas16550-rev4.dat");
+    const QEMU_VIRT_PL011: &[u8] = include_bytes!("../tests/acpi/qemu-virt-pl011.dat");
+
+    #[test]
+    fn parse() {
+        assert_eq!(Spcr::parse(&X86_COM1[..40]), Err(SpcrError::Truncated));
+        assert_eq!(Spcr::parse(b"APIC\0\0\0\0"), Err(SpcrError::BadSignature));
+        let mut corrupted = [0; 80];
+        corrupted.copy_from_slice(X86_COM1);
+        corrupted[58] = 6;
+        assert_eq!(Spcr::parse(&corrupted), Err(SpcrError::BadChecksum));
+
+        let spcr = Spcr::parse(QEMU_VIRT_PL011).unwrap();
+        assert_eq!(spcr.address, 0x0900_0000);
+        assert_eq!(spcr.baud_rate, Some(9600));
+        assert_eq!(
+            unsafe { spcr.into_uart() }.err(),
+            Some(SpcrError::UnsupportedInterface(InterfaceType::Pl011))
+        );
+    }
+
+    #[test]
+    fn x86_io_port() {
+        // COM1 in the I/O port space, only driven on x86_64
+        let spcr = Spcr::parse(X86_COM1).unwrap();
+        assert_eq!(
+            spcr,
+            Spcr {
+                revision: 2,
+                interface_type: InterfaceType::Full16550,
+                address_space: AddressSpace::Io,
+                address: 0x3f8,
+                access_width: Some(RegisterWidth::U8),
+                baud_rate: Some(115_200),
+                parity: Parity::No,
+                stop_bits: StopBits::One,
+                clock_frequency: None,
+            }
+        );
+        assert!(spcr.interface_type.is_8250_compatible());
+        // Without a clock frequency no register is accessed
+        #[cfg(target_arch = "x86_64")]
+        assert!(unsafe { spcr.into_uart() }.is_ok());
+        #[cfg(not(target_arch = "x86_64"))]
+        assert_eq!(
+            unsafe { spcr.into_uart() }.err(),
+            Some(SpcrError::NotMmio(AddressSpace::Io))
+        );
+
+        let spcr = Spcr {
+            address: 0x1_03f8,
+            ..spcr
+        };
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            unsafe { spcr.into_uart() }.err(),
+            Some(SpcrError::BadAddress)
+        );
+        let spcr = Spcr {
+            address_space: AddressSpace::Other(2),
+            ..spcr
+        };
+        assert_eq!(
+            unsafe { spcr.into_uart() }.err(),
+            Some(SpcrError::NotMmio(AddressSpace::Other(2)))
+        );
+    }
+
+    #[test]
+    fn mmio32_as_configured() {
+        let mut spcr = Spcr::parse(ARM_MMIO32).unwrap();
+        assert_eq!(spcr.address, 0x602b_0000);
+        assert_eq!(spcr.access_width, Some(RegisterWidth::U32));
+        assert_eq!(spcr.baud_rate, Some(115_200));
+        // Revision 2 has no clock frequency, so the line is left alone
+        assert_eq!(spcr.clock_frequency, None);
+
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        unsafe { base.add(3).write_volatile(0b0000_0011) };
+        spcr.address = base as u64;
+        let uart = unsafe { spcr.into_uart() }.unwrap();
+        assert_eq!(uart.frame_format().data_bits, DataBits::Eight);
+        assert_eq!(unsafe { base.add(1).read_volatile() }, 0);
+        unsafe { base.add(5).write_volatile(0b0010_0000) };
+        assert_eq!(uart.write_byte(b'a'), Ok(()));
+        assert_eq!(unsafe { base.read_volatile() }, b'a' as u32);
+    }
+
+    #[test]
+    fn gas16550_precise_baud_rate() {
+        let mut spcr = Spcr::parse(GAS16550_REV4).unwrap();
+        assert_eq!(spcr.revision, 4);
+        assert_eq!(spcr.interface_type, InterfaceType::Gas16550);
+        assert_eq!(spcr.access_width, Some(RegisterWidth::U8));
+        assert_eq!(spcr.clock_frequency, Some(48_000_000));
+        assert_eq!(spcr.baud_rate, Some(1_500_000));
+
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        spcr.address = base as u64;
+        let _uart = unsafe { spcr.into_uart() }.unwrap();
+        // 48 MHz / (16 * 1.5 Mbaud) = 2, DLAB cleared again, 8N1
+        unsafe {
+            assert_eq!(base.read_volatile(), 2);
+            assert_eq!(base.add(1).read_volatile() & !0x01, 0);
+            assert_eq!(base.add(3).read_volatile(), 0b0000_0011);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Automatic baud rate detection.
+
+use crate::uart::{MmioUart8250, LSR};
+use uart_common::ReceiveError;
+use uart_common::{BaudRate, Deadline, Monotonic};
+
+/// Common baud rates, fastest first.
+pub const STANDARD_BAUD_RATES: [BaudRate; 10] = [
+    BaudRate::B115200,
+    BaudRate::B57600,
+    BaudRate::B38400,
+    BaudRate::B19200,
+    BaudRate::B9600,
+    BaudRate::B4800,
+    BaudRate::B2400,
+    BaudRate::B1200,
+    BaudRate::B600,
+    BaudRate::B300,
+];
+
+/// How [`MmioUart8250::autobaud`] searches for the baud rate of the peer.
+///
+/// The UART is set to each rate in turn and listens for `dwell_us` microseconds. A byte received
+/// with a framing or parity error, a break, or a value other than `sync` counts as an error,
+/// any other byte as clean. A rate is accepted once it has produced `samples` clean bytes and no
+/// more than `max_errors` errors, and abandoned as soon as it produces more errors.
+///
+/// A rate too fast for the peer splits each of its characters into garbage with framing errors,
+/// a rate too slow mostly merges them into fewer bytes with wrong values. Sending a known
+/// character such as `'U'` (alternating bits) or CR, and setting `sync` to it, catches both.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AutobaudConfig<'a> {
+    /// Rates to try, in order. The search starts over after the last one.
+    pub rates: &'a [BaudRate],
+    /// The character the peer is expected to send, or `None` to accept any.
+    pub sync: Option<u8>,
+    /// Number of clean bytes needed to accept a rate.
+    pub samples: usize,
+    /// Number of errors tolerated at the accepted rate.
+    pub max_errors: usize,
+    /// How long to listen at each rate, in microseconds.
+    pub dwell_us: u64,
+}
+
+impl Default for AutobaudConfig<'_> {
+    /// Tries [`STANDARD_BAUD_RATES`] for 200 ms each, accepting 3 clean bytes of any value.
+    fn default() -> Self {
+        Self {
+            rates: &STANDARD_BAUD_RATES,
+            sync: None,
+            samples: 3,
+            max_errors: 0,
+            dwell_us: 200_000,
+        }
+    }
+}
+
+impl MmioUart8250<'_> {
+    /// Detects the baud rate of the peer by trying the rates of `config` until one receives clean
+    /// frames, and leaves the UART set to it.
+    ///
+    /// `uart_clock` is the input clock of the UART, as for [`set_baud_rate`](Self::set_baud_rate).
+    /// Returns the detected rate, or [`ReceiveError::Timeout`] if none was found within
+    /// `timeout_us` microseconds on `clock`, in which case the divisor is left at the last rate
+    /// tried. The character format (LCR) is not changed.
+    pub fn autobaud<M: Monotonic>(
+        &self,
+        uart_clock: usize,
+        config: &AutobaudConfig,
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<BaudRate, ReceiveError> {
+        if config.rates.is_empty() {
+            return Err(ReceiveError::Timeout);
+        }
+        let mut rates = config.rates.iter().cycle();
+        let start = clock.now_us();
+        loop {
+            let rate = *rates.next().unwrap();
+            self.set_baud_rate(&uart_clock, rate);
+            // Drop whatever was received at the previous rate. A peer sending continuously keeps
+            // DR set, so give up after as many bytes as the largest FIFO holds.
+            for _ in 0..64 {
+                if self.read_byte_with_status().is_none() {
+                    break;
+                }
+            }
+
+            let elapsed = clock.now_us().wrapping_sub(start);
+            if elapsed >= timeout_us {
+                return Err(ReceiveError::Timeout);
+            }
+            let deadline = Deadline::new(clock, config.dwell_us.min(timeout_us - elapsed));
+            let mut clean = 0;
+            let mut errors = 0;
+            while errors <= config.max_errors && !deadline.is_expired(clock) {
+                let (byte, lsr) = match self.read_byte_with_status() {
+                    Some(received) => received,
+                    None => continue,
+                };
+                if lsr.intersects(LSR::FE | LSR::PE | LSR::BI)
+                    || config.sync.is_some_and(|sync| sync != byte)
+                {
+                    errors += 1;
+                } else {
+                    clean += 1;
+                    if clean >= config.samples {
+                        return Ok(rate);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A peer sending `sync` continuously at `peer_bps`, delivered one byte per microsecond
+    ///
+    /// On every tick the byte received at the rate the UART is set to is put in RBR and LSR: the
+    /// byte itself at the right rate, garbage with a framing error at a faster one, and a wrong
+    /// byte without an error at a slower one. DR is never cleared, which the dropping of stale
+    /// bytes after a rate change has to cope with.
+    struct FakeLine<'u, 'a> {
+        uart: &'u MmioUart8250<'a>,
+        registers: *mut u8,
+        peer_bps: usize,
+        sync: u8,
+        now_us: u64,
+    }
+
+    impl Monotonic for FakeLine<'_, '_> {
+        fn now_us(&mut self) -> u64 {
+            let bps = self.uart.baud_rate().map_or(0, BaudRate::bps);
+            let (byte, lsr) = if bps == self.peer_bps {
+                (self.sync, LSR::DR)
+            } else if bps > self.peer_bps {
+                (0xff, LSR::DR | LSR::FE)
+            } else {
+                (0xf0, LSR::DR)
+            };
+            unsafe {
+                self.registers.write_volatile(byte);
+                self.registers.add(5).write_volatile(lsr.bits());
+            }
+            self.now_us += 1;
+            self.now_us
+        }
+    }
+
+    #[test]
+    fn autobaud() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let registers = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(registers as usize) };
+        let mut line = FakeLine {
+            uart: &uart,
+            registers,
+            peer_bps: 9600,
+            sync: b'U',
+            now_us: 0,
+        };
+        let mut config = AutobaudConfig {
+            rates: &[BaudRate::B115200, BaudRate::B2400, BaudRate::B9600],
+            sync: Some(b'U'),
+            samples: 3,
+            max_errors: 0,
+            dwell_us: 100,
+        };
+
+        // Framing errors at 115200, wrong bytes at 2400
+        assert_eq!(
+            uart.autobaud(1_843_200, &config, &mut line, 1000),
+            Ok(BaudRate::B9600)
+        );
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B9600));
+
+        // Without a sync character the wrong bytes at a slower rate look clean
+        config.sync = None;
+        assert_eq!(
+            uart.autobaud(1_843_200, &config, &mut line, 1000),
+            Ok(BaudRate::B2400)
+        );
+
+        // A peer at a rate not in the list
+        config.sync = Some(b'U');
+        line.peer_bps = 300;
+        assert_eq!(
+            uart.autobaud(1_843_200, &config, &mut line, 1000),
+            Err(ReceiveError::Timeout)
+        );
+    }
+}
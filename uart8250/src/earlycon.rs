@@ -0,0 +1,114 @@
+use crate::registers::{RegisterWidth, Registers};
+use crate::uart::MmioUart8250;
+#[cfg(feature = "fmt")]
+use core::fmt;
+
+/// LSR\[5\]: Transmitter Holding Register Empty
+const LSR_THRE: u8 = 0b0010_0000;
+/// LSR\[6\]: Data Holding Registers Empty
+const LSR_TEMT: u8 = 0b0100_0000;
+
+/// # Early console on an 8250 UART
+///
+/// A write-only console for the time before the kernel is able to set up the full driver, e.g.
+/// before paging is enabled. It can be built in a `const` context and put in a `static`, and it
+/// never initialises the UART: the firmware is expected to have configured the line already.
+///
+/// Once the kernel is ready, [`upgrade`](Self::upgrade) hands the same registers over to a
+/// [`MmioUart8250`].
+///
+/// ```ignore
+/// static EARLYCON: EarlyCon = unsafe { EarlyCon::new(0x1000_0000, 0, RegisterWidth::U8) };
+///
+/// EARLYCON.write_bytes(b"booting\n");
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct EarlyCon {
+    reg: Registers,
+}
+
+impl EarlyCon {
+    /// Creates an early console on the UART at `base_address`, whose registers are
+    /// `1 << reg_shift` bytes apart and must be accessed `width` at a time.
+    ///
+    /// # Safety
+    ///
+    /// The given base address must point to the 8 MMIO control registers of an appropriate UART
+    /// device laid out as described, and must be accessible whenever the console is written to.
+    pub const unsafe fn new(base_address: usize, reg_shift: u8, width: RegisterWidth) -> Self {
+        Self {
+            reg: Registers::new(base_address, reg_shift, width),
+        }
+    }
+
+    /// Writes a byte, spinning until the transmitter holding register is empty.
+    pub fn write_byte(&self, byte: u8) {
+        while self.reg.lsr.read() & LSR_THRE == 0 {}
+        unsafe { self.reg.thr_rbr_dll.write(byte) }
+    }
+
+    /// Writes all of `bytes`, spinning whenever the transmitter is busy.
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+    }
+
+    /// Waits until the UART has finished transmitting everything it has been given.
+    pub fn flush(&self) {
+        while self.reg.lsr.read() & LSR_TEMT == 0 {}
+    }
+
+    /// Hands the UART over to the full driver.
+    ///
+    /// Waits for everything written so far to leave the UART first, so that reconfiguring it
+    /// through the returned driver can't garble the early output. The line settings are left
+    /// untouched, so the driver can be used right away without calling `init`.
+    ///
+    /// # Safety
+    ///
+    /// The registers must be mapped at the same address as when the console was created, and
+    /// the console must not be written to any more while the driver is in use.
+    pub unsafe fn upgrade(&self) -> MmioUart8250<'static> {
+        self.flush();
+        MmioUart8250::from_registers(self.reg)
+    }
+}
+
+/// ## fmt::Write
+///
+/// A simple implementation, may be changed in the future
+#[cfg(feature = "fmt")]
+impl fmt::Write for EarlyCon {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::{DataBits, FrameFormat, Parity, StopBits};
+
+    #[test]
+    fn write_and_upgrade() {
+        // Registers 4 bytes apart with 32-bit accesses, transmitter idle.
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        unsafe { base.add(5).write_volatile(0b0110_0000) };
+
+        let earlycon = unsafe { EarlyCon::new(base as usize, 2, RegisterWidth::U32) };
+        earlycon.write_bytes(b"ok");
+        assert_eq!(unsafe { base.read_volatile() }, b'k' as u32);
+
+        let uart = unsafe { earlycon.upgrade() };
+        assert_eq!(uart.write_byte(b'!'), Ok(()));
+        assert_eq!(unsafe { base.read_volatile() }, b'!' as u32);
+        unsafe { base.add(3).write_volatile(0b0000_0111) };
+        assert_eq!(
+            uart.frame_format(),
+            FrameFormat::new(DataBits::Eight, Parity::No, StopBits::Two)
+        );
+    }
+}
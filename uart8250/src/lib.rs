@@ -8,7 +8,28 @@ This crate provides a struct with many methods to operate an 8250 UART.
 
 #![no_std]
 
+#[cfg(feature = "acpi")]
+mod acpi;
+mod autobaud;
+mod earlycon;
 mod registers;
 mod uart;
 
-pub use uart::{ChipFifoInfo, InterruptType, MmioUart8250, Parity, TransmitError};
+#[cfg(feature = "acpi")]
+pub use acpi::{AddressSpace, InterfaceType, Spcr, SpcrError};
+pub use autobaud::{AutobaudConfig, STANDARD_BAUD_RATES};
+pub use earlycon::EarlyCon;
+pub use registers::RegisterWidth;
+pub use uart::{
+    ChipFifoInfo, DataBits, DumpFlags, FifoTrigger, FrameFormat, Iir, InterruptMaskGuard,
+    InterruptType, InvalidFrameFormat, MmioUart8250, Parity, RegisterDump, StopBits, UartState,
+    IER, LSR, MCR, MSR,
+};
+#[cfg(feature = "embedded")]
+pub use uart_common::DelayMonotonic;
+#[cfg(feature = "fmt")]
+pub use uart_common::OutputFlags;
+pub use uart_common::{BaudRate, ClockSource, Monotonic, ReceiveError, TransmitError};
+
+#[cfg(feature = "stats")]
+pub use uart_common::UartStats;
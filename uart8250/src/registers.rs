@@ -1,6 +1,131 @@
-use core::u8;
+#[cfg(target_arch = "x86_64")]
+use core::arch::asm;
+use core::ptr;
 
-use volatile_register::{RO, RW};
+/// Width of the accesses used to read and write the UART registers.
+///
+/// Some SoCs only allow 32-bit accesses to their 8250-compatible UART, which is described by
+/// `reg-io-width` in a device tree or the access size of the ACPI generic address structure.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RegisterWidth {
+    U8,
+    U16,
+    U32,
+}
+
+/// Where the registers of a UART live.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Space {
+    /// System memory, accessed with volatile loads and stores.
+    Memory,
+    /// The x86 I/O port space, accessed with `in` and `out`.
+    #[cfg(target_arch = "x86_64")]
+    Port,
+}
+
+/// A single UART register, accessed with volatile reads and writes of the configured width.
+///
+/// Only the low 8 bits are meaningful, the upper bits are ignored on read and written as 0.
+#[derive(Copy, Clone, Debug)]
+pub struct Register {
+    address: usize,
+    width: RegisterWidth,
+    space: Space,
+}
+
+impl Register {
+    const fn new(address: usize, width: RegisterWidth, space: Space) -> Self {
+        Self {
+            address,
+            width,
+            space,
+        }
+    }
+
+    /// Reads the register.
+    #[inline]
+    pub fn read(&self) -> u8 {
+        #[cfg(target_arch = "x86_64")]
+        if self.space == Space::Port {
+            return unsafe { read_port(self.address as u16, self.width) };
+        }
+        unsafe {
+            match self.width {
+                RegisterWidth::U8 => ptr::read_volatile(self.address as *const u8),
+                RegisterWidth::U16 => ptr::read_volatile(self.address as *const u16) as u8,
+                RegisterWidth::U32 => ptr::read_volatile(self.address as *const u32) as u8,
+            }
+        }
+    }
+
+    /// Writes the register.
+    ///
+    /// # Safety
+    ///
+    /// Writing a UART register has side effects on the device.
+    #[inline]
+    pub unsafe fn write(&self, value: u8) {
+        #[cfg(target_arch = "x86_64")]
+        if self.space == Space::Port {
+            return write_port(self.address as u16, self.width, value);
+        }
+        match self.width {
+            RegisterWidth::U8 => ptr::write_volatile(self.address as *mut u8, value),
+            RegisterWidth::U16 => ptr::write_volatile(self.address as *mut u16, value as u16),
+            RegisterWidth::U32 => ptr::write_volatile(self.address as *mut u32, value as u32),
+        }
+    }
+
+    /// Reads the register, passes the value to `f` and writes back the result.
+    ///
+    /// # Safety
+    ///
+    /// Writing a UART register has side effects on the device.
+    #[inline]
+    pub unsafe fn modify(&self, f: impl FnOnce(u8) -> u8) {
+        self.write(f(self.read()))
+    }
+}
+
+/// Reads an I/O port `width` at a time.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn read_port(port: u16, width: RegisterWidth) -> u8 {
+    match width {
+        RegisterWidth::U8 => {
+            let value: u8;
+            asm!("in al, dx", out("al") value, in("dx") port, options(nostack, preserves_flags));
+            value
+        }
+        RegisterWidth::U16 => {
+            let value: u16;
+            asm!("in ax, dx", out("ax") value, in("dx") port, options(nostack, preserves_flags));
+            value as u8
+        }
+        RegisterWidth::U32 => {
+            let value: u32;
+            asm!("in eax, dx", out("eax") value, in("dx") port, options(nostack, preserves_flags));
+            value as u8
+        }
+    }
+}
+
+/// Writes an I/O port `width` at a time.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn write_port(port: u16, width: RegisterWidth, value: u8) {
+    match width {
+        RegisterWidth::U8 => {
+            asm!("out dx, al", in("dx") port, in("al") value, options(nostack, preserves_flags));
+        }
+        RegisterWidth::U16 => {
+            asm!("out dx, ax", in("dx") port, in("ax") value as u16, options(nostack, preserves_flags));
+        }
+        RegisterWidth::U32 => {
+            asm!("out dx, eax", in("dx") port, in("eax") value as u32, options(nostack, preserves_flags));
+        }
+    }
+}
 
 /// # UART Registers
 ///
@@ -22,21 +147,71 @@ use volatile_register::{RO, RW};
 /// | +5           | x    | Read       | LSR    | Line Status Register              |
 /// | +6           | x    | Read       | MSR    | Modem Status Register             |
 /// | +7           | x    | Read/Write | SR     | Scratch Register                  |
-#[repr(C, packed)]
+///
+/// The offsets above are register indices: register `n` lives at `base + (n << reg_shift)`.
+#[derive(Copy, Clone, Debug)]
 pub struct Registers {
-    pub thr_rbr_dll: RW<u8>,
-    pub ier_dlh: RW<u8>,
-    pub iir_fcr: RW<u8>,
-    pub lcr: RW<u8>,
-    pub mcr: RW<u8>,
-    pub lsr: RO<u8>,
-    pub msr: RO<u8>,
-    pub scratch: RW<u8>,
+    pub thr_rbr_dll: Register,
+    pub ier_dlh: Register,
+    pub iir_fcr: Register,
+    pub lcr: Register,
+    pub mcr: Register,
+    pub lsr: Register,
+    pub msr: Register,
+    pub scratch: Register,
+    reg_shift: u8,
 }
 
 impl Registers {
-    /// Constructs a new instance of the UART registers starting at the given base address.
-    pub unsafe fn from_base_address(base_address: usize) -> &'static mut Self {
-        &mut *(base_address as *mut crate::registers::Registers)
+    /// Constructs the UART registers starting at the given base address, `1 << reg_shift` bytes
+    /// apart and accessed `width` at a time.
+    pub const fn new(base_address: usize, reg_shift: u8, width: RegisterWidth) -> Self {
+        Self::in_space(base_address, reg_shift, width, Space::Memory)
+    }
+
+    /// Constructs the UART registers starting at the given I/O port, `1 << reg_shift` ports apart
+    /// and accessed `width` at a time.
+    #[cfg(target_arch = "x86_64")]
+    pub const fn new_port(base_port: u16, reg_shift: u8, width: RegisterWidth) -> Self {
+        Self::in_space(base_port as usize, reg_shift, width, Space::Port)
+    }
+
+    const fn in_space(
+        base_address: usize,
+        reg_shift: u8,
+        width: RegisterWidth,
+        space: Space,
+    ) -> Self {
+        Self {
+            thr_rbr_dll: Register::new(base_address, width, space),
+            ier_dlh: Register::new(base_address + (1 << reg_shift), width, space),
+            iir_fcr: Register::new(base_address + (2 << reg_shift), width, space),
+            lcr: Register::new(base_address + (3 << reg_shift), width, space),
+            mcr: Register::new(base_address + (4 << reg_shift), width, space),
+            lsr: Register::new(base_address + (5 << reg_shift), width, space),
+            msr: Register::new(base_address + (6 << reg_shift), width, space),
+            scratch: Register::new(base_address + (7 << reg_shift), width, space),
+            reg_shift,
+        }
+    }
+
+    /// Constructs the same registers starting at another address in the same space.
+    pub const fn rebase(&self, base_address: usize) -> Self {
+        Self::in_space(
+            base_address,
+            self.reg_shift(),
+            self.width(),
+            self.thr_rbr_dll.space,
+        )
+    }
+
+    /// Gets the distance between two registers, as a power of two.
+    pub const fn reg_shift(&self) -> u8 {
+        self.reg_shift
+    }
+
+    /// Gets the width of the accesses to the registers.
+    pub const fn width(&self) -> RegisterWidth {
+        self.thr_rbr_dll.width
     }
 }
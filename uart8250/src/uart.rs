@@ -1,9 +1,17 @@
 use bitflags::bitflags;
+use core::cell::Cell;
 #[cfg(feature = "embedded")]
 use core::convert::Infallible;
 use core::fmt::{self, Display, Formatter};
+use core::marker::PhantomData;
 
-use crate::registers::Registers;
+use crate::registers::{RegisterWidth, Registers};
+use uart_common::{BaudRate, ClockSource};
+use uart_common::{Deadline, Monotonic, ReceiveError, TransmitError};
+#[cfg(feature = "fmt")]
+use uart_common::{Output, OutputFlags};
+#[cfg(feature = "stats")]
+use uart_common::{StatsCounter, UartStats};
 
 bitflags! {
     /// Interrupt Enable Register (bitflags)
@@ -67,6 +75,40 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Modem Control Register (bitflags)
+    pub struct MCR: u8 {
+        /// Data Terminal Ready
+        const DTR = 0b0000_0001;
+        /// Request To Send
+        const RTS = 0b0000_0010;
+        /// Auxiliary Output 1
+        const OUT1 = 0b0000_0100;
+        /// Auxiliary Output 2
+        const OUT2 = 0b0000_1000;
+        /// Loopback Mode
+        const LOOP = 0b0001_0000;
+        /// Autoflow Control Enabled (16750)
+        const AFE = 0b0010_0000;
+    }
+}
+
+bitflags! {
+    /// Registers [`MmioUart8250::dump`] reads even though reading them has side effects.
+    pub struct DumpFlags: u8 {
+        /// Set DLAB briefly to read the divisor latch.
+        const DIVISOR = 0b0000_0001;
+        /// Read IIR, which acknowledges a pending THRE interrupt.
+        const IIR = 0b0000_0010;
+        /// Read LSR, which clears the error bits.
+        const LSR = 0b0000_0100;
+        /// Read MSR, which clears the delta bits.
+        const MSR = 0b0000_1000;
+        /// Read RBR, which consumes a received byte.
+        const RBR = 0b0001_0000;
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ChipFifoInfo {
     NoFifo,
@@ -75,6 +117,45 @@ pub enum ChipFifoInfo {
     Enabled,
 }
 
+impl ChipFifoInfo {
+    /// Decodes IIR\[7:6\]
+    fn from_iir(iir: u8) -> Self {
+        match iir & 0b1100_0000 {
+            0 => ChipFifoInfo::NoFifo,
+            0b0100_0000 => ChipFifoInfo::Reserved,
+            0b1000_0000 => ChipFifoInfo::EnabledNoFunction,
+            0b1100_0000 => ChipFifoInfo::Enabled,
+            _ => panic!("Can't reached"),
+        }
+    }
+}
+
+/// Receive FIFO interrupt trigger level, FCR\[7:6\]
+///
+/// Named after the levels of a 16 byte FIFO, a 64 byte FIFO (16750) triggers at 1, 16, 32 and 56.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FifoTrigger {
+    Bytes1 = 0b00,
+    Bytes4 = 0b01,
+    Bytes8 = 0b10,
+    Bytes14 = 0b11,
+}
+
+impl FifoTrigger {
+    /// Number of bytes in a FIFO of `depth` bytes that raise the interrupt
+    pub fn level(self, depth: usize) -> usize {
+        match (self, depth) {
+            (FifoTrigger::Bytes1, _) | (_, 0..=1) => 1,
+            (FifoTrigger::Bytes4, 64) => 16,
+            (FifoTrigger::Bytes8, 64) => 32,
+            (FifoTrigger::Bytes14, 64) => 56,
+            (FifoTrigger::Bytes4, _) => 4,
+            (FifoTrigger::Bytes8, _) => 8,
+            (FifoTrigger::Bytes14, _) => 14,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum InterruptType {
     ModemStatus,
@@ -85,6 +166,26 @@ pub enum InterruptType {
     Reserved,
 }
 
+impl InterruptType {
+    /// Decodes IIR\[3:0\], `None` if no interrupt is pending
+    fn from_iir(iir: u8) -> Option<Self> {
+        let iir = iir & 0b0000_1111;
+        if iir & 1 != 0 {
+            None
+        } else {
+            match iir {
+                0b0000 => Some(InterruptType::ModemStatus),
+                0b0010 => Some(InterruptType::TransmitterHoldingRegisterEmpty),
+                0b0100 => Some(InterruptType::ReceivedDataAvailable),
+                0b0110 => Some(InterruptType::ReceiverLineStatus),
+                0b1100 => Some(InterruptType::Timeout),
+                0b1000 | 0b1010 | 0b1110 => Some(InterruptType::Reserved),
+                _ => panic!("Can't reached"),
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Parity {
     No,
@@ -94,26 +195,227 @@ pub enum Parity {
     Space,
 }
 
-/// An error encountered which trying to transmit data.
+impl Parity {
+    /// Decodes LCR\[5:3\], the other bits are ignored when parity is disabled
+    fn from_lcr(lcr: u8) -> Self {
+        if lcr & 0b0000_1000 == 0 {
+            return Parity::No;
+        }
+        match lcr & 0b0011_0000 {
+            0b0000_0000 => Parity::Odd,
+            0b0001_0000 => Parity::Even,
+            0b0010_0000 => Parity::Mark,
+            _ => Parity::Space,
+        }
+    }
+
+    /// Encodes into LCR\[5:3\].
+    fn to_lcr(self) -> u8 {
+        match self {
+            Parity::No => 0b0000_0000,
+            Parity::Odd => 0b0000_1000,
+            Parity::Even => 0b0001_1000,
+            Parity::Mark => 0b0010_1000,
+            Parity::Space => 0b0011_1000,
+        }
+    }
+}
+
+/// Number of data bits per character (LCR\[1:0\]).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+impl DataBits {
+    /// Returns the number of bits.
+    pub fn bits(self) -> u8 {
+        self.to_lcr() + 5
+    }
+
+    /// Returns the variant for `bits` data bits, or `None` if it isn't 5 to 8.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            5 => Some(DataBits::Five),
+            6 => Some(DataBits::Six),
+            7 => Some(DataBits::Seven),
+            8 => Some(DataBits::Eight),
+            _ => None,
+        }
+    }
+
+    fn to_lcr(self) -> u8 {
+        match self {
+            DataBits::Five => 0b00,
+            DataBits::Six => 0b01,
+            DataBits::Seven => 0b10,
+            DataBits::Eight => 0b11,
+        }
+    }
+
+    fn from_lcr(lcr: u8) -> Self {
+        match lcr & 0b11 {
+            0b00 => DataBits::Five,
+            0b01 => DataBits::Six,
+            0b10 => DataBits::Seven,
+            _ => DataBits::Eight,
+        }
+    }
+}
+
+/// Number of stop bits (LCR\[2\]).
+///
+/// LCR\[2\] selects 1.5 stop bits with 5 data bits and 2 stop bits otherwise.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopBits {
+    One,
+    OnePointFive,
+    Two,
+}
+
+/// Character format: data bits, parity and stop bits (LCR\[5:0\]).
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum TransmitError {
-    /// The transmit buffer is full, try again later.
-    BufferFull,
+pub struct FrameFormat {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
 }
 
-impl Display for TransmitError {
+impl FrameFormat {
+    /// Creates a frame format.
+    pub const fn new(data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Self {
+        Self {
+            data_bits,
+            parity,
+            stop_bits,
+        }
+    }
+
+    /// Encodes into LCR\[5:0\], or returns `None` if the stop bits can't be used with the data
+    /// bits.
+    fn to_lcr(self) -> Option<u8> {
+        let stop = match (self.stop_bits, self.data_bits) {
+            (StopBits::One, _) => 0,
+            (StopBits::OnePointFive, DataBits::Five) => 0b100,
+            (StopBits::Two, DataBits::Five) | (StopBits::OnePointFive, _) => return None,
+            (StopBits::Two, _) => 0b100,
+        };
+        Some(self.data_bits.to_lcr() | stop | self.parity.to_lcr())
+    }
+
+    /// Decodes LCR\[5:0\].
+    fn from_lcr(lcr: u8) -> Self {
+        let data_bits = DataBits::from_lcr(lcr);
+        let stop_bits = match (lcr & 0b100 != 0, data_bits) {
+            (false, _) => StopBits::One,
+            (true, DataBits::Five) => StopBits::OnePointFive,
+            (true, _) => StopBits::Two,
+        };
+        Self::new(data_bits, Parity::from_lcr(lcr), stop_bits)
+    }
+}
+
+impl Default for FrameFormat {
+    /// 8 data bits, no parity, 1 stop bit.
+    fn default() -> Self {
+        Self::new(DataBits::Eight, Parity::No, StopBits::One)
+    }
+}
+
+/// The stop bits of a [`FrameFormat`] can't be used with its data bits: 1.5 stop bits need 5
+/// data bits, 2 stop bits need 6 or more.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct InvalidFrameFormat;
+
+impl Display for InvalidFrameFormat {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        f.write_str(match self {
-            Self::BufferFull => "UART buffer full",
-        })
+        f.write_str("stop bits not supported with this number of data bits")
     }
 }
 
+/// A snapshot of every programmable register of the UART.
+///
+/// Produced by [`MmioUart8250::save`] and written back by [`MmioUart8250::restore`], e.g. around
+/// suspend-to-RAM or when taking the UART over from firmware.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UartState {
+    /// Divisor latch (DLH:DLL)
+    pub divisor: u16,
+    /// Interrupt Enable Register
+    pub ier: u8,
+    /// Line Control Register, DLAB included
+    pub lcr: u8,
+    /// Modem Control Register
+    pub mcr: u8,
+    /// FIFO Control Register, as last written through this driver
+    pub fcr: u8,
+    /// Scratch Register
+    pub scratch: u8,
+}
+
+/// Interrupt Identification Register decoded into named fields.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Iir {
+    /// Pending interrupt with the highest priority (IIR\[3:0\])
+    pub interrupt: Option<InterruptType>,
+    /// FIFO state (IIR\[7:6\])
+    pub fifo: ChipFifoInfo,
+    /// Whether the 64 byte FIFO is enabled (IIR\[5\])
+    pub fifo_64byte: bool,
+}
+
+/// Every register of the UART decoded into named fields, as returned by
+/// [`MmioUart8250::dump`].
+///
+/// Registers whose read has side effects are `None` unless asked for with [`DumpFlags`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RegisterDump {
+    /// Divisor latch (DLH:DLL), read with [`DumpFlags::DIVISOR`]
+    pub divisor: Option<u16>,
+    /// Data bits, parity and stop bits (LCR\[5:0\])
+    pub frame_format: FrameFormat,
+    /// Break Control (LCR\[6\])
+    pub break_enabled: bool,
+    /// Divisor Latch Access Bit (LCR\[7\])
+    pub divisor_latch_accessible: bool,
+    /// Enabled interrupts, `None` if DLAB is set and [`DumpFlags::DIVISOR`] not given
+    pub ier: Option<IER>,
+    /// Interrupt identification, read with [`DumpFlags::IIR`]
+    pub iir: Option<Iir>,
+    /// FIFO Control Register, as last written through this driver
+    pub fcr: u8,
+    /// Modem control lines and loopback
+    pub mcr: MCR,
+    /// Line status, read with [`DumpFlags::LSR`]
+    pub lsr: Option<LSR>,
+    /// Modem status, read with [`DumpFlags::MSR`]
+    pub msr: Option<MSR>,
+    /// Scratch Register
+    pub scratch: u8,
+    /// Receiver Buffer, read with [`DumpFlags::RBR`]
+    pub rbr: Option<u8>,
+}
+
 /// # MMIO version of an 8250 UART.
 ///
 /// **Note** This is only tested on the NS16550 compatible UART used in QEMU 5.0 virt machine of RISC-V.
 pub struct MmioUart8250<'a> {
-    reg: &'a mut Registers,
+    reg: Registers,
+    _registers: PhantomData<&'a mut ()>,
+    /// FCR is write-only, so keep a copy of the last value written
+    fcr: Cell<u8>,
+    /// Number of bytes THR accepts once THRE is seen with the FIFOs enabled, detected by
+    /// `detect_fifo_depth`
+    fifo_depth: Cell<u8>,
+    /// Baud rate the divisor was last computed for
+    baud_rate: Cell<Option<BaudRate>>,
+    #[cfg(feature = "fmt")]
+    output: Output,
+    #[cfg(feature = "stats")]
+    stats: StatsCounter,
 }
 
 impl<'a> MmioUart8250<'a> {
@@ -125,8 +427,44 @@ impl<'a> MmioUart8250<'a> {
     /// device, which must be mapped into the address space of the process as device memory and not
     /// have any other aliases.
     pub unsafe fn new(base_address: usize) -> Self {
+        Self::with_layout(base_address, 0, RegisterWidth::U8)
+    }
+
+    /// Creates a new UART whose registers are `1 << reg_shift` bytes apart and must be accessed
+    /// `width` at a time, e.g. `reg-shift = <2>` and `reg-io-width = <4>` in a device tree.
+    ///
+    /// # Safety
+    ///
+    /// The given base address must point to the 8 MMIO control registers of an appropriate UART
+    /// device laid out as described, which must be mapped into the address space of the process
+    /// as device memory and not have any other aliases.
+    pub unsafe fn with_layout(base_address: usize, reg_shift: u8, width: RegisterWidth) -> Self {
+        Self::from_registers(Registers::new(base_address, reg_shift, width))
+    }
+
+    /// Creates a new UART in the x86 I/O port space, such as COM1 at port 0x3f8, whose registers
+    /// are `1 << reg_shift` ports apart and must be accessed `width` at a time.
+    ///
+    /// # Safety
+    ///
+    /// The given base port must be the first of the 8 control registers of an appropriate UART
+    /// device laid out as described, and the process must be allowed to access those ports.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn with_port(base_port: u16, reg_shift: u8, width: RegisterWidth) -> Self {
+        Self::from_registers(Registers::new_port(base_port, reg_shift, width))
+    }
+
+    pub(crate) fn from_registers(reg: Registers) -> Self {
         Self {
-            reg: Registers::from_base_address(base_address),
+            reg,
+            _registers: PhantomData,
+            fcr: Cell::new(0),
+            fifo_depth: Cell::new(1),
+            baud_rate: Cell::new(None),
+            #[cfg(feature = "fmt")]
+            output: Output::new(),
+            #[cfg(feature = "stats")]
+            stats: StatsCounter::new(),
         }
     }
 
@@ -136,11 +474,23 @@ impl<'a> MmioUart8250<'a> {
     pub fn init(&self, clock: usize, baud_rate: usize) {
         // Enable DLAB and set divisor
         self.set_divisor(clock, baud_rate);
+        self.init_line();
+    }
+
+    /// Initialises the UART like [`init`](Self::init), for `baud_rate` at the current frequency
+    /// of `clock`.
+    pub fn init_with_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C, baud_rate: BaudRate) {
+        self.set_baud_rate(clock, baud_rate);
+        self.init_line();
+    }
 
+    /// Everything `init` does after setting the divisor.
+    fn init_line(&self) {
         // Disable DLAB and set word length 8 bits, no parity, 1 stop bit
         self.write_lcr(3);
         // Enable FIFO
         self.write_fcr(1);
+        self.detect_fifo_depth();
         // No modem control
         self.write_mcr(0);
         // Enable received_data_available_interrupt
@@ -155,9 +505,10 @@ impl<'a> MmioUart8250<'a> {
     ///
     /// The given base address must point to the 8 MMIO control registers of an appropriate UART
     /// device, which must be mapped into the address space of the process as device memory and not
-    /// have any other aliases.
+    /// have any other aliases. For a UART created by `with_port` it is the first of the 8 I/O
+    /// ports instead.
     pub unsafe fn set_base_address(&mut self, base_address: usize) {
-        self.reg = Registers::from_base_address(base_address);
+        self.reg = self.reg.rebase(base_address);
     }
 
     /// Reads a byte from the UART.
@@ -165,15 +516,34 @@ impl<'a> MmioUart8250<'a> {
     /// Returns `None` when data is not ready (RBR\[0\] != 1)
     pub fn read_byte(&self) -> Option<u8> {
         if self.is_data_ready() {
+            #[cfg(feature = "stats")]
+            self.stats.count_rx(1);
             Some(self.read_rbr())
         } else {
             None
         }
     }
 
+    /// Reads a byte from the UART along with the LSR it was received with.
+    ///
+    /// Reading LSR clears its error bits, so this is the only way to tell which byte a parity or
+    /// framing error, or a break, belongs to. Returns `None` when data is not ready.
+    pub fn read_byte_with_status(&self) -> Option<(u8, LSR)> {
+        let lsr = self.lsr();
+        if lsr.contains(LSR::DR) {
+            #[cfg(feature = "stats")]
+            self.stats.count_rx(1);
+            Some((self.read_rbr(), lsr))
+        } else {
+            None
+        }
+    }
+
     /// Writes a byte to the UART.
     pub fn write_byte(&self, byte: u8) -> Result<(), TransmitError> {
         if self.is_transmitter_holding_register_empty() {
+            #[cfg(feature = "stats")]
+            self.stats.count_tx(1);
             self.write_thr(byte);
             Ok(())
         } else {
@@ -181,6 +551,302 @@ impl<'a> MmioUart8250<'a> {
         }
     }
 
+    /// Reads as many bytes as are available into `buf`, returning how many were read.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for current in buf {
+            if let Some(ch) = self.read_byte() {
+                count += 1;
+                *current = ch;
+            } else {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Writes a burst of bytes from `buf`, returning how many were written.
+    ///
+    /// If THR is empty, up to [`fifo_depth`] bytes are written at once, otherwise nothing is
+    /// written and 0 is returned.
+    ///
+    /// [`fifo_depth`]: Self::fifo_depth
+    pub fn write(&self, buf: &[u8]) -> usize {
+        if !self.is_transmitter_holding_register_empty() {
+            return 0;
+        }
+        let count = buf.len().min(self.fifo_depth());
+        for byte in &buf[..count] {
+            self.write_thr(*byte);
+        }
+        #[cfg(feature = "stats")]
+        self.stats.count_tx(count as u32);
+        count
+    }
+
+    /// Reads bytes until `buf` is full, spinning while no data is available.
+    pub fn read_exact(&self, buf: &mut [u8]) {
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.read(&mut buf[filled..]);
+        }
+    }
+
+    /// Writes all of `buf`, spinning while the transmitter is busy.
+    pub fn write_all(&self, buf: &[u8]) {
+        let mut written = 0;
+        while written < buf.len() {
+            written += self.write(&buf[written..]);
+        }
+    }
+
+    /// Sets the translation the `fmt::Write` impl applies to its output, none by default.
+    #[cfg(feature = "fmt")]
+    pub fn set_output_flags(&self, flags: OutputFlags) {
+        self.output.set_flags(flags)
+    }
+
+    /// Returns the translation the `fmt::Write` impl applies to its output.
+    #[cfg(feature = "fmt")]
+    pub fn output_flags(&self) -> OutputFlags {
+        self.output.flags()
+    }
+
+    /// Waits until the UART has finished transmitting everything it has been given (LSR\[6\]).
+    pub fn flush(&self) {
+        while !self.is_data_holding_registers_empty() {}
+    }
+
+    /// Reads bytes until `buf` is full or `timeout_us` microseconds have passed on `clock`.
+    ///
+    /// Returns how many bytes were read, or [`ReceiveError::Timeout`] if none arrived in time.
+    pub fn read_timeout<M: Monotonic>(
+        &self,
+        buf: &mut [u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<usize, ReceiveError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.read(&mut buf[filled..]);
+            if filled < buf.len() && deadline.is_expired(clock) {
+                break;
+            }
+        }
+        if filled == 0 && !buf.is_empty() {
+            Err(ReceiveError::Timeout)
+        } else {
+            Ok(filled)
+        }
+    }
+
+    /// Writes all of `buf`, giving up with [`TransmitError::Timeout`] if `timeout_us`
+    /// microseconds pass on `clock` before everything has been handed to the UART.
+    pub fn write_timeout<M: Monotonic>(
+        &self,
+        buf: &[u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        let mut written = 0;
+        while written < buf.len() {
+            written += self.write(&buf[written..]);
+            if written < buf.len() && deadline.is_expired(clock) {
+                return Err(TransmitError::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until the UART has finished transmitting, giving up with
+    /// [`TransmitError::Timeout`] after `timeout_us` microseconds on `clock`.
+    pub fn flush_timeout<M: Monotonic>(
+        &self,
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        while !self.is_data_holding_registers_empty() {
+            if deadline.is_expired(clock) {
+                return Err(TransmitError::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the number of bytes THR can take each time it is seen empty.
+    ///
+    /// This is 1 while the FIFOs are disabled, and otherwise the depth found by the last call to
+    /// [`detect_fifo_depth`](Self::detect_fifo_depth).
+    pub fn fifo_depth(&self) -> usize {
+        if self.fcr.get() & 1 == 0 {
+            1
+        } else {
+            self.fifo_depth.get() as usize
+        }
+    }
+
+    /// Detects the depth of the FIFOs from IIR and returns it.
+    ///
+    /// The depth is 64 bytes for a 16750 with the large FIFO enabled, 16 bytes for a working
+    /// 16550 FIFO and 1 byte otherwise. [`init`](Self::init) calls this once. Call it again after
+    /// enabling the FIFOs some other way, e.g. through [`restore`](Self::restore).
+    ///
+    /// Reading IIR acknowledges a pending THRE interrupt, so this should not be called while
+    /// transmission is interrupt driven.
+    pub fn detect_fifo_depth(&self) -> usize {
+        if self.fcr.get() & 1 != 0 {
+            // IIR[7:6] report whether the FIFOs really work, IIR[5] whether they are 64 bytes
+            let depth = match self.reg.iir_fcr.read() & 0b1110_0000 {
+                0b1110_0000 => 64,
+                0b1100_0000 => 16,
+                _ => 1,
+            };
+            self.fifo_depth.set(depth);
+        }
+        self.fifo_depth()
+    }
+
+    /// Returns a snapshot of the traffic and line error counters.
+    ///
+    /// Line errors are counted whenever LSR is read through this driver, so the information is
+    /// kept even though reading LSR clears the error bits.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> UartStats {
+        self.stats.get()
+    }
+
+    /// Resets all counters to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
+
+    /// Saves the state of the UART so that it can be reprogrammed later by [`restore`].
+    ///
+    /// The divisor latch is read by briefly setting DLAB, and LCR is put back as it was found.
+    /// FCR is write-only, so the value last written through this driver is reported instead. If
+    /// nothing was written yet, as when firmware set the UART up before [`new`], only the FIFO
+    /// enable bit is rebuilt from IIR\[7:6\] and the trigger level is left at 1 byte. Reading IIR
+    /// then clears a pending transmitter holding register empty interrupt.
+    ///
+    /// [`new`]: Self::new
+    /// [`restore`]: Self::restore
+    pub fn save(&self) -> UartState {
+        let fcr = match self.fcr.get() {
+            0 => match self.read_fifo_status() {
+                ChipFifoInfo::Enabled | ChipFifoInfo::EnabledNoFunction => 0b0000_0001,
+                ChipFifoInfo::NoFifo | ChipFifoInfo::Reserved => 0,
+            },
+            fcr => fcr,
+        };
+        let lcr = self.read_lcr();
+
+        self.write_lcr(lcr | 0b1000_0000);
+        let divisor = u16::from_le_bytes([self.read_dll(), self.read_dlh()]);
+        self.write_lcr(lcr & !0b1000_0000);
+        let ier = self.read_ier();
+        self.write_lcr(lcr);
+
+        UartState {
+            divisor,
+            ier,
+            lcr,
+            mcr: self.read_mcr(),
+            fcr,
+            scratch: self.read_sr(),
+        }
+    }
+
+    /// Reprograms the UART with a state previously returned by [`save`].
+    ///
+    /// Interrupts are masked while the other registers are written and IER is restored last, so
+    /// no interrupt can fire with a half-restored configuration.
+    ///
+    /// [`save`]: Self::save
+    pub fn restore(&self, state: &UartState) {
+        self.write_lcr(state.lcr & !0b1000_0000);
+        self.write_ier(0);
+
+        self.write_lcr(state.lcr | 0b1000_0000);
+        // The saved divisor may be for another rate than the one remembered
+        self.baud_rate.set(None);
+        let [dll, dlh] = state.divisor.to_le_bytes();
+        self.write_dll(dll);
+        self.write_dlh(dlh);
+        self.write_lcr(state.lcr & !0b1000_0000);
+
+        self.write_fcr(state.fcr);
+        self.write_mcr(state.mcr);
+        self.write_sr(state.scratch);
+        self.write_ier(state.ier);
+        self.write_lcr(state.lcr);
+    }
+
+    /// Reads the registers and decodes them into a [`RegisterDump`].
+    ///
+    /// Only registers that can be read without side effects are read, plus those selected by
+    /// `flags`. With [`DumpFlags::empty()`] the UART is left exactly as it was found.
+    pub fn dump(&self, flags: DumpFlags) -> RegisterDump {
+        let lcr = self.read_lcr();
+        let dlab = lcr & 0b1000_0000 != 0;
+
+        let divisor = if flags.contains(DumpFlags::DIVISOR) {
+            self.write_lcr(lcr | 0b1000_0000);
+            let divisor = u16::from_le_bytes([self.read_dll(), self.read_dlh()]);
+            self.write_lcr(lcr & !0b1000_0000);
+            Some(divisor)
+        } else {
+            None
+        };
+        // With DLAB set, IER and RBR read as DLH and DLL
+        let dlab_clear = !dlab || divisor.is_some();
+        let ier = if dlab_clear { Some(self.ier()) } else { None };
+        let rbr = if dlab_clear && flags.contains(DumpFlags::RBR) {
+            Some(self.read_rbr())
+        } else {
+            None
+        };
+        if divisor.is_some() {
+            self.write_lcr(lcr);
+        }
+        let iir = if flags.contains(DumpFlags::IIR) {
+            let iir = self.reg.iir_fcr.read();
+            Some(Iir {
+                interrupt: InterruptType::from_iir(iir),
+                fifo: ChipFifoInfo::from_iir(iir),
+                fifo_64byte: iir & 0b0010_0000 != 0,
+            })
+        } else {
+            None
+        };
+
+        RegisterDump {
+            divisor,
+            frame_format: FrameFormat::from_lcr(lcr),
+            break_enabled: lcr & 0b0100_0000 != 0,
+            divisor_latch_accessible: dlab,
+            ier,
+            iir,
+            fcr: self.fcr.get(),
+            mcr: MCR::from_bits_truncate(self.read_mcr()),
+            lsr: if flags.contains(DumpFlags::LSR) {
+                Some(self.lsr())
+            } else {
+                None
+            },
+            msr: if flags.contains(DumpFlags::MSR) {
+                Some(self.msr())
+            } else {
+                None
+            },
+            scratch: self.read_sr(),
+            rbr,
+        }
+    }
+
     /// write THR (offset + 0)
     ///
     /// Write Transmitter Holding Buffer to send data
@@ -205,6 +871,14 @@ impl<'a> MmioUart8250<'a> {
         self.reg.thr_rbr_dll.read()
     }
 
+    /// read DLL (offset + 0)
+    ///
+    /// get divisor latch low byte in the register
+    #[inline]
+    fn read_dll(&self) -> u8 {
+        self.reg.thr_rbr_dll.read()
+    }
+
     /// write DLL (offset + 0)
     ///
     /// set divisor latch low byte in the register
@@ -213,6 +887,14 @@ impl<'a> MmioUart8250<'a> {
         unsafe { self.reg.thr_rbr_dll.write(value) }
     }
 
+    /// read DLH (offset + 1)
+    ///
+    /// get divisor latch high byte in the register
+    #[inline]
+    fn read_dlh(&self) -> u8 {
+        self.reg.ier_dlh.read()
+    }
+
     /// write DLH (offset + 1)
     ///
     /// set divisor latch high byte in the register
@@ -249,13 +931,60 @@ impl<'a> MmioUart8250<'a> {
     /// | 38400     | 3                    | $00                     | $03                    |
     /// | 57600     | 2                    | $00                     | $02                    |
     /// | 115200    | 1                    | $00                     | $01                    |
+    ///
+    /// Does nothing if `baud_rate` is 0. When `clock` is too slow or too fast for `baud_rate`, the
+    /// divisor is clamped to what the latch holds, 1 to 65535.
     #[inline]
     pub fn set_divisor(&self, clock: usize, baud_rate: usize) {
+        let divisor = match clock.checked_div(baud_rate.saturating_mul(16)) {
+            Some(divisor) => divisor.clamp(1, u16::MAX as usize),
+            None => return,
+        };
         self.enable_divisor_latch_accessible();
-        let divisor = clock / (16 * baud_rate);
         self.write_dll(divisor as u8);
         self.write_dlh((divisor >> 8) as u8);
         self.disable_divisor_latch_accessible();
+        self.baud_rate.set(BaudRate::from_bps(baud_rate));
+    }
+
+    /// Sets the divisor for `baud_rate` at the current frequency of `clock`.
+    pub fn set_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C, baud_rate: BaudRate) {
+        self.set_divisor(clock.frequency_hz(), baud_rate.bps())
+    }
+
+    /// Returns the baud rate the divisor was last set for, by [`init`](Self::init),
+    /// [`set_divisor`](Self::set_divisor) or [`set_baud_rate`](Self::set_baud_rate).
+    ///
+    /// Unlike [`get_baud_rate`](Self::get_baud_rate) this is the rate asked for, not the one the
+    /// divisor actually gives.
+    pub fn baud_rate(&self) -> Option<BaudRate> {
+        self.baud_rate.get()
+    }
+
+    /// Recomputes and writes the divisor after the frequency of `clock` changed, e.g. on a DVFS
+    /// transition, so the UART keeps its baud rate.
+    ///
+    /// Returns the baud rate, or `None` without touching the UART if none was set.
+    pub fn reapply_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C) -> Option<BaudRate> {
+        let baud_rate = self.baud_rate.get()?;
+        self.set_baud_rate(clock, baud_rate);
+        Some(baud_rate)
+    }
+
+    /// Sets DLAB to true, reads the divisor latch, then sets DLAB to false.
+    pub fn get_divisor(&self) -> u16 {
+        self.enable_divisor_latch_accessible();
+        let divisor = u16::from_le_bytes([self.read_dll(), self.read_dlh()]);
+        self.disable_divisor_latch_accessible();
+        divisor
+    }
+
+    /// get the baud rate the divisor latch gives with `clock`, `None` if the divisor is 0
+    pub fn get_baud_rate(&self, clock: usize) -> Option<usize> {
+        match self.get_divisor() {
+            0 => None,
+            divisor => Some(clock / (16 * divisor as usize)),
+        }
     }
 
     /// Read IER (offset + 1)
@@ -303,6 +1032,26 @@ impl<'a> MmioUart8250<'a> {
         self.write_ier(flag.bits())
     }
 
+    /// Gets the enabled interrupts (IER).
+    pub fn interrupts(&self) -> IER {
+        self.ier()
+    }
+
+    /// Enables exactly the given interrupts (IER) with a single write.
+    pub fn set_interrupts(&self, interrupts: IER) {
+        self.set_ier(interrupts)
+    }
+
+    /// Masks all UART interrupts until the returned guard is dropped, which restores the
+    /// previous IER.
+    ///
+    /// Only the interrupt enable bits are cleared, the 16750 sleep and low power modes are kept.
+    pub fn mask_interrupts(&self) -> InterruptMaskGuard<'_, 'a> {
+        let saved = self.ier();
+        self.set_ier(saved & (IER::SM | IER::LPM));
+        InterruptMaskGuard { uart: self, saved }
+    }
+
     /// get whether low power mode (16750) is enabled (IER\[5\])
     pub fn is_low_power_mode_enabled(&self) -> bool {
         self.ier().contains(IER::LPM)
@@ -393,15 +1142,41 @@ impl<'a> MmioUart8250<'a> {
         self.set_ier(self.ier() & !IER::RDAI)
     }
 
+    /// Enable the FIFOs with the given receive trigger level (FCR\[7:6\])
+    ///
+    /// The other FCR bits written so far are kept.
+    pub fn set_fifo_trigger(&self, trigger: FifoTrigger) {
+        self.write_fcr((self.fcr.get() & 0b0011_1111) | (trigger as u8) << 6 | 1)
+    }
+
+    /// Selects DMA mode 1 (FCR\[3\]) or mode 0 and enables the FIFOs
+    ///
+    /// In mode 1 TXRDY stays active until the transmit FIFO is full and RXRDY until the receive
+    /// FIFO drops below the trigger level, so a DMA engine can move several bytes per request. The
+    /// other FCR bits written so far are kept.
+    pub fn set_dma_mode(&self, mode1: bool) {
+        let fcr = self.fcr.get() & !0b0000_1000;
+        self.write_fcr(fcr | (mode1 as u8) << 3 | 1)
+    }
+
+    /// Get whether DMA mode 1 was last written to FCR\[3\]
+    pub fn is_dma_mode(&self) -> bool {
+        self.fcr.get() & 0b0000_1000 != 0
+    }
+
+    /// Get the receive trigger level last written to FCR\[7:6\]
+    pub fn fifo_trigger(&self) -> FifoTrigger {
+        match self.fcr.get() >> 6 {
+            0b00 => FifoTrigger::Bytes1,
+            0b01 => FifoTrigger::Bytes4,
+            0b10 => FifoTrigger::Bytes8,
+            _ => FifoTrigger::Bytes14,
+        }
+    }
+
     /// Read IIR\[7:6\] to get FIFO status
     pub fn read_fifo_status(&self) -> ChipFifoInfo {
-        match self.reg.iir_fcr.read() & 0b1100_0000 {
-            0 => ChipFifoInfo::NoFifo,
-            0b0100_0000 => ChipFifoInfo::Reserved,
-            0b1000_0000 => ChipFifoInfo::EnabledNoFunction,
-            0b1100_0000 => ChipFifoInfo::Enabled,
-            _ => panic!("Can't reached"),
-        }
+        ChipFifoInfo::from_iir(self.reg.iir_fcr.read())
     }
 
     /// get whether 64 Byte fifo (16750 only) is enabled (IIR\[5\])
@@ -411,20 +1186,7 @@ impl<'a> MmioUart8250<'a> {
 
     /// Read IIR\[3:1\] to get interrupt type
     pub fn read_interrupt_type(&self) -> Option<InterruptType> {
-        let iir = self.reg.iir_fcr.read() & 0b0000_1111;
-        if iir & 1 != 0 {
-            None
-        } else {
-            match iir {
-                0b0000 => Some(InterruptType::ModemStatus),
-                0b0010 => Some(InterruptType::TransmitterHoldingRegisterEmpty),
-                0b0100 => Some(InterruptType::ReceivedDataAvailable),
-                0b0110 => Some(InterruptType::ReceiverLineStatus),
-                0b1100 => Some(InterruptType::Timeout),
-                0b1000 | 0b1010 | 0b1110 => Some(InterruptType::Reserved),
-                _ => panic!("Can't reached"),
-            }
-        }
+        InterruptType::from_iir(self.reg.iir_fcr.read())
     }
 
     /// Write FCR (offset + 2) to control FIFO buffers
@@ -450,9 +1212,19 @@ impl<'a> MmioUart8250<'a> {
     /// > | 0     | Enable FIFOs                |       |                                   |                         |
     #[inline]
     fn write_fcr(&self, value: u8) {
+        // Clearing the FIFOs (FCR[2:1]) is a one-shot action, don't replay it on restore
+        self.fcr.set(value & !0b0000_0110);
         unsafe { self.reg.iir_fcr.write(value) }
     }
 
+    /// Read LCR (offset + 3)
+    ///
+    /// Read Line Control Register to get the data protocol and DLAB
+    #[inline]
+    fn read_lcr(&self) -> u8 {
+        self.reg.lcr.read()
+    }
+
     /// Write LCR (offset + 3)
     ///
     /// Write Line Control Register to set DLAB and the serial data protocol
@@ -473,55 +1245,80 @@ impl<'a> MmioUart8250<'a> {
 
     /// get parity of used data protocol
     pub fn get_parity(&self) -> Parity {
-        match self.reg.lcr.read() & 0b0011_1000 {
-            0b0000_0000 => Parity::No,
-            0b0000_1000 => Parity::Odd,
-            0b0001_1000 => Parity::Even,
-            0b0010_1000 => Parity::Mark,
-            0b0011_1000 => Parity::Space,
-            _ => panic!("Invalid Parity! Please check your uart"),
-        }
+        Parity::from_lcr(self.reg.lcr.read())
     }
 
     /// set parity
     pub fn set_parity(&self, parity: Parity) {
-        match parity {
-            Parity::No => unsafe { self.reg.lcr.modify(|v| (v & 0b1100_0111)) },
-            Parity::Odd => unsafe { self.reg.lcr.modify(|v| (v & 0b1100_0111) | 0b0000_1000) },
-            Parity::Even => unsafe { self.reg.lcr.modify(|v| (v & 0b1100_0111) | 0b0001_1000) },
-            Parity::Mark => unsafe { self.reg.lcr.modify(|v| (v & 0b1100_0111) | 0b0010_1000) },
-            Parity::Space => unsafe { self.reg.lcr.modify(|v| v | 0b0011_1000) },
+        unsafe {
+            self.reg
+                .lcr
+                .modify(|v| (v & !0b0011_1000) | parity.to_lcr())
         }
     }
 
     /// get stop bit of used data protocol
     ///
     /// Simply return a u8 to indicate 1 or 1.5/2 bits
+    #[deprecated(note = "use `frame_format().stop_bits`, which tells 1.5 from 2 stop bits")]
     pub fn get_stop_bit(&self) -> u8 {
-        ((self.reg.lcr.read() & 0b100) >> 2) + 1
+        match self.frame_format().stop_bits {
+            StopBits::One => 1,
+            StopBits::OnePointFive | StopBits::Two => 2,
+        }
     }
 
     /// set stop bit, only 1 and 2 can be used as `stop_bit`
+    ///
+    /// 2 gives 1.5 stop bits with 5 data bits.
+    #[deprecated(note = "use `set_frame_format`")]
     pub fn set_stop_bit(&self, stop_bit: u8) {
-        match stop_bit {
-            1 => unsafe { self.reg.lcr.modify(|v| v & 0b1111_1011) },
-            2 => unsafe { self.reg.lcr.modify(|v| v | 0b0000_0100) },
+        let stop = match stop_bit {
+            1 => 0,
+            2 => 0b100,
             _ => panic!("Invalid stop bit"),
-        }
+        };
+        self.set_frame_format_from_lcr((self.reg.lcr.read() & !0b100) | stop)
     }
 
     /// get word length of used data protocol
+    #[deprecated(note = "use `frame_format().data_bits`")]
     pub fn get_word_length(&self) -> u8 {
-        (self.reg.lcr.read() & 0b11) + 5
+        self.frame_format().data_bits.bits()
     }
 
     /// set word length, only 5..=8 can be used as `length`
+    #[deprecated(note = "use `set_frame_format`")]
     pub fn set_word_length(&self, length: u8) {
-        if (5..=8).contains(&length) {
-            unsafe { self.reg.lcr.modify(|v| v | (length - 5)) }
-        } else {
-            panic!("Invalid word length")
-        }
+        let data_bits = DataBits::from_bits(length).expect("Invalid word length");
+        self.set_frame_format_from_lcr((self.reg.lcr.read() & !0b11) | data_bits.to_lcr())
+    }
+
+    /// Sets the character format decoded from `lcr`, so LCR\[2\] keeps meaning 1.5 or 2 stop
+    /// bits whatever the number of data bits.
+    fn set_frame_format_from_lcr(&self, lcr: u8) {
+        // A format decoded from LCR can always be encoded back
+        let _ = self.set_frame_format(FrameFormat::from_lcr(lcr));
+    }
+
+    /// Returns the character format (LCR\[5:0\]).
+    pub fn frame_format(&self) -> FrameFormat {
+        FrameFormat::from_lcr(self.reg.lcr.read())
+    }
+
+    /// Sets the character format (LCR\[5:0\]), keeping the break and DLAB bits.
+    pub fn set_frame_format(&self, format: FrameFormat) -> Result<(), InvalidFrameFormat> {
+        let lcr = format.to_lcr().ok_or(InvalidFrameFormat)?;
+        unsafe { self.reg.lcr.modify(|v| (v & 0b1100_0000) | lcr) };
+        Ok(())
+    }
+
+    /// Read MCR (offset + 4)
+    ///
+    /// Read Modem Control Register to get how flow is controlled
+    #[inline]
+    fn read_mcr(&self) -> u8 {
+        self.reg.mcr.read()
     }
 
     /// Write MCR (offset + 4)
@@ -556,7 +1353,16 @@ impl<'a> MmioUart8250<'a> {
     /// Get LSR bitflags
     #[inline]
     fn lsr(&self) -> LSR {
-        LSR::from_bits_truncate(self.read_lsr())
+        let lsr = LSR::from_bits_truncate(self.read_lsr());
+        // The error bits are cleared by this read, so count them before they are lost
+        #[cfg(feature = "stats")]
+        self.stats.count_line_errors(
+            lsr.contains(LSR::FE),
+            lsr.contains(LSR::PE),
+            lsr.contains(LSR::BI),
+            lsr.contains(LSR::OE),
+        );
+        lsr
     }
 
     /// get whether there is an error in received FIFO
@@ -666,16 +1472,42 @@ impl<'a> MmioUart8250<'a> {
     }
 }
 
+/// Keeps the UART interrupts masked while alive, see [`MmioUart8250::mask_interrupts`].
+pub struct InterruptMaskGuard<'u, 'a> {
+    uart: &'u MmioUart8250<'a>,
+    saved: IER,
+}
+
+impl InterruptMaskGuard<'_, '_> {
+    /// Gets the interrupts that will be enabled again when the guard is dropped.
+    pub fn saved(&self) -> IER {
+        self.saved
+    }
+}
+
+impl Drop for InterruptMaskGuard<'_, '_> {
+    fn drop(&mut self) {
+        self.uart.set_ier(self.saved)
+    }
+}
+
+/// Dumps the registers without reading RBR, see [`MmioUart8250::dump`].
+impl fmt::Debug for MmioUart8250<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MmioUart8250")
+            .field("registers", &self.dump(DumpFlags::empty()))
+            .finish()
+    }
+}
+
 /// ## fmt::Write
 ///
-/// A simple implementation, may be changed in the future
+/// Output is translated as set by [`MmioUart8250::set_output_flags`]
 #[cfg(feature = "fmt")]
 impl<'a> fmt::Write for MmioUart8250<'a> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.as_bytes() {
-            // If buffer is full, keep retrying.
-            while self.write_byte(*c) == Err(TransmitError::BufferFull) {}
-        }
+        self.output
+            .write(s.as_bytes(), |bytes| self.write_all(bytes));
         Ok(())
     }
 }
@@ -694,9 +1526,8 @@ impl embedded_hal::serial::Write<u8> for MmioUart8250<'_> {
     type Error = Infallible;
 
     fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        self.write_byte(byte).map_err(|e| match e {
-            TransmitError::BufferFull => nb::Error::WouldBlock,
-        })
+        // write_byte only fails with a full buffer
+        self.write_byte(byte).map_err(|_| nb::Error::WouldBlock)
     }
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
@@ -709,7 +1540,11 @@ impl embedded_hal::serial::Write<u8> for MmioUart8250<'_> {
 }
 
 #[cfg(test)]
+// Some tests write `fake_registers` and read them back through the UART, which the lint can't see
+#[allow(unused_assignments)]
 mod tests {
+    extern crate std;
+
     use super::*;
 
     // These tests treat normal memory as device memory, which is not necessarily guaranteed to
@@ -724,9 +1559,95 @@ mod tests {
 
         uart.init(11_059_200, 115200);
 
-        assert!(matches!(uart.get_parity(), Parity::No));
-        assert_eq!(uart.get_stop_bit(), 1);
-        assert_eq!(uart.get_word_length(), 8);
+        assert_eq!(uart.frame_format(), FrameFormat::default());
+    }
+
+    #[test]
+    fn fifo_trigger() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        uart.init(1_843_200, 115_200);
+        assert_eq!(uart.fifo_depth(), 1);
+        unsafe {
+            (&mut fake_registers as *mut u8)
+                .add(2)
+                .write_volatile(0b1100_0001)
+        };
+        assert_eq!(uart.detect_fifo_depth(), 16);
+        // Writing FCR doesn't read IIR back, which would acknowledge a THRE interrupt
+        uart.set_fifo_trigger(FifoTrigger::Bytes8);
+        assert_eq!(uart.fifo_depth(), 16);
+        assert_eq!(fake_registers[2], 0b1000_0001);
+        assert_eq!(uart.fifo_trigger(), FifoTrigger::Bytes8);
+        uart.set_dma_mode(true);
+        assert_eq!(fake_registers[2], 0b1000_1001);
+        assert!(uart.is_dma_mode());
+        assert_eq!(FifoTrigger::Bytes8.level(16), 8);
+        assert_eq!(FifoTrigger::Bytes8.level(64), 32);
+        assert_eq!(FifoTrigger::Bytes8.level(1), 1);
+    }
+
+    #[test]
+    fn read_with_status() {
+        let mut fake_registers: [u8; 8] = [0x41, 0, 0, 0, 0, (LSR::DR | LSR::PE).bits(), 0, 0];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        assert_eq!(
+            uart.read_byte_with_status(),
+            Some((0x41, LSR::DR | LSR::PE))
+        );
+    }
+
+    #[test]
+    fn divisor() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        assert_eq!(uart.get_baud_rate(1_843_200), None);
+        uart.set_divisor(1_843_200, 50);
+        assert_eq!(uart.get_divisor(), 2304);
+        assert_eq!(uart.get_baud_rate(1_843_200), Some(50));
+        assert_eq!(fake_registers[3] & 0x80, 0);
+    }
+
+    #[test]
+    fn baud_rate() {
+        struct Pll(Cell<usize>);
+
+        impl ClockSource for Pll {
+            fn frequency_hz(&self) -> usize {
+                self.0.get()
+            }
+        }
+
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+        let pll = Pll(Cell::new(1_843_200));
+
+        assert_eq!(uart.reapply_baud_rate(&pll), None);
+        uart.set_baud_rate(&pll, BaudRate::B115200);
+        assert_eq!(uart.get_divisor(), 1);
+        pll.0.set(7_372_800);
+        assert_eq!(uart.reapply_baud_rate(&pll), Some(BaudRate::B115200));
+        assert_eq!(uart.get_divisor(), 4);
+
+        uart.init(1_843_200, 11_520);
+        assert_eq!(uart.baud_rate(), BaudRate::from_bps(11_520));
+        uart.init_with_baud_rate(&pll, BaudRate::B9600);
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B9600));
+        // DLL, the low byte of 48, is kept: nothing overwrites RBR
+        assert_eq!((fake_registers[0], uart.read_lcr()), (48, 3));
+
+        // A zero baud rate is ignored, and the divisor clamped to the latch
+        uart.set_divisor(1_843_200, 11_520);
+        assert_eq!(uart.get_divisor(), 10);
+        uart.set_divisor(1_843_200, 0);
+        assert_eq!(uart.get_divisor(), 10);
+        uart.set_divisor(1_843_200, 230_400);
+        assert_eq!(uart.get_divisor(), 1);
+        uart.set_divisor(usize::MAX, 50);
+        assert_eq!(uart.get_divisor(), u16::MAX);
     }
 
     #[test]
@@ -759,4 +1680,296 @@ mod tests {
 
         assert_eq!(uart.read_byte(), Some(0xab));
     }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+
+        // Transmit buffer available, a byte ready with a framing and an overrun error.
+        unsafe { base.add(5).write_volatile(0b0010_1011) };
+        assert_eq!(uart.write_byte(0x42), Ok(()));
+        assert!(uart.read_byte().is_some());
+        assert_eq!(
+            uart.stats(),
+            UartStats {
+                tx: 1,
+                rx: 1,
+                frame: 2,
+                overrun: 2,
+                ..UartStats::default()
+            }
+        );
+
+        // A break is not counted as a framing error.
+        unsafe { base.add(5).write_volatile(0b0001_1000) };
+        assert!(uart.is_break_interrupt());
+        let stats = uart.stats();
+        assert_eq!((stats.brk, stats.frame), (1, 2));
+        assert_eq!(std::format!("{}", stats), "tx:1 rx:1 fe:2 brk:1 oe:2");
+
+        uart.reset_stats();
+        assert_eq!(uart.stats(), UartStats::default());
+    }
+
+    #[test]
+    fn bulk_read_write() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+
+        // Without a working FIFO only one byte is written per THRE.
+        uart.init(11_059_200, 115200);
+        assert_eq!(uart.fifo_depth(), 1);
+        assert_eq!(uart.write(b"hello"), 0);
+        unsafe { base.add(5).write_volatile(0b0110_0000) };
+        assert_eq!(uart.write(b"hello"), 1);
+        uart.write_all(b"hello");
+        assert_eq!(unsafe { base.read_volatile() }, b'o');
+        uart.flush();
+
+        let mut buf = [0; 4];
+        assert_eq!(uart.read(&mut buf), 0);
+        unsafe {
+            base.write_volatile(0xab);
+            base.add(5).write_volatile(0b0000_0001);
+        }
+        uart.read_exact(&mut buf);
+        assert_eq!(buf, [0xab; 4]);
+    }
+
+    /// A clock that advances by 1µs every time it is read.
+    struct FakeClock(u64);
+
+    impl Monotonic for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.0 += 1;
+            self.0
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+        let mut clock = FakeClock(0);
+
+        // Nothing to read, transmitter stuck.
+        let mut buf = [0; 4];
+        assert_eq!(
+            uart.read_timeout(&mut buf, &mut clock, 100),
+            Err(ReceiveError::Timeout)
+        );
+        assert_eq!(
+            uart.write_timeout(b"hello", &mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        assert_eq!(
+            uart.flush_timeout(&mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+
+        // Data available and transmitter idle.
+        unsafe {
+            base.write_volatile(0xab);
+            base.add(5).write_volatile(0b0110_0001);
+        }
+        assert_eq!(uart.read_timeout(&mut buf, &mut clock, 100), Ok(4));
+        assert_eq!(uart.write_timeout(b"hello", &mut clock, 100), Ok(()));
+        assert_eq!(uart.flush_timeout(&mut clock, 100), Ok(()));
+    }
+
+    #[test]
+    fn dump() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+
+        uart.init(11_059_200, 115200);
+        unsafe {
+            base.write_volatile(0x42);
+            // 7 data bits, 2 stop bits, even parity
+            base.add(3).write_volatile(0b0001_1110);
+            base.add(4).write_volatile(0b0000_1011);
+            base.add(5).write_volatile(0b0110_0001);
+        }
+
+        // Without flags only LCR, IER, MCR and the scratch register are read
+        let dump = uart.dump(DumpFlags::empty());
+        assert_eq!(dump.divisor, None);
+        assert_eq!(dump.ier, Some(IER::RDAI));
+        assert_eq!(
+            (dump.iir, dump.lsr, dump.msr, dump.rbr),
+            (None, None, None, None)
+        );
+        assert!(std::format!("{:?}", uart).starts_with("MmioUart8250 { registers: RegisterDump {"));
+
+        let dump = uart.dump(DumpFlags::all() - DumpFlags::RBR);
+        assert_eq!(
+            dump.frame_format,
+            FrameFormat::new(DataBits::Seven, Parity::Even, StopBits::Two)
+        );
+        assert!(!dump.divisor_latch_accessible);
+        assert_eq!(dump.fcr, 1);
+        assert_eq!(dump.mcr, MCR::DTR | MCR::RTS | MCR::OUT2);
+        assert_eq!(dump.lsr, Some(LSR::DR | LSR::THRE | LSR::DHRE));
+        assert_eq!(dump.rbr, None);
+        assert_eq!(uart.dump(DumpFlags::RBR).rbr, Some(0x42));
+    }
+
+    #[test]
+    fn interrupt_mask() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+
+        uart.set_interrupts(IER::RDAI | IER::RLSI | IER::SM);
+        assert_eq!(uart.interrupts(), IER::RDAI | IER::RLSI | IER::SM);
+        {
+            let guard = uart.mask_interrupts();
+            assert_eq!(guard.saved(), IER::RDAI | IER::RLSI | IER::SM);
+            assert_eq!(uart.interrupts(), IER::SM);
+        }
+        assert_eq!(uart.interrupts(), IER::RDAI | IER::RLSI | IER::SM);
+    }
+
+    #[test]
+    fn save_restore() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        uart.init(11_059_200, 115200);
+        uart.write_sr(0x5a);
+        let state = uart.save();
+        assert_eq!(state.lcr, 0b0000_0011);
+        assert_eq!(state.fcr, 1);
+        assert_eq!(state.mcr, 0);
+        assert_eq!(state.scratch, 0x5a);
+
+        let mut other_registers: [u8; 8] = [0xff; 8];
+        let other = unsafe { MmioUart8250::new(&mut other_registers as *mut u8 as usize) };
+        other.restore(&state);
+        assert_eq!(other.save(), state);
+        assert_eq!(other_registers[3], 0b0000_0011);
+        assert_eq!(other_registers[4], 0);
+        assert_eq!(other_registers[7], 0x5a);
+    }
+
+    #[test]
+    fn save_before_init() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let base = fake_registers.as_mut_ptr();
+        let uart = unsafe { MmioUart8250::new(base as usize) };
+
+        // Firmware left the FIFOs enabled, which only shows in IIR[7:6].
+        unsafe { base.add(2).write_volatile(0b1100_0001) };
+        assert_eq!(uart.save().fcr, 1);
+
+        unsafe { base.add(2).write_volatile(0b0000_0001) };
+        assert_eq!(uart.save().fcr, 0);
+
+        // Once FCR has been written, the shadow is reported whatever IIR says.
+        uart.set_fifo_trigger(FifoTrigger::Bytes8);
+        unsafe { base.add(2).write_volatile(0b0000_0001) };
+        assert_eq!(uart.save().fcr, 0b1000_0001);
+    }
+
+    const DATA_BITS: [DataBits; 4] = [
+        DataBits::Five,
+        DataBits::Six,
+        DataBits::Seven,
+        DataBits::Eight,
+    ];
+    const PARITIES: [Parity; 5] = [
+        Parity::No,
+        Parity::Odd,
+        Parity::Even,
+        Parity::Mark,
+        Parity::Space,
+    ];
+    const STOP_BITS: [StopBits; 3] = [StopBits::One, StopBits::OnePointFive, StopBits::Two];
+
+    #[test]
+    fn frame_format() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        for data_bits in DATA_BITS {
+            for parity in PARITIES {
+                for stop_bits in STOP_BITS {
+                    let format = FrameFormat::new(data_bits, parity, stop_bits);
+                    let valid = match stop_bits {
+                        StopBits::One => true,
+                        StopBits::OnePointFive => data_bits == DataBits::Five,
+                        StopBits::Two => data_bits != DataBits::Five,
+                    };
+                    // Start from every other format, with break and DLAB set or not
+                    for previous in 0..=0b1111_1111 {
+                        uart.write_lcr(previous);
+                        if valid {
+                            assert_eq!(uart.set_frame_format(format), Ok(()));
+                            assert_eq!(uart.frame_format(), format);
+                            assert_eq!(uart.read_lcr() & 0b1100_0000, previous & 0b1100_0000);
+                            assert_eq!(uart.get_parity(), parity);
+                        } else {
+                            assert_eq!(uart.set_frame_format(format), Err(InvalidFrameFormat));
+                            assert_eq!(uart.read_lcr(), previous);
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            FrameFormat::default(),
+            FrameFormat::new(DataBits::Eight, Parity::No, StopBits::One)
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn frame_format_setters() {
+        let mut fake_registers: [u8; 8] = [0; 8];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        for lcr in 0..=0b1111_1111 {
+            uart.write_lcr(lcr);
+            // Every encoding decodes, including parity bits set with parity disabled
+            let format = uart.frame_format();
+            assert_eq!(uart.get_parity(), format.parity);
+            assert_eq!(uart.get_word_length(), format.data_bits.bits());
+            if lcr & 0b1000 == 0 {
+                assert_eq!(format.parity, Parity::No);
+            }
+
+            for data_bits in DATA_BITS {
+                uart.write_lcr(lcr);
+                uart.set_word_length(data_bits.bits());
+                assert_eq!(uart.get_word_length(), data_bits.bits());
+                assert_eq!(
+                    uart.frame_format(),
+                    FrameFormat::from_lcr((lcr & !0b11) | data_bits.to_lcr())
+                );
+                assert_eq!(uart.read_lcr() & 0b1100_0000, lcr & 0b1100_0000);
+            }
+            for parity in PARITIES {
+                uart.write_lcr(lcr);
+                uart.set_parity(parity);
+                assert_eq!(uart.get_parity(), parity);
+                assert_eq!(uart.read_lcr() & !0b0011_1000, lcr & !0b0011_1000);
+            }
+            for stop_bit in 1..=2 {
+                uart.write_lcr(lcr);
+                uart.set_stop_bit(stop_bit);
+                assert_eq!(uart.get_stop_bit(), stop_bit);
+                assert_eq!(
+                    uart.frame_format(),
+                    FrameFormat::from_lcr((lcr & !0b100) | (stop_bit - 1) << 2)
+                );
+                assert_eq!(uart.read_lcr() & 0b1100_0000, lcr & 0b1100_0000);
+            }
+        }
+    }
 }
@@ -0,0 +1,117 @@
+/*!
+# baud
+
+Baud rates and the input clock of the uarts
+*/
+
+use core::num::NonZeroUsize;
+
+/// A baud rate: one of the standard rates, or a custom one
+///
+/// Prefer the named rates, a typo in one of them doesn't compile
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BaudRate {
+    B300,
+    B600,
+    B1200,
+    B2400,
+    B4800,
+    B9600,
+    B19200,
+    B38400,
+    B57600,
+    B115200,
+    B230400,
+    B460800,
+    B921600,
+    /// Any other rate in bits per second
+    Custom(NonZeroUsize),
+}
+
+impl BaudRate {
+    /// Every named rate, slowest first
+    pub const STANDARD: [BaudRate; 13] = [
+        BaudRate::B300,
+        BaudRate::B600,
+        BaudRate::B1200,
+        BaudRate::B2400,
+        BaudRate::B4800,
+        BaudRate::B9600,
+        BaudRate::B19200,
+        BaudRate::B38400,
+        BaudRate::B57600,
+        BaudRate::B115200,
+        BaudRate::B230400,
+        BaudRate::B460800,
+        BaudRate::B921600,
+    ];
+
+    /// Get the rate in bits per second, never 0
+    pub const fn bps(self) -> usize {
+        match self {
+            BaudRate::B300 => 300,
+            BaudRate::B600 => 600,
+            BaudRate::B1200 => 1200,
+            BaudRate::B2400 => 2400,
+            BaudRate::B4800 => 4800,
+            BaudRate::B9600 => 9600,
+            BaudRate::B19200 => 19200,
+            BaudRate::B38400 => 38400,
+            BaudRate::B57600 => 57600,
+            BaudRate::B115200 => 115200,
+            BaudRate::B230400 => 230400,
+            BaudRate::B460800 => 460800,
+            BaudRate::B921600 => 921600,
+            BaudRate::Custom(bps) => bps.get(),
+        }
+    }
+
+    /// Get the named rate for `bps`, or `Custom(bps)` if it isn't a standard one
+    ///
+    /// Return `None` for 0
+    pub fn from_bps(bps: usize) -> Option<Self> {
+        let custom = BaudRate::Custom(NonZeroUsize::new(bps)?);
+        Some(
+            Self::STANDARD
+                .iter()
+                .copied()
+                .find(|rate| rate.bps() == bps)
+                .unwrap_or(custom),
+        )
+    }
+}
+
+impl From<BaudRate> for usize {
+    fn from(rate: BaudRate) -> usize {
+        rate.bps()
+    }
+}
+
+/// The input clock of the uart, whose frequency may change at runtime, e.g. with DVFS
+pub trait ClockSource {
+    /// Get the current frequency in Hz
+    fn frequency_hz(&self) -> usize;
+}
+
+/// A fixed frequency in Hz
+impl ClockSource for usize {
+    fn frequency_hz(&self) -> usize {
+        *self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_bps() {
+        for rate in BaudRate::STANDARD {
+            assert_eq!(BaudRate::from_bps(rate.bps()), Some(rate));
+        }
+        let custom = BaudRate::from_bps(11_520).unwrap();
+        assert_eq!(custom, BaudRate::Custom(NonZeroUsize::new(11_520).unwrap()));
+        assert_eq!(usize::from(custom), 11_520);
+        assert_eq!(BaudRate::from_bps(0), None);
+    }
+}
@@ -0,0 +1,25 @@
+/*!
+# uart_common
+
+Types shared by the uart crates of [uart-rs](https://github.com/duskmoon314/uart-rs)
+
+Each driver re-exports them, so a `BaudRate` or a `Monotonic` clock works with all of them.
+*/
+
+#![no_std]
+
+pub mod baud;
+#[cfg(feature = "fmt")]
+pub mod output;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod timeout;
+
+pub use baud::{BaudRate, ClockSource};
+#[cfg(feature = "fmt")]
+pub use output::{Output, OutputFlags};
+#[cfg(feature = "stats")]
+pub use stats::{StatsCounter, UartStats};
+#[cfg(feature = "embedded")]
+pub use timeout::DelayMonotonic;
+pub use timeout::{Deadline, Monotonic, ReceiveError, TransmitError};
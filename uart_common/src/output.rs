@@ -0,0 +1,133 @@
+/*!
+# output
+
+Output translation for the `fmt::Write` impls of the uarts
+*/
+
+use bitflags::bitflags;
+use core::cell::Cell;
+
+bitflags! {
+    /// Output translation applied by `fmt::Write`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OutputFlags: u8 {
+        /// Translate LF into CR LF
+        const ONLCR = 1 << 0;
+        /// Drop CR
+        const STRIP_CR = 1 << 1;
+        /// Expand tabs with spaces up to the next multiple of 8 columns
+        const XTABS = 1 << 2;
+    }
+}
+
+/// Output translation state of a uart, kept by the driver next to its registers
+pub struct Output {
+    flags: Cell<OutputFlags>,
+    /// Column of the cursor, for tab expansion
+    column: Cell<usize>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Output {
+    /// No translation, cursor at column 0
+    pub const fn new() -> Self {
+        Self {
+            flags: Cell::new(OutputFlags::empty()),
+            column: Cell::new(0),
+        }
+    }
+
+    /// Get the translation applied
+    pub fn flags(&self) -> OutputFlags {
+        self.flags.get()
+    }
+
+    /// Set the translation applied from now on
+    pub fn set_flags(&self, flags: OutputFlags) {
+        self.flags.set(flags);
+    }
+
+    /// Translate `bytes` and hand them to `write` in chunks
+    pub fn write(&self, bytes: &[u8], mut write: impl FnMut(&[u8])) {
+        let flags = self.flags.get();
+        if flags.is_empty() {
+            return write(bytes);
+        }
+
+        let mut buf = [0; 32];
+        let mut len = 0;
+        let mut column = self.column.get();
+        for &byte in bytes {
+            // Room for the longest expansion, a tab
+            if len + 8 > buf.len() {
+                write(&buf[..len]);
+                len = 0;
+            }
+            match byte {
+                b'\n' => {
+                    if flags.contains(OutputFlags::ONLCR) {
+                        buf[len] = b'\r';
+                        len += 1;
+                    }
+                    column = 0;
+                }
+                b'\r' if flags.contains(OutputFlags::STRIP_CR) => continue,
+                b'\r' => column = 0,
+                b'\t' if flags.contains(OutputFlags::XTABS) => {
+                    let spaces = 8 - column % 8;
+                    buf[len..len + spaces].fill(b' ');
+                    len += spaces;
+                    column += spaces;
+                    continue;
+                }
+                0x08 => column = column.saturating_sub(1),
+                // UTF-8 continuation bytes don't take a column
+                0x80..=0xbf => {}
+                _ => column += 1,
+            }
+            buf[len] = byte;
+            len += 1;
+        }
+        self.column.set(column);
+        write(&buf[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn translate(output: &Output, s: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        output.write(s.as_bytes(), |bytes| out.extend_from_slice(bytes));
+        out
+    }
+
+    #[test]
+    fn translate_output() {
+        let output = Output::new();
+        assert_eq!(translate(&output, "a\r\n\tb\n"), b"a\r\n\tb\n");
+
+        output.set_flags(OutputFlags::ONLCR | OutputFlags::STRIP_CR);
+        assert_eq!(translate(&output, "a\r\nb\n"), b"a\r\nb\r\n");
+
+        output.set_flags(OutputFlags::XTABS);
+        assert_eq!(translate(&output, "ab\t"), b"ab      ");
+        assert_eq!(
+            translate(&output, "é\tc\r\t"),
+            b"\xc3\xa9       c\r        "
+        );
+
+        let long = "\t".repeat(10);
+        assert_eq!(translate(&output, &long), [b' '; 80]);
+    }
+}
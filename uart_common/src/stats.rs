@@ -0,0 +1,158 @@
+/*!
+# stats
+
+Traffic and line error counters of a uart
+*/
+
+use core::cell::Cell;
+use core::fmt::{self, Display, Formatter};
+
+/// Traffic and line error counters of a port
+///
+/// The counters mirror Linux's `struct uart_icount` and are displayed in the same way as a line
+/// of `/proc/tty/driver/serial`, e.g. `tx:1024 rx:87 fe:2 oe:1`. Uarts without line error flags
+/// only count traffic.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UartStats {
+    /// Bytes written to the transmitter
+    pub tx: u32,
+    /// Bytes read from the receiver
+    pub rx: u32,
+    /// Framing errors
+    pub frame: u32,
+    /// Parity errors
+    pub parity: u32,
+    /// Break conditions
+    pub brk: u32,
+    /// Overrun errors
+    pub overrun: u32,
+}
+
+impl UartStats {
+    /// All counters at 0
+    pub const fn new() -> Self {
+        Self {
+            tx: 0,
+            rx: 0,
+            frame: 0,
+            parity: 0,
+            brk: 0,
+            overrun: 0,
+        }
+    }
+}
+
+impl Display for UartStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "tx:{} rx:{}", self.tx, self.rx)?;
+        if self.frame != 0 {
+            write!(f, " fe:{}", self.frame)?;
+        }
+        if self.parity != 0 {
+            write!(f, " pe:{}", self.parity)?;
+        }
+        if self.brk != 0 {
+            write!(f, " brk:{}", self.brk)?;
+        }
+        if self.overrun != 0 {
+            write!(f, " oe:{}", self.overrun)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `UartStats` of a uart, counted through a shared reference
+///
+/// Drivers keep one next to their registers and count the bytes and line errors that go through
+/// them. The counters wrap around.
+#[derive(Debug, Default)]
+pub struct StatsCounter {
+    stats: Cell<UartStats>,
+}
+
+impl StatsCounter {
+    /// A counter with all counts at 0
+    pub const fn new() -> Self {
+        Self {
+            stats: Cell::new(UartStats::new()),
+        }
+    }
+
+    /// The counts so far
+    pub fn get(&self) -> UartStats {
+        self.stats.get()
+    }
+
+    /// Set all counts back to 0
+    pub fn reset(&self) {
+        self.stats.set(UartStats::new())
+    }
+
+    /// Count `n` bytes written to the transmitter
+    pub fn count_tx(&self, n: u32) {
+        self.update(|stats| stats.tx = stats.tx.wrapping_add(n));
+    }
+
+    /// Count `n` bytes read from the receiver
+    pub fn count_rx(&self, n: u32) {
+        self.update(|stats| stats.rx = stats.rx.wrapping_add(n));
+    }
+
+    /// Count the error flags of one line status read
+    ///
+    /// A break is also reported as a framing error, so `frame` and `parity` are not counted when
+    /// `brk` is set.
+    pub fn count_line_errors(&self, frame: bool, parity: bool, brk: bool, overrun: bool) {
+        self.update(|stats| {
+            if brk {
+                stats.brk = stats.brk.wrapping_add(1);
+            } else {
+                stats.frame = stats.frame.wrapping_add(frame as u32);
+                stats.parity = stats.parity.wrapping_add(parity as u32);
+            }
+            stats.overrun = stats.overrun.wrapping_add(overrun as u32);
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut UartStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn counter() {
+        let counter = StatsCounter::new();
+        counter.count_tx(3);
+        counter.count_rx(1);
+        counter.count_line_errors(true, true, true, false);
+        counter.count_line_errors(true, false, false, true);
+        assert_eq!(
+            counter.get(),
+            UartStats {
+                tx: 3,
+                rx: 1,
+                frame: 1,
+                brk: 1,
+                overrun: 1,
+                ..UartStats::new()
+            }
+        );
+        assert_eq!(
+            std::format!("{}", counter.get()),
+            "tx:3 rx:1 fe:1 brk:1 oe:1"
+        );
+
+        counter.count_tx(u32::MAX);
+        assert_eq!(counter.get().tx, 2);
+        counter.reset();
+        assert_eq!(counter.get(), UartStats::new());
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+# timeout
+
+Time sources and errors for the `*_timeout` methods of the uarts, and time sources for the
+protocols built on them
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+/// A monotonic clock used to bound blocking operations.
+pub trait Monotonic {
+    /// Returns the current time in microseconds. It must never go backwards, but may wrap.
+    fn now_us(&mut self) -> u64;
+}
+
+impl<M: Monotonic + ?Sized> Monotonic for &mut M {
+    fn now_us(&mut self) -> u64 {
+        (**self).now_us()
+    }
+}
+
+/// A [`Monotonic`] clock built on top of an `embedded_hal` delay.
+///
+/// Every call to [`now_us`](Monotonic::now_us) waits `step_us` microseconds and advances the
+/// clock by that amount. The time spent accessing registers between polls is not counted, so
+/// timeouts measured with it are slightly longer than requested.
+#[cfg(feature = "embedded")]
+pub struct DelayMonotonic<D> {
+    delay: D,
+    step_us: u32,
+    now_us: u64,
+}
+
+#[cfg(feature = "embedded")]
+impl<D: embedded_hal::blocking::delay::DelayUs<u32>> DelayMonotonic<D> {
+    /// Creates a clock that waits `step_us` microseconds per poll.
+    pub fn new(delay: D, step_us: u32) -> Self {
+        Self {
+            delay,
+            step_us,
+            now_us: 0,
+        }
+    }
+
+    /// Gives back the underlying delay.
+    pub fn free(self) -> D {
+        self.delay
+    }
+}
+
+#[cfg(feature = "embedded")]
+impl<D: embedded_hal::blocking::delay::DelayUs<u32>> Monotonic for DelayMonotonic<D> {
+    fn now_us(&mut self) -> u64 {
+        self.delay.delay_us(self.step_us);
+        self.now_us = self.now_us.wrapping_add(self.step_us as u64);
+        self.now_us
+    }
+}
+
+/// A point in time after which a blocking operation gives up.
+///
+/// It doesn't borrow the clock, so the clock can be used for other things in between.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Deadline {
+    start: u64,
+    timeout_us: u64,
+}
+
+impl Deadline {
+    /// Starts a deadline `timeout_us` microseconds from now on `clock`.
+    pub fn new<M: Monotonic + ?Sized>(clock: &mut M, timeout_us: u64) -> Self {
+        Self {
+            start: clock.now_us(),
+            timeout_us,
+        }
+    }
+
+    /// Returns whether the deadline passed on `clock`, which must be the one it was started on.
+    pub fn is_expired<M: Monotonic + ?Sized>(&self, clock: &mut M) -> bool {
+        clock.now_us().wrapping_sub(self.start) >= self.timeout_us
+    }
+}
+
+/// An error encountered while trying to transmit data.
+///
+/// More variants may be added, so matches need a wildcard arm.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TransmitError {
+    /// The transmit buffer is full, try again later. Only returned by uarts whose `write_byte`
+    /// doesn't wait for room.
+    BufferFull,
+    /// The transmitter did not make progress before the timeout expired.
+    Timeout,
+}
+
+impl Display for TransmitError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::BufferFull => "UART buffer full",
+            Self::Timeout => "UART transmit timed out",
+        })
+    }
+}
+
+/// An error encountered while trying to receive data.
+///
+/// More variants may be added, so matches need a wildcard arm.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReceiveError {
+    /// No data arrived before the timeout expired.
+    Timeout,
+}
+
+impl Display for ReceiveError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Timeout => "UART receive timed out",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClock(u64);
+
+    impl Monotonic for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(10);
+            self.0
+        }
+    }
+
+    #[test]
+    fn deadline() {
+        // Starts just before the clock wraps
+        let mut clock = FakeClock(u64::MAX - 14);
+        let deadline = Deadline::new(&mut clock, 25);
+        assert!(!deadline.is_expired(&mut clock));
+        assert!(!deadline.is_expired(&mut &mut clock));
+        assert!(deadline.is_expired(&mut clock));
+    }
+}
@@ -0,0 +1,493 @@
+/*!
+# burst
+
+Receive data in idle-terminated bursts: the bytes received between two silences of the line,
+with how many there were and which line errors they carried, handed to a callback once the line
+goes idle. Packet-oriented protocols can use the bursts as frames without a software timer.
+
+```ignore
+uart.set_fifo_trigger(FifoTrigger::Bytes8);
+let mut rx = BurstReceiver::new([0; 256], |burst: Burst| {
+    if burst.is_complete() {
+        queue.push(burst.data);
+    }
+});
+
+// In the interrupt handler
+if let Some(other) = rx.handle_uart8250(&uart) {
+    // TransmitterHoldingRegisterEmpty or ModemStatus
+}
+```
+
+A 16550 raises the character timeout interrupt (`InterruptType::Timeout`) when its receive FIFO
+holds data and nothing has been received or read for 4 character times. The handlers below only
+ever read one byte less than the trigger level on `ReceivedDataAvailable`, so the FIFO is normally
+not emptied by it and the burst ends on the timeout. If it does run dry, on a line status
+interrupt or with fewer bytes than that in the FIFO, no timeout will follow and the burst ends
+there. With a trigger level of 1 the FIFO is emptied on every interrupt and the timeout never
+fires, so a burst is whatever each interrupt drained.
+
+The SiFive UART has no idle detection. Its rx watermark interrupt is pending while the FIFO holds
+more than the watermark, so bursts are the bytes drained on each interrupt: a watermark of 0
+delivers data as soon as it arrives, a higher one fewer, larger chunks.
+
+## REF
+
+- [TL16C550C datasheet, 2.4 Receiver](https://www.ti.com/lit/ds/symlink/tl16c550c.pdf)
+- [SiFive FU540-C000 Manual, 13.7 Receive Control Register](https://sifive.cdn.prismic.io/sifive/d3ed5cd0-6e74-46b2-a12d-72b06706513e_fu540-c000-manual-v1p4.pdf)
+*/
+
+use bitflags::bitflags;
+
+#[cfg(feature = "uart8250")]
+use uart8250::{InterruptType, MmioUart8250};
+#[cfg(feature = "uart_sifive")]
+use uart_sifive::MmioUartSifive;
+#[cfg(feature = "uart_xilinx")]
+use uart_xilinx::{uart_16550::InterruptType as Axi16550Interrupt, MmioUartAxi16550};
+
+bitflags! {
+    /// Line errors seen in a burst, at the same positions as in the 16550 LSR
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LineErrors: u8 {
+        /// A byte was lost because the receive FIFO was full
+        const OVERRUN = 1 << 1;
+        /// A byte had the wrong parity
+        const PARITY = 1 << 2;
+        /// A byte had no valid stop bit
+        const FRAMING = 1 << 3;
+        /// The line was held low for longer than a character
+        const BREAK = 1 << 4;
+    }
+}
+
+/// Data received between two silences of the line
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Burst<'a> {
+    /// The bytes received, up to the size of the buffer
+    pub data: &'a [u8],
+    /// Number of bytes received, including those that did not fit in the buffer
+    pub len: usize,
+    /// Line errors seen while receiving
+    pub errors: LineErrors,
+}
+
+impl Burst<'_> {
+    /// Whether every byte fit in the buffer and none had a line error
+    pub fn is_complete(&self) -> bool {
+        self.len == self.data.len() && self.errors.is_empty()
+    }
+}
+
+/// A pending 16550 interrupt, by what the receiver does with it
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+    not(any(feature = "uart8250", feature = "uart_xilinx")),
+    allow(dead_code)
+)]
+enum Pending<T> {
+    /// Received data available or receiver line status: the FIFO reached the trigger level or
+    /// holds a byte with an error
+    Data,
+    /// Character timeout: the line went idle with data in the FIFO
+    Timeout,
+    /// Not about received data, left for the caller
+    Other(T),
+}
+
+/// Collect received bytes and hand them to `on_burst` when the line goes idle
+pub struct BurstReceiver<B, F> {
+    buf: B,
+    len: usize,
+    errors: LineErrors,
+    on_burst: F,
+}
+
+impl<B: AsMut<[u8]>, F: FnMut(Burst)> BurstReceiver<B, F> {
+    /// Create a receiver collecting bursts into `buf`
+    pub fn new(buf: B, on_burst: F) -> Self {
+        Self {
+            buf,
+            len: 0,
+            errors: LineErrors::empty(),
+            on_burst,
+        }
+    }
+
+    /// Return the buffer and the callback, dropping any partial burst
+    pub fn free(self) -> (B, F) {
+        (self.buf, self.on_burst)
+    }
+
+    /// Whether no byte or error has been received since the last burst
+    pub fn is_idle(&self) -> bool {
+        self.len == 0 && self.errors.is_empty()
+    }
+
+    /// Add a received byte and the errors it was received with to the current burst
+    pub fn push(&mut self, byte: u8, errors: LineErrors) {
+        if let Some(slot) = self.buf.as_mut().get_mut(self.len) {
+            *slot = byte;
+        }
+        self.len += 1;
+        self.errors |= errors;
+    }
+
+    /// End the current burst, calling `on_burst` unless nothing was received
+    pub fn end_burst(&mut self) {
+        if self.is_idle() {
+            return;
+        }
+        let buf = self.buf.as_mut();
+        let burst = Burst {
+            data: &buf[..self.len.min(buf.len())],
+            len: self.len,
+            errors: self.errors,
+        };
+        (self.on_burst)(burst);
+        self.len = 0;
+        self.errors = LineErrors::empty();
+    }
+
+    /// Push up to `max` bytes from `read`, return whether it ran dry
+    // Only the driver handlers below use it
+    #[cfg_attr(
+        not(any(feature = "uart8250", feature = "uart_xilinx", feature = "uart_sifive")),
+        allow(dead_code)
+    )]
+    fn drain(&mut self, max: usize, mut read: impl FnMut() -> Option<(u8, u8)>) -> bool {
+        for _ in 0..max {
+            match read() {
+                Some((byte, lsr)) => self.push(byte, LineErrors::from_bits_truncate(lsr)),
+                None => return true,
+            }
+        }
+        false
+    }
+
+    /// Service 16550 receive interrupts from `pending` until none is left
+    ///
+    /// Shared by the 16550 handlers below. Ends the burst when the FIFO runs dry, as a 16550
+    /// raises no character timeout for an empty FIFO.
+    #[cfg_attr(
+        not(any(feature = "uart8250", feature = "uart_xilinx")),
+        allow(dead_code)
+    )]
+    fn handle_16550<T>(
+        &mut self,
+        trigger: usize,
+        mut pending: impl FnMut() -> Option<Pending<T>>,
+        mut read: impl FnMut() -> Option<(u8, u8)>,
+    ) -> Option<T> {
+        loop {
+            match pending()? {
+                Pending::Data if trigger > 1 => {
+                    if self.drain(trigger - 1, &mut read) {
+                        self.end_burst();
+                    }
+                }
+                Pending::Data | Pending::Timeout => {
+                    self.drain(usize::MAX, &mut read);
+                    self.end_burst();
+                }
+                Pending::Other(other) => return Some(other),
+            }
+        }
+    }
+
+    /// Service the receive interrupts of a 16550 until none is pending
+    ///
+    /// The trigger level is taken from [`MmioUart8250::fifo_trigger`]. Returns the first pending
+    /// interrupt that is not about received data, which is left for the caller to handle.
+    #[cfg(feature = "uart8250")]
+    pub fn handle_uart8250(&mut self, uart: &MmioUart8250) -> Option<InterruptType> {
+        let trigger = uart.fifo_trigger().level(uart.fifo_depth());
+        self.handle_16550(
+            trigger,
+            || {
+                Some(match uart.read_interrupt_type()? {
+                    InterruptType::ReceivedDataAvailable | InterruptType::ReceiverLineStatus => {
+                        Pending::Data
+                    }
+                    InterruptType::Timeout => Pending::Timeout,
+                    other => Pending::Other(other),
+                })
+            },
+            || uart.read_byte_with_status().map(|(b, lsr)| (b, lsr.bits())),
+        )
+    }
+
+    /// Service the receive interrupts of an AXI UART 16550 until none is pending
+    ///
+    /// `rx_trigger` is the receive trigger level written to FCR, in bytes. Returns the first
+    /// pending interrupt that is not about received data, which is left for the caller to handle.
+    #[cfg(feature = "uart_xilinx")]
+    pub fn handle_axi16550(
+        &mut self,
+        uart: &MmioUartAxi16550,
+        rx_trigger: usize,
+    ) -> Option<Axi16550Interrupt> {
+        self.handle_16550(
+            rx_trigger,
+            || {
+                Some(match uart.read_interrupt_type()? {
+                    Axi16550Interrupt::ReceivedDataAvailable
+                    | Axi16550Interrupt::ReceiverLineStatus => Pending::Data,
+                    Axi16550Interrupt::Timeout => Pending::Timeout,
+                    other => Pending::Other(other),
+                })
+            },
+            || uart.read_byte_with_status().map(|(b, lsr)| (b, lsr.bits())),
+        )
+    }
+
+    /// Service the rx watermark interrupt of a SiFive UART
+    ///
+    /// Drains the FIFO and ends the burst if the watermark interrupt is pending, returns whether
+    /// it was. The UART reports no line errors.
+    #[cfg(feature = "uart_sifive")]
+    pub fn handle_sifive(&mut self, uart: &MmioUartSifive) -> bool {
+        if !uart.is_rx_watermark_pending() {
+            return false;
+        }
+        self.drain(usize::MAX, || uart.read_byte().map(|b| (b, 0)));
+        self.end_burst();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// A 16550 receive FIFO and the interrupts it raises
+    #[derive(Default)]
+    struct Fake16550 {
+        fifo: RefCell<VecDeque<(u8, u8)>>,
+        pending: RefCell<VecDeque<Pending<()>>>,
+    }
+
+    impl Fake16550 {
+        fn receive(&self, data: &[u8], errors: LineErrors) {
+            let mut fifo = self.fifo.borrow_mut();
+            fifo.extend(data.iter().map(|&b| (b, errors.bits())));
+        }
+
+        fn raise(&self, interrupt: Pending<()>) {
+            self.pending.borrow_mut().push_back(interrupt);
+        }
+
+        fn handle<B: AsMut<[u8]>, F: FnMut(Burst)>(
+            &self,
+            rx: &mut BurstReceiver<B, F>,
+            trigger: usize,
+        ) -> Option<()> {
+            rx.handle_16550(
+                trigger,
+                || self.pending.borrow_mut().pop_front(),
+                || self.fifo.borrow_mut().pop_front(),
+            )
+        }
+    }
+
+    #[test]
+    fn bursts() {
+        let mut bursts = Vec::new();
+        let mut rx = BurstReceiver::new([0; 4], |burst: Burst| {
+            bursts.push((
+                burst.data.to_vec(),
+                burst.len,
+                burst.errors,
+                burst.is_complete(),
+            ))
+        });
+
+        rx.end_burst();
+        assert!(rx.is_idle());
+        for &b in b"abc" {
+            rx.push(b, LineErrors::empty());
+        }
+        assert!(!rx.is_idle());
+        rx.end_burst();
+        for &b in b"hello" {
+            rx.push(b, LineErrors::empty());
+        }
+        rx.end_burst();
+        rx.push(0, LineErrors::BREAK | LineErrors::FRAMING);
+        rx.end_burst();
+
+        assert_eq!(
+            bursts,
+            [
+                (b"abc".to_vec(), 3, LineErrors::empty(), true),
+                (b"hell".to_vec(), 5, LineErrors::empty(), false),
+                (
+                    [0].to_vec(),
+                    1,
+                    LineErrors::BREAK | LineErrors::FRAMING,
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn drain() {
+        let mut count = 0;
+        let mut rx = BurstReceiver::new([0; 8], |burst: Burst| count += burst.len);
+        let mut fifo = b"abcdef".iter().map(|&b| (b, 0));
+
+        assert!(!rx.drain(3, || fifo.next()));
+        assert!(rx.drain(usize::MAX, || fifo.next()));
+        rx.end_burst();
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn timeout_ends_burst() {
+        let bursts = RefCell::new(Vec::new());
+        let mut rx = BurstReceiver::new([0; 16], |burst: Burst| {
+            bursts.borrow_mut().push(burst.data.to_vec())
+        });
+        let uart = Fake16550::default();
+
+        uart.receive(b"abcdefgh", LineErrors::empty());
+        uart.raise(Pending::Data);
+        assert_eq!(uart.handle(&mut rx, 8), None);
+        // One byte is left in the FIFO for the timeout
+        assert!(bursts.borrow().is_empty());
+        assert_eq!(uart.fifo.borrow().len(), 1);
+
+        uart.receive(b"ij", LineErrors::empty());
+        uart.raise(Pending::Timeout);
+        uart.raise(Pending::Other(()));
+        assert_eq!(uart.handle(&mut rx, 8), Some(()));
+        assert_eq!(*bursts.borrow(), [b"abcdefghij".to_vec()]);
+        assert!(uart.fifo.borrow().is_empty());
+    }
+
+    #[test]
+    fn line_status_ends_short_burst() {
+        let bursts = RefCell::new(Vec::new());
+        let mut rx = BurstReceiver::new([0; 16], |burst: Burst| {
+            bursts
+                .borrow_mut()
+                .push((burst.data.to_vec(), burst.errors))
+        });
+        let uart = Fake16550::default();
+
+        // A byte with a parity error raises the line status interrupt below the trigger level
+        uart.receive(b"ab", LineErrors::empty());
+        uart.receive(b"c", LineErrors::PARITY);
+        uart.raise(Pending::Data);
+        assert_eq!(uart.handle(&mut rx, 8), None);
+        // The FIFO ran dry, no timeout will follow
+        assert!(rx.is_idle());
+
+        uart.receive(b"de", LineErrors::empty());
+        uart.raise(Pending::Timeout);
+        assert_eq!(uart.handle(&mut rx, 8), None);
+        assert_eq!(
+            *bursts.borrow(),
+            [
+                (b"abc".to_vec(), LineErrors::PARITY),
+                (b"de".to_vec(), LineErrors::empty())
+            ]
+        );
+    }
+
+    #[test]
+    fn trigger_bytes1() {
+        let bursts = RefCell::new(Vec::new());
+        let mut rx = BurstReceiver::new([0; 16], |burst: Burst| {
+            bursts.borrow_mut().push(burst.data.to_vec())
+        });
+        let uart = Fake16550::default();
+
+        uart.receive(b"a", LineErrors::empty());
+        uart.raise(Pending::Data);
+        assert_eq!(uart.handle(&mut rx, 1), None);
+        uart.receive(b"bc", LineErrors::empty());
+        uart.raise(Pending::Data);
+        assert_eq!(uart.handle(&mut rx, 1), None);
+        assert_eq!(*bursts.borrow(), [b"a".to_vec(), b"bc".to_vec()]);
+    }
+
+    #[cfg(feature = "uart8250")]
+    #[test]
+    fn handle_uart8250() {
+        // IIR: transmitter holding register empty
+        let mut fake_registers: [u8; 8] = [0, 0, 0b0000_0010, 0, 0, 0, 0, 0];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+        let mut count = 0;
+        let mut rx = BurstReceiver::new([0; 4], |burst: Burst| count += burst.len);
+
+        rx.push(b'a', LineErrors::empty());
+        assert_eq!(
+            rx.handle_uart8250(&uart),
+            Some(InterruptType::TransmitterHoldingRegisterEmpty)
+        );
+        assert!(!rx.is_idle());
+
+        // No interrupt pending
+        unsafe {
+            (&mut fake_registers as *mut u8)
+                .add(2)
+                .write_volatile(0b0000_0001)
+        };
+        assert_eq!(rx.handle_uart8250(&uart), None);
+        assert!(!rx.is_idle());
+        rx.end_burst();
+        assert_eq!(count, 1);
+    }
+
+    #[cfg(feature = "uart_xilinx")]
+    #[test]
+    fn handle_axi16550() {
+        // IIR: modem status
+        let mut fake_registers: [u32; 8] = [0, 0, 0b0000_0000, 0, 0, 0, 0, 0];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+        let mut rx = BurstReceiver::new([0; 4], |_: Burst| {});
+
+        assert_eq!(
+            rx.handle_axi16550(&uart, 8),
+            Some(Axi16550Interrupt::ModemStatus)
+        );
+        unsafe {
+            (&mut fake_registers as *mut u32)
+                .add(2)
+                .write_volatile(0b0000_0001)
+        };
+        assert_eq!(rx.handle_axi16550(&uart, 8), None);
+    }
+
+    #[cfg(feature = "uart_sifive")]
+    #[test]
+    fn handle_sifive() {
+        // rxdata: empty, ip: none pending
+        let mut fake_registers: [u32; 7] = [0, 1 << 31, 0, 0, 0, 0, 0];
+        let uart = MmioUartSifive::new(&mut fake_registers as *mut u32 as usize);
+        let mut bursts = Vec::new();
+        let mut rx = BurstReceiver::new([0; 4], |burst: Burst| bursts.push(burst.data.to_vec()));
+
+        rx.push(b'a', LineErrors::empty());
+        assert!(!rx.handle_sifive(&uart));
+        assert!(!rx.is_idle());
+
+        // ip: rx watermark pending
+        unsafe {
+            (&mut fake_registers as *mut u32)
+                .add(5)
+                .write_volatile(0b10)
+        };
+        assert!(rx.handle_sifive(&uart));
+        assert!(rx.is_idle());
+        assert_eq!(bursts, [b"a".to_vec()]);
+    }
+}
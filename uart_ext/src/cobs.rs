@@ -0,0 +1,722 @@
+/*!
+# cobs
+
+COBS and rCOBS packet framing, and [`PacketUart`] sending and receiving whole frames over any
+[`Serial`], e.g. `MmioUart8250`, `MmioUartAxiLite` or `MmioUartSifive`.
+
+Both encodings remove every zero byte from a frame, so a single zero delimits frames on the line.
+COBS puts the length of each run before it and is decoded as bytes arrive, with [`CobsDecoder`].
+rCOBS, reversed COBS, puts it after, so it is encoded as bytes are sent, with [`RcobsEncoder`],
+but is decoded once the whole frame is in.
+
+```ignore
+let mut port = PacketUart::new(uart, [0; 256]).with_crc();
+port.send(&telemetry);
+match port.poll() {
+    Some(Ok(frame)) => handle(frame),
+    Some(Err(_)) => dropped += 1,
+    None => {}
+}
+```
+
+## REF
+
+- [Consistent Overhead Byte Stuffing](http://www.stuartcheshire.org/papers/COBSforToN.pdf)
+- [rCOBS](https://github.com/Dirbaio/rcobs)
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::crc::crc16_xmodem;
+use crate::serial::Serial;
+
+/// Frame delimiter
+pub const DELIMITER: u8 = 0;
+
+/// A frame that could not be decoded
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CobsError {
+    /// The frame didn't fit in the buffer; `len` is its length, decoded for COBS and encoded for
+    /// rCOBS
+    Oversize { len: usize },
+    /// The frame ended in the middle of a run, or was abandoned with `abort`
+    Truncated,
+    /// The frame is not valid COBS or rCOBS
+    Malformed,
+}
+
+impl Display for CobsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Oversize { len } => write!(f, "frame of {} bytes too large", len),
+            Self::Truncated => f.write_str("frame truncated"),
+            Self::Malformed => f.write_str("malformed frame"),
+        }
+    }
+}
+
+/// Upper bound of the encoded length of `len` bytes, delimiter excluded, for COBS and rCOBS
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// COBS encode `len` bytes given by `get`, passing each encoded byte to `emit`
+fn encode_with(len: usize, get: impl Fn(usize) -> u8, mut emit: impl FnMut(u8)) {
+    let mut start = 0;
+    loop {
+        let mut end = start;
+        while end < len && end - start < 254 && get(end) != 0 {
+            end += 1;
+        }
+        emit((end - start + 1) as u8);
+        (start..end).for_each(|i| emit(get(i)));
+        if end == len {
+            return;
+        }
+        // A full run isn't followed by a zero
+        start = if end - start == 254 { end } else { end + 1 };
+    }
+}
+
+/// COBS encode `data` into `out`, delimiter excluded, returning the encoded length
+///
+/// Returns `None` when `out` is too short.
+pub fn encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut full = false;
+    encode_with(
+        data.len(),
+        |i| data[i],
+        |byte| match out.get_mut(len) {
+            Some(slot) => {
+                *slot = byte;
+                len += 1;
+            }
+            None => full = true,
+        },
+    );
+    (!full).then_some(len)
+}
+
+/// Decode the COBS frame in `buf`, delimiter excluded, returning the decoded length
+///
+/// The decoded frame is written at the start of `buf`.
+pub fn decode_in_place(buf: &mut [u8]) -> Result<usize, CobsError> {
+    let mut decoder = CobsDecoder::new(&mut *buf);
+    for i in 0..decoder.buf.len() {
+        // Decoding never writes past the byte being read
+        let byte = decoder.buf[i];
+        if byte == DELIMITER {
+            return Err(CobsError::Malformed);
+        }
+        decoder.push_len(byte);
+    }
+    match decoder.push_len(DELIMITER) {
+        Some(result) => result,
+        None => Err(CobsError::Truncated),
+    }
+}
+
+/// # Incremental COBS decoder
+///
+/// Fed one byte at a time, it decodes straight into `B`, a `[u8; N]` or a `&mut [u8]`, whose
+/// length is the largest frame accepted. Lone delimiters are skipped.
+pub struct CobsDecoder<B> {
+    buf: B,
+    len: usize,
+    /// Bytes left in the current run
+    remaining: u8,
+    /// Code of the previous run, 0 at the start of a frame
+    code: u8,
+    /// An error seen in the current frame, reported at its delimiter
+    error: Option<CobsError>,
+}
+
+impl<B: AsMut<[u8]>> CobsDecoder<B> {
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            remaining: 0,
+            code: 0,
+            error: None,
+        }
+    }
+
+    /// Give back the buffer
+    pub fn free(self) -> B {
+        self.buf
+    }
+
+    /// Whether a frame is partially decoded
+    pub fn is_idle(&self) -> bool {
+        self.code == 0
+    }
+
+    /// Drop the frame being decoded
+    ///
+    /// Returns `Err(CobsError::Truncated)` if there was one.
+    pub fn abort(&mut self) -> Result<(), CobsError> {
+        let idle = self.is_idle();
+        self.reset();
+        if idle {
+            Ok(())
+        } else {
+            Err(CobsError::Truncated)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.remaining = 0;
+        self.code = 0;
+        self.error = None;
+    }
+
+    /// Feed one byte, returning a frame or an error at a delimiter
+    ///
+    /// The frame stays valid until the next call.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], CobsError>> {
+        match self.push_len(byte)? {
+            Ok(len) => Some(Ok(&self.buf.as_mut()[..len])),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// `push`, returning the length of the frame rather than borrowing it
+    fn push_len(&mut self, byte: u8) -> Option<Result<usize, CobsError>> {
+        if byte == DELIMITER {
+            if self.is_idle() {
+                return None;
+            }
+            let result = match self.error {
+                Some(CobsError::Oversize { .. }) => Err(CobsError::Oversize { len: self.len }),
+                Some(error) => Err(error),
+                None if self.remaining != 0 => Err(CobsError::Truncated),
+                None => Ok(self.len),
+            };
+            self.reset();
+            return Some(result);
+        }
+
+        if self.remaining == 0 {
+            if self.code != 0 && self.code != 0xff {
+                self.store(0);
+            }
+            self.code = byte;
+            self.remaining = byte - 1;
+        } else {
+            self.store(byte);
+            self.remaining -= 1;
+        }
+        None
+    }
+
+    fn store(&mut self, byte: u8) {
+        // Keep counting an oversized frame, so its length can be reported
+        match self.buf.as_mut().get_mut(self.len) {
+            Some(slot) if self.error.is_none() => *slot = byte,
+            Some(_) => {}
+            None => {
+                self.error.get_or_insert(CobsError::Oversize { len: 0 });
+            }
+        }
+        self.len += 1;
+    }
+}
+
+/// # Streaming rCOBS encoder
+///
+/// Each byte pushed is encoded right away, without looking ahead.
+#[derive(Debug, Default)]
+pub struct RcobsEncoder {
+    run: u8,
+}
+
+impl RcobsEncoder {
+    pub const fn new() -> Self {
+        Self { run: 0 }
+    }
+
+    /// Encode `byte`, passing the one or two encoded bytes to `emit`
+    pub fn push(&mut self, byte: u8, mut emit: impl FnMut(u8)) {
+        if byte == 0 {
+            emit(self.run + 1);
+            self.run = 0;
+            return;
+        }
+        emit(byte);
+        self.run += 1;
+        if self.run == 254 {
+            emit(0xff);
+            self.run = 0;
+        }
+    }
+
+    /// End the frame, delimiter excluded, and get ready for the next one
+    pub fn finish(&mut self, mut emit: impl FnMut(u8)) {
+        emit(self.run + 1);
+        self.run = 0;
+    }
+}
+
+/// rCOBS encode `data` into `out`, delimiter excluded, returning the encoded length
+///
+/// Returns `None` when `out` is too short.
+pub fn rcobs_encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut encoder = RcobsEncoder::new();
+    let mut len = 0;
+    let mut full = false;
+    let mut emit = |byte| match out.get_mut(len) {
+        Some(slot) => {
+            *slot = byte;
+            len += 1;
+        }
+        None => full = true,
+    };
+    for byte in data {
+        encoder.push(*byte, &mut emit);
+    }
+    encoder.finish(&mut emit);
+    (!full).then_some(len)
+}
+
+/// Decode the rCOBS frame in `buf`, delimiter excluded, returning the decoded length
+///
+/// The decoded frame is written at the start of `buf`.
+pub fn rcobs_decode_in_place(buf: &mut [u8]) -> Result<usize, CobsError> {
+    // Runs are decoded from the end, to the end of `buf`, which never overtakes the reads
+    let mut read = buf.len();
+    let mut write = buf.len();
+    let mut last = true;
+    while read > 0 {
+        read -= 1;
+        let code = buf[read];
+        if code == 0 || code as usize - 1 > read {
+            return Err(CobsError::Malformed);
+        }
+        let run = code as usize - 1;
+        // A run ended by a zero rather than by its length is followed by it
+        if !last && code != 0xff {
+            write -= 1;
+            buf[write] = 0;
+        }
+        buf.copy_within(read - run..read, write - run);
+        read -= run;
+        write -= run;
+        last = false;
+    }
+    if last {
+        return Err(CobsError::Truncated);
+    }
+    buf.copy_within(write.., 0);
+    Ok(buf.len() - write)
+}
+
+/// # Incremental rCOBS receiver
+///
+/// Collects the encoded frame in `B` and decodes it in place at the delimiter. The length of `B`
+/// bounds the encoded frame.
+pub struct RcobsDecoder<B> {
+    buf: B,
+    len: usize,
+}
+
+impl<B: AsMut<[u8]>> RcobsDecoder<B> {
+    pub fn new(buf: B) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Give back the buffer
+    pub fn free(self) -> B {
+        self.buf
+    }
+
+    /// Whether a frame is partially received
+    pub fn is_idle(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drop the frame being received
+    ///
+    /// Returns `Err(CobsError::Truncated)` if there was one.
+    pub fn abort(&mut self) -> Result<(), CobsError> {
+        match core::mem::take(&mut self.len) {
+            0 => Ok(()),
+            _ => Err(CobsError::Truncated),
+        }
+    }
+
+    /// Feed one byte, returning a frame or an error at a delimiter
+    ///
+    /// The frame stays valid until the next call.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], CobsError>> {
+        match self.push_len(byte)? {
+            Ok(len) => Some(Ok(&self.buf.as_mut()[..len])),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    fn push_len(&mut self, byte: u8) -> Option<Result<usize, CobsError>> {
+        if byte != DELIMITER {
+            if let Some(slot) = self.buf.as_mut().get_mut(self.len) {
+                *slot = byte;
+            }
+            self.len += 1;
+            return None;
+        }
+        match core::mem::take(&mut self.len) {
+            0 => None,
+            len if len > self.buf.as_mut().len() => Some(Err(CobsError::Oversize { len })),
+            len => Some(rcobs_decode_in_place(&mut self.buf.as_mut()[..len])),
+        }
+    }
+}
+
+/// Encoding used by a [`PacketUart`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Framing {
+    Cobs,
+    Rcobs,
+}
+
+/// A frame [`PacketUart`] could not receive
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PacketError {
+    /// The frame could not be decoded
+    Framing(CobsError),
+    /// The CRC-16 trailer doesn't match, or the frame is too short to hold one
+    BadCrc,
+}
+
+impl From<CobsError> for PacketError {
+    fn from(error: CobsError) -> Self {
+        Self::Framing(error)
+    }
+}
+
+impl Display for PacketError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Framing(error) => error.fmt(f),
+            Self::BadCrc => f.write_str("bad CRC"),
+        }
+    }
+}
+
+enum Decoder<B> {
+    Cobs(CobsDecoder<B>),
+    Rcobs(RcobsDecoder<B>),
+}
+
+impl<B: AsMut<[u8]>> Decoder<B> {
+    fn new(framing: Framing, buf: B) -> Self {
+        match framing {
+            Framing::Cobs => Self::Cobs(CobsDecoder::new(buf)),
+            Framing::Rcobs => Self::Rcobs(RcobsDecoder::new(buf)),
+        }
+    }
+
+    fn free(self) -> B {
+        match self {
+            Self::Cobs(decoder) => decoder.free(),
+            Self::Rcobs(decoder) => decoder.free(),
+        }
+    }
+
+    fn push_len(&mut self, byte: u8) -> Option<Result<usize, CobsError>> {
+        match self {
+            Self::Cobs(decoder) => decoder.push_len(byte),
+            Self::Rcobs(decoder) => decoder.push_len(byte),
+        }
+    }
+
+    fn buf(&mut self) -> &mut [u8] {
+        match self {
+            Self::Cobs(decoder) => decoder.buf.as_mut(),
+            Self::Rcobs(decoder) => decoder.buf.as_mut(),
+        }
+    }
+}
+
+/// # Whole frames over a [`Serial`]
+///
+/// Frames are COBS encoded by default, and end with a delimiter. With a CRC, each frame carries
+/// its CRC-16/XMODEM as a big endian trailer, which is checked and stripped on receive.
+///
+/// Received frames are decoded into `B`, a `[u8; N]` or a `&mut [u8]`, which must have room for
+/// the trailer, and also for the encoding overhead with rCOBS.
+pub struct PacketUart<S, B> {
+    serial: S,
+    decoder: Decoder<B>,
+    crc: bool,
+}
+
+impl<S: Serial, B: AsMut<[u8]>> PacketUart<S, B> {
+    /// COBS frames without CRC
+    pub fn new(serial: S, buf: B) -> Self {
+        Self {
+            serial,
+            decoder: Decoder::new(Framing::Cobs, buf),
+            crc: false,
+        }
+    }
+
+    /// Append a CRC-16 to the frames sent, and check it on the frames received
+    pub fn with_crc(mut self) -> Self {
+        self.crc = true;
+        self
+    }
+
+    /// Switch encoding, dropping any frame partially received
+    pub fn with_framing(self, framing: Framing) -> Self {
+        Self {
+            serial: self.serial,
+            decoder: Decoder::new(framing, self.decoder.free()),
+            crc: self.crc,
+        }
+    }
+
+    pub fn framing(&self) -> Framing {
+        match self.decoder {
+            Decoder::Cobs(_) => Framing::Cobs,
+            Decoder::Rcobs(_) => Framing::Rcobs,
+        }
+    }
+
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Give back the serial and the buffer
+    pub fn free(self) -> (S, B) {
+        (self.serial, self.decoder.free())
+    }
+
+    /// Send `frame` followed by a delimiter, waiting for room in the transmitter
+    pub fn send(&mut self, frame: &[u8]) {
+        let crc = crc16_xmodem(0, frame).to_be_bytes();
+        let trailer: &[u8] = if self.crc { &crc } else { &[] };
+        let serial = &mut self.serial;
+        match self.decoder {
+            Decoder::Cobs(_) => encode_with(
+                frame.len() + trailer.len(),
+                |i| match frame.get(i) {
+                    Some(byte) => *byte,
+                    None => trailer[i - frame.len()],
+                },
+                |byte| serial.write_byte_blocking(byte),
+            ),
+            Decoder::Rcobs(_) => {
+                let mut encoder = RcobsEncoder::new();
+                for byte in frame.iter().chain(trailer) {
+                    encoder.push(*byte, |byte| serial.write_byte_blocking(byte));
+                }
+                encoder.finish(|byte| serial.write_byte_blocking(byte));
+            }
+        }
+        serial.write_byte_blocking(DELIMITER);
+    }
+
+    fn poll_len(&mut self) -> Option<Result<usize, PacketError>> {
+        while let Some(byte) = self.serial.read_byte() {
+            let len = match self.decoder.push_len(byte) {
+                Some(Ok(len)) => len,
+                Some(Err(error)) => return Some(Err(error.into())),
+                None => continue,
+            };
+            if !self.crc {
+                return Some(Ok(len));
+            }
+            let buf = self.decoder.buf();
+            return Some(match len.checked_sub(2) {
+                Some(len) if crc16_xmodem(0, &buf[..len + 2]) == 0 => Ok(len),
+                _ => Err(PacketError::BadCrc),
+            });
+        }
+        None
+    }
+
+    /// Decode the bytes available, returning as soon as a frame or an error ends
+    ///
+    /// The frame, without its CRC, stays valid until the next call.
+    pub fn poll(&mut self) -> Option<Result<&[u8], PacketError>> {
+        let result = self.poll_len()?;
+        Some(result.map(|len| &self.decoder.buf()[..len]))
+    }
+
+    /// Wait for the next frame or error, see `poll`
+    pub fn receive(&mut self) -> Result<&[u8], PacketError> {
+        loop {
+            if let Some(result) = self.poll_len() {
+                return result.map(|len| &self.decoder.buf()[..len]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::FakeSerial;
+    use std::vec::Vec;
+
+    /// From the Wikipedia article on COBS
+    fn vectors() -> Vec<(Vec<u8>, Vec<u8>)> {
+        let run = |range: core::ops::RangeInclusive<u8>| range.collect::<Vec<_>>();
+        let cat = |parts: &[&[u8]]| parts.concat();
+        std::vec![
+            (std::vec![0x00], std::vec![0x01, 0x01]),
+            (std::vec![0x00, 0x00], std::vec![0x01, 0x01, 0x01]),
+            (
+                std::vec![0x00, 0x11, 0x00],
+                std::vec![0x01, 0x02, 0x11, 0x01]
+            ),
+            (
+                std::vec![0x11, 0x22, 0x00, 0x33],
+                std::vec![0x03, 0x11, 0x22, 0x02, 0x33]
+            ),
+            (
+                std::vec![0x11, 0x22, 0x33, 0x44],
+                std::vec![0x05, 0x11, 0x22, 0x33, 0x44]
+            ),
+            (
+                std::vec![0x11, 0x00, 0x00, 0x00],
+                std::vec![0x02, 0x11, 0x01, 0x01, 0x01]
+            ),
+            (run(0x01..=0xfe), cat(&[&[0xff], &run(0x01..=0xfe)])),
+            (
+                cat(&[&[0x00], &run(0x01..=0xfe)]),
+                cat(&[&[0x01, 0xff], &run(0x01..=0xfe)])
+            ),
+            (
+                run(0x01..=0xff),
+                cat(&[&[0xff], &run(0x01..=0xfe), &[0x02, 0xff]])
+            ),
+            (
+                cat(&[&run(0x02..=0xff), &[0x00]]),
+                cat(&[&[0xff], &run(0x02..=0xff), &[0x01, 0x01]])
+            ),
+            (
+                cat(&[&run(0x03..=0xff), &[0x00, 0x01]]),
+                cat(&[&[0xfe], &run(0x03..=0xff), &[0x02, 0x01]]),
+            ),
+        ]
+    }
+
+    #[test]
+    fn cobs() {
+        let mut decoder = CobsDecoder::new([0; 256]);
+        for (data, encoded) in vectors() {
+            let mut out = [0; 260];
+            assert_eq!(encode(&data, &mut out), Some(encoded.len()));
+            assert_eq!(out[..encoded.len()], encoded);
+            assert!(encoded.len() <= max_encoded_len(data.len()));
+            assert_eq!(encode(&data, &mut out[..encoded.len() - 1]), None);
+
+            let len = decode_in_place(&mut out[..encoded.len()]);
+            assert_eq!(len, Ok(data.len()));
+            assert_eq!(out[..data.len()], data);
+
+            for byte in &encoded {
+                assert_eq!(decoder.push(*byte), None);
+            }
+            assert_eq!(decoder.push(DELIMITER), Some(Ok(&data[..])));
+        }
+    }
+
+    #[test]
+    fn cobs_errors() {
+        let mut decoder = CobsDecoder::new([0; 4]);
+        let mut feed = |bytes: &[u8]| {
+            let mut last = None;
+            for byte in bytes {
+                last = decoder
+                    .push(*byte)
+                    .map(|frame| frame.map(|frame| frame.len()));
+            }
+            last
+        };
+
+        assert_eq!(
+            feed(&[0x07, 1, 2, 3, 4, 5, 6, 0]),
+            Some(Err(CobsError::Oversize { len: 6 }))
+        );
+        assert_eq!(feed(&[0x03, 1, 2, 0x02, 3, 0]), Some(Ok(4)));
+        assert_eq!(feed(&[0x04, 1, 0]), Some(Err(CobsError::Truncated)));
+        assert_eq!(feed(&[0, 0]), None);
+        assert_eq!(feed(&[0x02, 1]), None);
+        assert_eq!(decoder.abort(), Err(CobsError::Truncated));
+        assert_eq!(decoder.abort(), Ok(()));
+
+        assert_eq!(
+            decode_in_place(&mut [0x03, 1, 0]),
+            Err(CobsError::Malformed)
+        );
+        assert_eq!(decode_in_place(&mut [0x03, 1]), Err(CobsError::Truncated));
+    }
+
+    #[test]
+    fn rcobs() {
+        let mut decoder = RcobsDecoder::new([0; 260]);
+        for (data, _) in vectors() {
+            let mut out = [0; 260];
+            let len = rcobs_encode(&data, &mut out).unwrap();
+            assert!(len <= max_encoded_len(data.len()));
+
+            // rCOBS is COBS of the reversed frame, reversed, as long as no run is full
+            if data.len() < 254 {
+                let reversed: Vec<u8> = data.iter().rev().copied().collect();
+                let mut cobs = [0; 260];
+                let cobs_len = encode(&reversed, &mut cobs).unwrap();
+                cobs[..cobs_len].reverse();
+                assert_eq!(out[..len], cobs[..cobs_len]);
+            }
+
+            for byte in &out[..len] {
+                assert_eq!(decoder.push(*byte), None);
+            }
+            assert_eq!(decoder.push(DELIMITER), Some(Ok(&data[..])));
+        }
+
+        assert_eq!(
+            rcobs_decode_in_place(&mut [1, 2, 0x05]),
+            Err(CobsError::Malformed)
+        );
+        assert_eq!(rcobs_decode_in_place(&mut []), Err(CobsError::Truncated));
+        let mut decoder = RcobsDecoder::new([0; 2]);
+        for byte in [1, 2, 3] {
+            decoder.push(byte);
+        }
+        assert_eq!(
+            decoder.push(DELIMITER),
+            Some(Err(CobsError::Oversize { len: 3 }))
+        );
+    }
+
+    #[test]
+    fn packet_uart() {
+        for framing in [Framing::Cobs, Framing::Rcobs] {
+            let mut port = PacketUart::new(FakeSerial::default(), [0; 16])
+                .with_crc()
+                .with_framing(framing);
+            port.send(&[0x00, 0x01, 0x00]);
+            port.send(b"telemetry");
+            port.send(b"noise");
+            let serial = port.serial_mut();
+            let mut wire = core::mem::take(&mut serial.output);
+            assert_eq!(wire.iter().filter(|byte| **byte == 0).count(), 3);
+            // Corrupt the last frame
+            let last = wire.len() - 3;
+            wire[last] ^= 0x10;
+            serial.input.extend(wire);
+
+            assert_eq!(port.receive(), Ok(&[0x00, 0x01, 0x00][..]));
+            assert_eq!(port.poll(), Some(Ok(&b"telemetry"[..])));
+            assert_eq!(port.poll(), Some(Err(PacketError::BadCrc)));
+            assert_eq!(port.poll(), None);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*!
+# crc
+
+Bitwise CRCs used by the protocols in this crate, small rather than fast
+*/
+
+/// CRC-16/XMODEM: polynomial 0x1021, not reflected, initial value 0
+pub(crate) fn crc16_xmodem(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-16/X-25 register update, the FCS-16 of RFC 1662: polynomial 0x1021 reflected
+///
+/// Start from `!0` and invert the result to get the FCS.
+pub(crate) fn crc16_x25_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-16/MODBUS: polynomial 0x8005 reflected, initial value 0xFFFF
+///
+/// Sent low byte first, after which the CRC of the whole frame is 0.
+pub(crate) fn crc16_modbus(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-32/ISO-HDLC register update: polynomial 0x04c11db7 reflected
+///
+/// Start from `!0` and invert the result to get the usual CRC-32.
+pub(crate) fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        // The check values from the CRC catalogue
+        assert_eq!(crc16_xmodem(0, b"123456789"), 0x31c3);
+        assert_eq!(!crc16_x25_update(!0, b"123456789"), 0x906e);
+        assert_eq!(crc16_modbus(0xffff, b"123456789"), 0x4b37);
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,482 @@
+/*!
+# dma
+
+A transmit queue handing whole buffers to a DMA channel wired to the TXRDY line of a 16550, so
+the CPU doesn't copy them into the FIFO. Each buffer is given back to a completion callback once
+sent. Without a DMA channel the queue falls back to PIO driven by the THRE interrupt.
+
+```ignore
+static GREETING: &[u8] = b"hello\r\n";
+
+let mut tx = TxQueue::<_, _, _, _, 4>::with_dma(uart, channel, |buf: &'static [u8]| {
+    // buf has been sent
+});
+tx.enqueue(GREETING).unwrap();
+
+// In the DMA completion (or, with PIO, the uart) interrupt handler
+tx.poll();
+```
+
+Buffers are moved into the queue and must implement [`StableBuffer`]: the channel reads them
+after `enqueue` returns, so their bytes must not move with them. `&'static [u8]` and
+`&'static mut [u8]`, e.g. taken from a static pool, do, and so do `Box<[u8]>` and `Vec<u8>` with
+the `alloc` feature. A handle to a pool slot that gives the slot back when dropped can implement
+it too, as long as the slot is not reused before the handle is dropped. A transfer in progress
+is waited for before its buffer is dropped, by [`TxQueue::free`] or when the queue is dropped.
+
+With DMA the FIFOs are switched to DMA mode 1, so TXRDY requests data as long as the transmit
+FIFO has room rather than only when it is empty.
+*/
+
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::ptr;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec::Vec};
+
+#[cfg(feature = "uart8250")]
+use uart8250::MmioUart8250;
+#[cfg(feature = "uart_xilinx")]
+use uart_xilinx::MmioUartAxi16550;
+
+/// A DMA channel moving bytes from memory to the transmit holding register, paced by TXRDY
+pub trait DmaChannel {
+    /// Start transferring `data` to the uart
+    ///
+    /// # Safety
+    ///
+    /// `data` must stay valid and in place until [`is_done`](Self::is_done) returns true.
+    unsafe fn start(&mut self, data: &[u8]);
+
+    /// Whether the transfer last started is complete
+    fn is_done(&mut self) -> bool;
+}
+
+/// A buffer whose bytes stay valid and in place while it is alive, even when it is moved
+///
+/// # Safety
+///
+/// The slice returned by `deref` must be the same every time, and must not be moved, freed or
+/// written to through another path until the buffer is dropped.
+pub unsafe trait StableBuffer: Deref<Target = [u8]> {}
+
+unsafe impl StableBuffer for &'static [u8] {}
+
+unsafe impl StableBuffer for &'static mut [u8] {}
+
+#[cfg(feature = "alloc")]
+unsafe impl StableBuffer for Box<[u8]> {}
+
+// The queue owns the vector, so it can't grow and reallocate while queued
+#[cfg(feature = "alloc")]
+unsafe impl StableBuffer for Vec<u8> {}
+
+/// No DMA channel, for queues using PIO
+pub enum NoDma {}
+
+impl DmaChannel for NoDma {
+    unsafe fn start(&mut self, _: &[u8]) {
+        match *self {}
+    }
+
+    fn is_done(&mut self) -> bool {
+        match *self {}
+    }
+}
+
+/// The transmit side of a 16550 as used by [`TxQueue`]
+pub trait DmaUart {
+    /// Switch the FIFOs to DMA mode 1
+    fn enable_tx_dma(&self);
+
+    /// Write as much of `bytes` as the transmitter takes without waiting, return how many
+    fn write_pio(&self, bytes: &[u8]) -> usize;
+
+    /// Enable or disable the THRE interrupt
+    fn set_tx_interrupt(&self, enabled: bool);
+}
+
+#[cfg(feature = "uart8250")]
+impl DmaUart for MmioUart8250<'_> {
+    fn enable_tx_dma(&self) {
+        self.set_dma_mode(true)
+    }
+
+    fn write_pio(&self, bytes: &[u8]) -> usize {
+        self.write(bytes)
+    }
+
+    fn set_tx_interrupt(&self, enabled: bool) {
+        if enabled {
+            self.enable_transmitter_holding_register_empty_interrupt()
+        } else {
+            self.disable_transmitter_holding_register_empty_interrupt()
+        }
+    }
+}
+
+#[cfg(feature = "uart_xilinx")]
+impl DmaUart for MmioUartAxi16550<'_> {
+    fn enable_tx_dma(&self) {
+        self.set_dma_mode(true)
+    }
+
+    /// The AXI UART 16550 has a 16 byte FIFO, which is empty when THRE is set
+    fn write_pio(&self, bytes: &[u8]) -> usize {
+        if !self.is_transmitter_holding_register_empty() {
+            return 0;
+        }
+        let count = bytes.len().min(if self.is_fifo_enabled() { 16 } else { 1 });
+        for byte in &bytes[..count] {
+            self.write_thr(*byte as u32);
+        }
+        count
+    }
+
+    fn set_tx_interrupt(&self, enabled: bool) {
+        if enabled {
+            self.enable_transmitter_holding_register_empty_interrupt()
+        } else {
+            self.disable_transmitter_holding_register_empty_interrupt()
+        }
+    }
+}
+
+/// A queue of up to `N` buffers sent one after the other, by DMA or PIO, `N` must not be 0
+pub struct TxQueue<U, D, B, F, const N: usize>
+where
+    D: DmaChannel,
+    B: StableBuffer,
+    F: FnMut(B),
+{
+    uart: U,
+    dma: Option<D>,
+    queue: [Option<B>; N],
+    head: usize,
+    len: usize,
+    /// Bytes of the head buffer written by PIO
+    sent: usize,
+    /// The head buffer has been handed to the DMA channel
+    started: bool,
+    on_complete: F,
+}
+
+impl<U, B, F, const N: usize> TxQueue<U, NoDma, B, F, N>
+where
+    U: DmaUart,
+    B: StableBuffer,
+    F: FnMut(B),
+{
+    /// Create a queue writing to `uart` by PIO, calling `on_complete` with every buffer sent
+    pub fn pio(uart: U, on_complete: F) -> Self {
+        Self::new(uart, None, on_complete)
+    }
+}
+
+impl<U, D, B, F, const N: usize> TxQueue<U, D, B, F, N>
+where
+    U: DmaUart,
+    D: DmaChannel,
+    B: StableBuffer,
+    F: FnMut(B),
+{
+    /// Create a queue sending through `dma`, calling `on_complete` with every buffer sent
+    ///
+    /// Switches the FIFOs of `uart` to DMA mode 1.
+    pub fn with_dma(uart: U, dma: D, on_complete: F) -> Self {
+        uart.enable_tx_dma();
+        Self::new(uart, Some(dma), on_complete)
+    }
+
+    /// Fails to build for `N == 0`, which `enqueue` and `poll` would index out of bounds
+    const NONEMPTY: () = assert!(N > 0, "a TxQueue needs room for at least one buffer");
+
+    fn new(uart: U, dma: Option<D>, on_complete: F) -> Self {
+        let () = Self::NONEMPTY;
+        Self {
+            uart,
+            dma,
+            queue: core::array::from_fn(|_| None),
+            head: 0,
+            len: 0,
+            sent: 0,
+            started: false,
+            on_complete,
+        }
+    }
+
+    /// Get the uart
+    pub fn uart(&self) -> &U {
+        &self.uart
+    }
+
+    /// Whether a DMA channel is used
+    pub fn is_dma(&self) -> bool {
+        self.dma.is_some()
+    }
+
+    /// Number of buffers queued, including the one being sent
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether every buffer has been sent
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Queue `buf` to be sent after the buffers already queued, `Err(buf)` if the queue is full
+    pub fn enqueue(&mut self, buf: B) -> Result<(), B> {
+        if self.len == N {
+            return Err(buf);
+        }
+        self.queue[(self.head + self.len) % N] = Some(buf);
+        self.len += 1;
+        self.poll();
+        Ok(())
+    }
+
+    /// Make progress: complete finished buffers and start the next, or write by PIO
+    ///
+    /// Call from the DMA completion interrupt, or the THRE interrupt of the uart with PIO.
+    pub fn poll(&mut self) {
+        while let Some(buf) = &self.queue[self.head] {
+            let done = match &mut self.dma {
+                Some(dma) => {
+                    if !self.started {
+                        // Safety: the buffer stays in the queue until the channel is done
+                        unsafe { dma.start(buf) };
+                        self.started = true;
+                    }
+                    dma.is_done()
+                }
+                None => {
+                    self.sent += self.uart.write_pio(&buf[self.sent..]);
+                    self.sent == buf.len()
+                }
+            };
+            if !done {
+                break;
+            }
+            self.complete();
+        }
+        if self.dma.is_none() {
+            // Wait for THRE only while there is something left to write
+            self.uart.set_tx_interrupt(self.len != 0);
+        }
+    }
+
+    /// Return the uart, the channel and the callback, dropping any buffers not yet sent
+    ///
+    /// A DMA transfer in progress is waited for first, and its buffer handed to the callback.
+    pub fn free(self) -> (U, Option<D>, F) {
+        let mut this = ManuallyDrop::new(self);
+        this.finish_transfer();
+        // Safety: every field is moved out or dropped once, and `this` isn't dropped
+        unsafe {
+            ptr::drop_in_place(&mut this.queue);
+            (
+                ptr::read(&this.uart),
+                ptr::read(&this.dma),
+                ptr::read(&this.on_complete),
+            )
+        }
+    }
+}
+
+impl<U, D, B, F, const N: usize> TxQueue<U, D, B, F, N>
+where
+    D: DmaChannel,
+    B: StableBuffer,
+    F: FnMut(B),
+{
+    /// Hand the head buffer to the callback and move on to the next
+    fn complete(&mut self) {
+        let buf = self.queue[self.head].take().unwrap();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        self.sent = 0;
+        self.started = false;
+        (self.on_complete)(buf);
+    }
+
+    /// Wait for the DMA transfer in progress, so that its buffer can be dropped
+    fn finish_transfer(&mut self) {
+        if let (Some(dma), true) = (&mut self.dma, self.started) {
+            while !dma.is_done() {}
+            self.complete();
+        }
+    }
+}
+
+impl<U, D, B, F, const N: usize> Drop for TxQueue<U, D, B, F, N>
+where
+    D: DmaChannel,
+    B: StableBuffer,
+    F: FnMut(B),
+{
+    fn drop(&mut self) {
+        self.finish_transfer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeUart {
+        dma_mode: Cell<bool>,
+        tx_interrupt: Cell<bool>,
+        output: RefCell<Vec<u8>>,
+    }
+
+    impl DmaUart for &FakeUart {
+        fn enable_tx_dma(&self) {
+            self.dma_mode.set(true)
+        }
+
+        /// A 4 byte FIFO which empties between calls
+        fn write_pio(&self, bytes: &[u8]) -> usize {
+            let count = bytes.len().min(4);
+            self.output.borrow_mut().extend_from_slice(&bytes[..count]);
+            count
+        }
+
+        fn set_tx_interrupt(&self, enabled: bool) {
+            self.tx_interrupt.set(enabled)
+        }
+    }
+
+    /// A channel moving 3 bytes to the uart every time it is polled
+    struct SimDma<'a> {
+        transfer: Option<(*const u8, usize)>,
+        uart: &'a FakeUart,
+    }
+
+    impl DmaChannel for SimDma<'_> {
+        unsafe fn start(&mut self, data: &[u8]) {
+            assert!(self.transfer.is_none());
+            self.transfer = Some((data.as_ptr(), data.len()));
+        }
+
+        fn is_done(&mut self) -> bool {
+            let (src, len) = self.transfer.take().unwrap();
+            let count = len.min(3);
+            let data = unsafe { core::slice::from_raw_parts(src, count) };
+            self.uart.output.borrow_mut().extend_from_slice(data);
+            if count < len {
+                self.transfer = Some((unsafe { src.add(count) }, len - count));
+                false
+            } else {
+                true
+            }
+        }
+    }
+
+    #[test]
+    fn dma() {
+        let uart = FakeUart::default();
+        let mut completed = Vec::new();
+        let dma = SimDma {
+            transfer: None,
+            uart: &uart,
+        };
+        let mut tx = TxQueue::<_, _, _, _, 1>::with_dma(&uart, dma, |buf: &'static [u8]| {
+            completed.push(buf)
+        });
+        assert!(uart.dma_mode.get());
+
+        assert_eq!(tx.enqueue(b"hello"), Ok(()));
+        assert_eq!(tx.enqueue(b", "), Err(&b", "[..]));
+        while !tx.is_empty() {
+            tx.poll();
+        }
+        assert_eq!(tx.enqueue(b", "), Ok(()));
+        assert_eq!(tx.enqueue(b"world"), Ok(()));
+        tx.poll();
+        assert!(tx.is_empty());
+
+        assert_eq!(*uart.output.borrow(), b"hello, world");
+        assert!(!uart.tx_interrupt.get());
+        drop(tx);
+        assert_eq!(completed, [&b"hello"[..], b", ", b"world"]);
+    }
+
+    #[test]
+    fn free_waits_for_dma() {
+        let uart = FakeUart::default();
+        let mut completed = Vec::new();
+        let dma = SimDma {
+            transfer: None,
+            uart: &uart,
+        };
+        let mut tx = TxQueue::<_, _, _, _, 1>::with_dma(&uart, dma, |buf: &'static [u8]| {
+            completed.push(buf)
+        });
+
+        // Only the first 3 bytes are sent when enqueued
+        tx.enqueue(b"hello").unwrap();
+        assert_eq!(*uart.output.borrow(), b"hel");
+        let (_, dma, _) = tx.free();
+        assert!(dma.unwrap().transfer.is_none());
+        assert_eq!(*uart.output.borrow(), b"hello");
+        assert_eq!(completed, [&b"hello"[..]]);
+    }
+
+    #[test]
+    fn pio() {
+        let uart = FakeUart::default();
+        let mut completed = 0;
+        let mut tx = TxQueue::<_, _, _, _, 4>::pio(&uart, |_: &'static [u8]| completed += 1);
+
+        tx.enqueue(b"0123456789").unwrap();
+        assert!(uart.tx_interrupt.get());
+        tx.enqueue(b"ab").unwrap();
+        tx.poll();
+        tx.poll();
+        assert!(!uart.tx_interrupt.get());
+        assert!(tx.is_empty());
+
+        assert!(!uart.dma_mode.get());
+        assert_eq!(*uart.output.borrow(), b"0123456789ab");
+        drop(tx);
+        assert_eq!(completed, 2);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn owned_buffers() {
+        use alloc::boxed::Box;
+        use alloc::vec;
+
+        let uart = FakeUart::default();
+        let mut completed = Vec::new();
+        let dma = SimDma {
+            transfer: None,
+            uart: &uart,
+        };
+        let mut tx =
+            TxQueue::<_, _, _, _, 2>::with_dma(&uart, dma, |buf: Vec<u8>| completed.push(buf));
+        tx.enqueue(b"hello".to_vec()).unwrap();
+        tx.enqueue(vec![b'!'; 4]).unwrap();
+        while !tx.is_empty() {
+            tx.poll();
+        }
+        drop(tx);
+        assert_eq!(*uart.output.borrow(), b"hello!!!!");
+        assert_eq!(completed, [&b"hello"[..], b"!!!!"]);
+
+        let mut tx = TxQueue::<_, _, _, _, 1>::pio(&uart, |_: Box<[u8]>| {});
+        tx.enqueue(Box::from(&b" world"[..])).unwrap();
+        tx.poll();
+        assert!(tx.is_empty());
+        assert_eq!(*uart.output.borrow(), b"hello!!!! world");
+    }
+}
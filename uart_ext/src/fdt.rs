@@ -0,0 +1,745 @@
+/*!
+# fdt
+
+Construct a configured driver from a node of a Flattened Device Tree (DTB).
+
+Recognised `compatible` strings:
+
+| compatible                 | driver                                  |
+| -------------------------- | --------------------------------------- |
+| `ns16550a`, `ns16550`      | `uart8250::MmioUart8250`                |
+| `snps,dw-apb-uart`         | `uart8250::MmioUart8250`                |
+| `xlnx,axi-uart16550`       | `uart_xilinx::MmioUartAxi16550`         |
+| `xlnx,xps-uartlite-1.00.a` | `uart_xilinx::MmioUartXpsLite`          |
+| `sifive,uart0`             | `uart_sifive::MmioUartSifive`           |
+
+The properties `reg`, `reg-offset`, `reg-shift`, `reg-io-width`, `clock-frequency` and
+`current-speed` are honoured, as well as the options of `/chosen/stdout-path`.
+
+```ignore
+let fdt = Fdt::new(dtb)?;
+let config = UartConfig::from_stdout(&fdt)?;
+let uart = unsafe { config.into_uart()? };
+```
+
+## REF
+
+- [Devicetree Specification v0.4](https://github.com/devicetree-org/devicetree-specification/releases/tag/v0.4)
+*/
+
+use core::str;
+
+use crate::uart::Uart;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+
+/// Deepest node nesting the parser keeps track of
+const MAX_DEPTH: usize = 16;
+
+/// An error encountered while reading the header of a DTB
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FdtError {
+    /// The blob doesn't start with the FDT magic number
+    BadMagic,
+    /// The blob is shorter than its header says
+    Truncated,
+}
+
+/// Read a big-endian u32 at `offset`
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Read a nul-terminated string at `offset`
+fn cstr(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|b| *b == 0)?;
+    str::from_utf8(&bytes[..len]).ok()
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// # A parsed Flattened Device Tree
+///
+/// Only borrows the blob, nothing is copied.
+#[derive(Copy, Clone)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Parse the header of a DTB
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |index: usize| be32(data, index * 4).ok_or(FdtError::Truncated);
+        if header(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = header(1)? as usize;
+        let off_structs = header(2)? as usize;
+        let off_strings = header(3)? as usize;
+        let version = header(5)?;
+        let size_strings = header(8)? as usize;
+        // size_dt_struct only exists since version 17
+        let size_structs = if version >= 17 {
+            header(9)? as usize
+        } else {
+            off_strings.saturating_sub(off_structs)
+        };
+
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let slice = |offset: usize, size: usize| {
+            let end = offset.checked_add(size).ok_or(FdtError::Truncated)?;
+            data.get(offset..end).ok_or(FdtError::Truncated)
+        };
+        Ok(Self {
+            structs: slice(off_structs, size_structs)?,
+            strings: slice(off_strings, size_strings)?,
+        })
+    }
+
+    /// Iterate over every node in depth-first order, starting at the root
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            cells: [(2, 1); MAX_DEPTH],
+        }
+    }
+
+    /// Find a node by its full path, e.g. `/soc/serial@10000000`
+    ///
+    /// A path component without a unit address also matches a node with one, like `dtc` does.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let component = |index: usize| path.split('/').filter(|c| !c.is_empty()).nth(index);
+        let depth = path.split('/').filter(|c| !c.is_empty()).count();
+
+        // number of components matched by the ancestors of the current node
+        let mut matched = 0;
+        for node in self.nodes() {
+            if node.depth == 0 {
+                if depth == 0 {
+                    return Some(node);
+                }
+                continue;
+            }
+            if node.depth <= matched {
+                matched = node.depth - 1;
+            }
+            if node.depth == matched + 1 && node.name_matches(component(matched)?) {
+                matched += 1;
+                if matched == depth {
+                    return Some(node);
+                }
+            }
+        }
+        None
+    }
+
+    /// Get the path an alias in `/aliases` stands for
+    pub fn alias(&self, alias: &str) -> Option<&'a str> {
+        self.find_node("/aliases")?.property_str(alias)
+    }
+
+    /// Find the node `/chosen/stdout-path` points to, along with its options
+    ///
+    /// The path may be an alias and may be followed by options after a `:`, e.g.
+    /// `serial0:115200n8`.
+    pub fn stdout(&self) -> Option<(Node<'a>, Option<&'a str>)> {
+        let chosen = self.find_node("/chosen")?;
+        let stdout = chosen
+            .property_str("stdout-path")
+            .or_else(|| chosen.property_str("linux,stdout-path"))?;
+        let (path, options) = match stdout.split_once(':') {
+            Some((path, options)) => (path, Some(options)),
+            None => (stdout, None),
+        };
+        let path = if path.starts_with('/') {
+            path
+        } else {
+            self.alias(path)?
+        };
+        Some((self.find_node(path)?, options))
+    }
+}
+
+/// Iterator over the nodes of a device tree, see `Fdt::nodes`
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// `#address-cells` and `#size-cells` of the open nodes, which apply to their children
+    cells: [(u32, u32); MAX_DEPTH],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        let structs = self.fdt.structs;
+        loop {
+            match be32(structs, self.offset)? {
+                FDT_BEGIN_NODE => {
+                    if self.depth >= MAX_DEPTH {
+                        return None;
+                    }
+                    let name = cstr(structs, self.offset + 4)?;
+                    self.offset = align4(self.offset + 4 + name.len() + 1);
+
+                    let (address_cells, size_cells) = match self.depth {
+                        0 => (2, 1),
+                        depth => self.cells[depth - 1],
+                    };
+                    let node = Node {
+                        fdt: self.fdt,
+                        name,
+                        props_offset: self.offset,
+                        depth: self.depth,
+                        address_cells,
+                        size_cells,
+                    };
+                    self.cells[self.depth] = (
+                        node.property_u32("#address-cells").unwrap_or(2),
+                        node.property_u32("#size-cells").unwrap_or(1),
+                    );
+                    self.depth += 1;
+                    return Some(node);
+                }
+                FDT_END_NODE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    self.offset += 4;
+                }
+                FDT_PROP => {
+                    let len = be32(structs, self.offset + 4)? as usize;
+                    self.offset = align4(self.offset + 12 + len);
+                }
+                FDT_NOP => self.offset += 4,
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// A property of a node: its name and raw value
+#[derive(Copy, Clone, Debug)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// Iterator over the properties of a node, see `Node::properties`
+pub struct Properties<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = Property<'a>;
+
+    fn next(&mut self) -> Option<Property<'a>> {
+        let structs = self.fdt.structs;
+        loop {
+            match be32(structs, self.offset)? {
+                FDT_PROP => {
+                    let len = be32(structs, self.offset + 4)? as usize;
+                    let name_offset = be32(structs, self.offset + 8)? as usize;
+                    let value = structs.get(self.offset + 12..self.offset + 12 + len)?;
+                    self.offset = align4(self.offset + 12 + len);
+                    return Some(Property {
+                        name: cstr(self.fdt.strings, name_offset)?,
+                        value,
+                    });
+                }
+                FDT_NOP => self.offset += 4,
+                // properties always come before the subnodes
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// # A node of a device tree
+#[derive(Copy, Clone)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    props_offset: usize,
+    depth: usize,
+    /// `#address-cells` of the parent
+    address_cells: u32,
+    /// `#size-cells` of the parent
+    size_cells: u32,
+}
+
+impl<'a> Node<'a> {
+    /// Name of the node, including the unit address, e.g. `serial@10000000`
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn name_matches(&self, component: &str) -> bool {
+        self.name == component
+            || (!component.contains('@') && self.name.split('@').next() == Some(component))
+    }
+
+    /// Iterate over the properties of the node
+    pub fn properties(&self) -> Properties<'a> {
+        Properties {
+            fdt: self.fdt,
+            offset: self.props_offset,
+        }
+    }
+
+    /// Get the raw value of a property
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|property| property.name == name)
+            .map(|property| property.value)
+    }
+
+    /// Get a property holding a single `<u32>`
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        let value = self.property(name)?;
+        if value.len() == 4 {
+            be32(value, 0)
+        } else {
+            None
+        }
+    }
+
+    /// Get a property holding a single string
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        let value = self.property(name)?;
+        str::from_utf8(value.strip_suffix(&[0])?).ok()
+    }
+
+    /// Iterate over the strings of the `compatible` property
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible")
+            .unwrap_or(&[])
+            .split(|b| *b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| str::from_utf8(s).ok())
+    }
+
+    /// Get the address and size of the first region in `reg`
+    pub fn reg(&self) -> Option<(u64, u64)> {
+        let value = self.property("reg")?;
+        let cells = |offset: usize, count: u32| {
+            (0..count as usize).try_fold(0u64, |acc, i| {
+                Some(acc << 32 | be32(value, offset + i * 4)? as u64)
+            })
+        };
+        let address = cells(0, self.address_cells)?;
+        let size = cells(self.address_cells as usize * 4, self.size_cells)?;
+        Some((address, size))
+    }
+}
+
+/// An error encountered while probing a uart
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProbeError {
+    /// No recognised string in `compatible`
+    NotCompatible,
+    /// `reg` is missing, malformed or out of the address space
+    BadReg,
+    /// `reg-shift` or `reg-io-width` describe a layout the driver can't access
+    UnsupportedLayout,
+    /// The driver crate for this uart is not enabled
+    DriverDisabled,
+    /// `/chosen/stdout-path` is missing or doesn't point to a node
+    NoStdout,
+}
+
+/// The kinds of uart this module recognises
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum UartKind {
+    /// `ns16550a`, `ns16550`
+    Ns16550,
+    /// `snps,dw-apb-uart`
+    DesignWareApb,
+    /// `xlnx,axi-uart16550`
+    Axi16550,
+    /// `xlnx,xps-uartlite-1.00.a`
+    XpsLite,
+    /// `sifive,uart0`
+    Sifive,
+}
+
+impl UartKind {
+    fn from_compatible(compatible: &str) -> Option<Self> {
+        match compatible {
+            "ns16550a" | "ns16550" => Some(Self::Ns16550),
+            "snps,dw-apb-uart" => Some(Self::DesignWareApb),
+            "xlnx,axi-uart16550" => Some(Self::Axi16550),
+            "xlnx,xps-uartlite-1.00.a" => Some(Self::XpsLite),
+            "sifive,uart0" => Some(Self::Sifive),
+            _ => None,
+        }
+    }
+}
+
+/// Parity requested by the options of `stdout-path`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StdoutParity {
+    No,
+    Odd,
+    Even,
+}
+
+/// # Everything needed to construct and configure a driver
+///
+/// Read from a device tree node by `from_node` or `from_stdout`, then turned into a driver by
+/// `into_uart`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UartConfig {
+    pub kind: UartKind,
+    /// Address of the first register, `reg-offset` included
+    pub base_address: usize,
+    /// `reg-shift`, 0 if absent
+    pub reg_shift: u8,
+    /// `reg-io-width` in bytes, 1 if absent
+    pub reg_io_width: u8,
+    /// `clock-frequency` of the uart input clock
+    pub clock_frequency: Option<u32>,
+    /// `current-speed`, or the baud rate from the options of `stdout-path`
+    pub baud_rate: Option<u32>,
+    /// Parity from the options of `stdout-path`
+    pub parity: Option<StdoutParity>,
+    /// Data bits from the options of `stdout-path`
+    pub data_bits: Option<u8>,
+}
+
+impl UartConfig {
+    /// Read the configuration of the uart described by `node`
+    pub fn from_node(node: &Node) -> Result<Self, ProbeError> {
+        let kind = node
+            .compatible()
+            .find_map(UartKind::from_compatible)
+            .ok_or(ProbeError::NotCompatible)?;
+        let (address, _) = node.reg().ok_or(ProbeError::BadReg)?;
+        let offset = node.property_u32("reg-offset").unwrap_or(0) as u64;
+        let base_address = address
+            .checked_add(offset)
+            .and_then(|address| usize::try_from(address).ok())
+            .ok_or(ProbeError::BadReg)?;
+        let reg_shift = node.property_u32("reg-shift").unwrap_or(0);
+        let reg_io_width = node.property_u32("reg-io-width").unwrap_or(1);
+        if reg_shift > 3 || !matches!(reg_io_width, 1 | 2 | 4) {
+            return Err(ProbeError::UnsupportedLayout);
+        }
+
+        Ok(Self {
+            kind,
+            base_address,
+            reg_shift: reg_shift as u8,
+            reg_io_width: reg_io_width as u8,
+            clock_frequency: node.property_u32("clock-frequency"),
+            baud_rate: node
+                .property_u32("current-speed")
+                .filter(|&baud_rate| baud_rate != 0),
+            parity: None,
+            data_bits: None,
+        })
+    }
+
+    /// Read the configuration of the uart `/chosen/stdout-path` points to
+    ///
+    /// The options after the path, in the `<baud>{<parity>{<bits>}}` format of Linux, take
+    /// precedence over the properties of the node.
+    pub fn from_stdout(fdt: &Fdt) -> Result<Self, ProbeError> {
+        let (node, options) = fdt.stdout().ok_or(ProbeError::NoStdout)?;
+        let mut config = Self::from_node(&node)?;
+        if let Some(options) = options {
+            config.apply_options(options);
+        }
+        Ok(config)
+    }
+
+    fn apply_options(&mut self, options: &str) {
+        let digits = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        match options[..digits].parse() {
+            Ok(0) | Err(_) => {}
+            Ok(baud_rate) => self.baud_rate = Some(baud_rate),
+        }
+        let mut rest = options[digits..].chars();
+        self.parity = match rest.next() {
+            Some('n') => Some(StdoutParity::No),
+            Some('o') => Some(StdoutParity::Odd),
+            Some('e') => Some(StdoutParity::Even),
+            _ => self.parity,
+        };
+        self.data_bits = match rest.next() {
+            Some(bits @ '5'..='8') => Some(bits as u8 - b'0'),
+            _ => self.data_bits,
+        };
+    }
+
+    /// Construct the driver and configure it
+    ///
+    /// The line is only reprogrammed when both the input clock and the baud rate are known and
+    /// not zero, otherwise it is left as the firmware configured it.
+    ///
+    /// # Safety
+    ///
+    /// The registers described by the configuration must be mapped at `base_address` as device
+    /// memory and not have any other aliases.
+    pub unsafe fn into_uart(self) -> Result<Uart, ProbeError> {
+        #[cfg(any(feature = "uart8250", feature = "uart_xilinx", feature = "uart_sifive"))]
+        let line = self
+            .clock_frequency
+            .filter(|&clock| clock != 0)
+            .zip(
+                self.baud_rate
+                    .and_then(|bps| uart_common::BaudRate::from_bps(bps as usize)),
+            )
+            .map(|(clock, baud_rate)| (clock as usize, baud_rate));
+        match self.kind {
+            #[cfg(feature = "uart8250")]
+            UartKind::Ns16550 | UartKind::DesignWareApb => {
+                use uart8250::{DataBits, MmioUart8250, Parity, RegisterWidth};
+
+                let width = match self.reg_io_width {
+                    1 => RegisterWidth::U8,
+                    2 => RegisterWidth::U16,
+                    _ => RegisterWidth::U32,
+                };
+                let uart = MmioUart8250::with_layout(self.base_address, self.reg_shift, width);
+                if let Some((clock, baud_rate)) = line {
+                    uart.init_with_baud_rate(&clock, baud_rate);
+                    let mut format = uart.frame_format();
+                    if let Some(parity) = self.parity {
+                        format.parity = match parity {
+                            StdoutParity::No => Parity::No,
+                            StdoutParity::Odd => Parity::Odd,
+                            StdoutParity::Even => Parity::Even,
+                        };
+                    }
+                    if let Some(data_bits) = self.data_bits.and_then(DataBits::from_bits) {
+                        format.data_bits = data_bits;
+                    }
+                    // init leaves 1 stop bit, which goes with any number of data bits
+                    let _ = uart.set_frame_format(format);
+                }
+                Ok(Uart::Uart8250(uart))
+            }
+            #[cfg(feature = "uart_xilinx")]
+            UartKind::Axi16550 => {
+                use uart_xilinx::uart_16550::{DataBits, MmioUartAxi16550, Parity};
+
+                // The registers of the AXI UART 16550 are always 32 bits wide and 4 bytes apart
+                if self.reg_shift != 0 && self.reg_shift != 2 {
+                    return Err(ProbeError::UnsupportedLayout);
+                }
+                let uart = MmioUartAxi16550::new(self.base_address);
+                if let Some((clock, baud_rate)) = line {
+                    uart.init_with_baud_rate(&clock, baud_rate);
+                    let mut format = uart.frame_format();
+                    if let Some(parity) = self.parity {
+                        format.parity = match parity {
+                            StdoutParity::No => Parity::No,
+                            StdoutParity::Odd => Parity::Odd,
+                            StdoutParity::Even => Parity::Even,
+                        };
+                    }
+                    if let Some(data_bits) = self
+                        .data_bits
+                        .and_then(|bits| DataBits::from_bits(bits as u32))
+                    {
+                        format.data_bits = data_bits;
+                    }
+                    // init leaves 1 stop bit, which goes with any number of data bits
+                    let _ = uart.set_frame_format(format);
+                }
+                Ok(Uart::Axi16550(uart))
+            }
+            // The line settings of the UART Lite are fixed when the IP is generated
+            #[cfg(feature = "uart_xilinx")]
+            UartKind::XpsLite => Ok(Uart::XpsLite(uart_xilinx::MmioUartXpsLite::new(
+                self.base_address,
+            ))),
+            #[cfg(feature = "uart_sifive")]
+            UartKind::Sifive => {
+                let uart = uart_sifive::MmioUartSifive::new(self.base_address);
+                if let Some((clock, baud_rate)) = line {
+                    uart.set_baud_rate(&clock, baud_rate);
+                    uart.enable_write();
+                    uart.enable_read();
+                }
+                Ok(Uart::Sifive(uart))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(ProbeError::DriverDisabled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QEMU_VIRT: &[u8] = include_bytes!("../tests/fdt/qemu-riscv-virt.dtb");
+    const DW_APB: &[u8] = include_bytes!("../tests/fdt/dw-apb-uart.dtb");
+    const XILINX: &[u8] = include_bytes!("../tests/fdt/xilinx-axi.dtb");
+    const HIFIVE: &[u8] = include_bytes!("../tests/fdt/hifive-unleashed.dtb");
+
+    #[test]
+    fn header() {
+        assert_eq!(Fdt::new(&QEMU_VIRT[..20]).err(), Some(FdtError::Truncated));
+        assert_eq!(Fdt::new(&[0; 64]).err(), Some(FdtError::BadMagic));
+        assert!(Fdt::new(QEMU_VIRT).is_ok());
+    }
+
+    #[test]
+    fn find_node() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        assert_eq!(fdt.find_node("/").unwrap().name(), "");
+        assert_eq!(
+            fdt.find_node("/soc/serial").unwrap().name(),
+            "serial@10000000"
+        );
+        assert!(fdt.find_node("/soc/serial@10000001").is_none());
+        assert!(fdt.find_node("/serial@10000000").is_none());
+    }
+
+    #[test]
+    fn qemu_virt() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        let config = UartConfig::from_stdout(&fdt).unwrap();
+        assert_eq!(
+            config,
+            UartConfig {
+                kind: UartKind::Ns16550,
+                base_address: 0x1000_0000,
+                reg_shift: 0,
+                reg_io_width: 1,
+                clock_frequency: Some(3_686_400),
+                baud_rate: None,
+                parity: None,
+                data_bits: None,
+            }
+        );
+    }
+
+    #[test]
+    fn dw_apb_uart() {
+        let fdt = Fdt::new(DW_APB).unwrap();
+        // stdout-path = "serial0:115200n8"
+        let config = UartConfig::from_stdout(&fdt).unwrap();
+        assert_eq!(config.kind, UartKind::DesignWareApb);
+        assert_eq!(config.base_address, 0x1244_0000);
+        assert_eq!((config.reg_shift, config.reg_io_width), (2, 4));
+        assert_eq!(config.clock_frequency, Some(100_000_000));
+        assert_eq!(config.baud_rate, Some(115_200));
+        assert_eq!(config.parity, Some(StdoutParity::No));
+        assert_eq!(config.data_bits, Some(8));
+
+        // The other uart uses current-speed
+        let node = fdt.find_node("/soc/serial@12430000").unwrap();
+        let mut config = UartConfig::from_node(&node).unwrap();
+        assert_eq!(config.baud_rate, Some(9600));
+        assert_eq!(config.parity, None);
+
+        // A zero baud rate in the options is ignored
+        config.apply_options("0n8");
+        assert_eq!(config.baud_rate, Some(9600));
+        assert_eq!(config.data_bits, Some(8));
+    }
+
+    #[cfg(feature = "uart_sifive")]
+    #[test]
+    fn zero_baud_rate() {
+        let mut fake_registers: [u32; 7] = [0x5a; 7];
+        let config = UartConfig {
+            kind: UartKind::Sifive,
+            base_address: &mut fake_registers as *mut u32 as usize,
+            reg_shift: 0,
+            reg_io_width: 4,
+            clock_frequency: Some(3_686_400),
+            baud_rate: Some(0),
+            parity: None,
+            data_bits: None,
+        };
+        assert!(unsafe { config.into_uart() }.is_ok());
+        // The line is left as it was found, div included
+        assert_eq!(fake_registers, [0x5a; 7]);
+    }
+
+    #[cfg(feature = "uart_sifive")]
+    #[test]
+    fn sifive_baud_rate() {
+        let mut fake_registers: [u32; 7] = [0; 7];
+        let config = UartConfig {
+            kind: UartKind::Sifive,
+            base_address: &mut fake_registers as *mut u32 as usize,
+            reg_shift: 0,
+            reg_io_width: 4,
+            clock_frequency: Some(16_000_000),
+            baud_rate: Some(115_200),
+            parity: None,
+            data_bits: None,
+        };
+        let uart = match unsafe { config.into_uart() } {
+            Ok(Uart::Sifive(uart)) => uart,
+            #[allow(unreachable_patterns)]
+            _ => panic!("not a SiFive UART"),
+        };
+        assert_eq!(uart.baud_rate(), Some(uart_common::BaudRate::B115200));
+        assert_eq!(uart.read_div(), 137);
+        // Following a clock change needs the baud rate
+        assert_eq!(
+            uart.reapply_baud_rate(&32_000_000),
+            Some(uart_common::BaudRate::B115200)
+        );
+        assert_eq!(uart.read_div(), 276);
+    }
+
+    #[test]
+    fn xilinx() {
+        let fdt = Fdt::new(XILINX).unwrap();
+        let config = UartConfig::from_stdout(&fdt).unwrap();
+        assert_eq!(config.kind, UartKind::Axi16550);
+        // reg-offset = <0x1000>
+        assert_eq!(config.base_address, 0x44a0_1000);
+        assert_eq!(config.baud_rate, Some(115_200));
+
+        let node = fdt.find_node("/amba_pl/serial@40600000").unwrap();
+        let config = UartConfig::from_node(&node).unwrap();
+        assert_eq!(config.kind, UartKind::XpsLite);
+        assert_eq!(config.base_address, 0x4060_0000);
+        assert_eq!(config.clock_frequency, None);
+
+        let node = fdt.find_node("/amba_pl/gpio@40000000").unwrap();
+        assert_eq!(
+            UartConfig::from_node(&node).err(),
+            Some(ProbeError::NotCompatible)
+        );
+    }
+
+    #[test]
+    fn hifive_unleashed() {
+        let fdt = Fdt::new(HIFIVE).unwrap();
+        let config = UartConfig::from_stdout(&fdt).unwrap();
+        assert_eq!(config.kind, UartKind::Sifive);
+        assert_eq!(config.base_address, 0x1001_0000);
+        // The input clock is given by a phandle, which is not followed
+        assert_eq!(config.clock_frequency, None);
+        assert_eq!(
+            fdt.find_node("/soc/serial@10011000")
+                .unwrap()
+                .compatible()
+                .nth(1),
+            Some("sifive,uart0")
+        );
+    }
+}
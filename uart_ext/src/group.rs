@@ -0,0 +1,320 @@
+/*!
+# group
+
+Several uarts sharing one interrupt line, as on 4 and 8 port 16550 cards or designs with several
+AXI UART 16550 on one IRQ. A [`UartGroup`] holds the ports and a handler for each, and services
+them round-robin from a single interrupt handler until none has an interrupt pending.
+
+```ignore
+let ports = [0, 1, 2, 3].map(|i| unsafe { MmioUart8250::new(BASE + i * 8) });
+let mut group = UartGroup::new(ports, [&mut console as &mut dyn PortHandler<_>, &mut modem, ..]);
+
+// In the interrupt handler
+group.service();
+```
+
+Boards with a global interrupt status register, e.g. the `INT0` register of Exar cards, can use
+[`UartGroup::service_with_status`] to only look at the ports it reports.
+
+Every handler must clear the interrupt it is given (read RBR on received data, LSR on a line
+status interrupt, and so on), otherwise its port stays pending. A port that cannot be cleared is
+given up on after [`UartGroup::with_max_rounds`] rounds.
+*/
+
+use core::ptr::read_volatile;
+
+#[cfg(feature = "uart8250")]
+use uart8250::{InterruptType, MmioUart8250};
+#[cfg(feature = "uart_sifive")]
+use uart_sifive::{uart::InterruptRegister, MmioUartSifive};
+#[cfg(feature = "uart_xilinx")]
+use uart_xilinx::{uart_16550::InterruptType as Axi16550Interrupt, MmioUartAxi16550};
+
+/// A port that can tell which of its interrupts is pending
+pub trait InterruptSource {
+    /// The pending interrupt
+    type Interrupt: Copy;
+
+    /// Read the pending interrupt, `None` if there is none
+    fn pending_interrupt(&self) -> Option<Self::Interrupt>;
+}
+
+#[cfg(feature = "uart8250")]
+impl InterruptSource for MmioUart8250<'_> {
+    type Interrupt = InterruptType;
+
+    /// Read IIR, the highest priority interrupt
+    fn pending_interrupt(&self) -> Option<InterruptType> {
+        self.read_interrupt_type()
+    }
+}
+
+#[cfg(feature = "uart_xilinx")]
+impl InterruptSource for MmioUartAxi16550<'_> {
+    type Interrupt = Axi16550Interrupt;
+
+    /// Read IIR, the highest priority interrupt
+    fn pending_interrupt(&self) -> Option<Axi16550Interrupt> {
+        self.read_interrupt_type()
+    }
+}
+
+#[cfg(feature = "uart_sifive")]
+impl InterruptSource for MmioUartSifive {
+    type Interrupt = InterruptRegister;
+
+    /// The enabled watermark interrupts that are pending
+    fn pending_interrupt(&self) -> Option<InterruptRegister> {
+        let pending = self.read_ip() & self.read_ie();
+        (!pending.is_empty()).then_some(pending)
+    }
+}
+
+/// Handles the interrupts of one port of a [`UartGroup`]
+pub trait PortHandler<P: InterruptSource + ?Sized> {
+    /// Handle and clear `interrupt` of `port`
+    fn handle(&mut self, port: &P, interrupt: P::Interrupt);
+}
+
+impl<P: InterruptSource + ?Sized, F: FnMut(&P, P::Interrupt)> PortHandler<P> for F {
+    fn handle(&mut self, port: &P, interrupt: P::Interrupt) {
+        self(port, interrupt)
+    }
+}
+
+impl<P: InterruptSource + ?Sized> PortHandler<P> for &mut dyn PortHandler<P> {
+    fn handle(&mut self, port: &P, interrupt: P::Interrupt) {
+        (**self).handle(port, interrupt)
+    }
+}
+
+/// A register with a bit set for each port that has an interrupt pending
+pub trait GlobalStatus {
+    /// Bit `i` is set when port `i` is pending
+    fn pending_ports(&self) -> u32;
+}
+
+impl<F: Fn() -> u32> GlobalStatus for F {
+    fn pending_ports(&self) -> u32 {
+        self()
+    }
+}
+
+/// An 8 bit memory mapped global interrupt status register
+#[derive(Debug)]
+pub struct StatusRegister {
+    addr: *const u8,
+    active_low: bool,
+}
+
+impl StatusRegister {
+    /// Create a status register whose bits are set for pending ports
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be the address of the register, mapped as device memory.
+    pub const unsafe fn new(addr: usize) -> Self {
+        Self {
+            addr: addr as *const u8,
+            active_low: false,
+        }
+    }
+
+    /// Set whether bits are cleared, rather than set, for pending ports
+    pub const fn with_active_low(mut self, active_low: bool) -> Self {
+        self.active_low = active_low;
+        self
+    }
+}
+
+impl GlobalStatus for StatusRegister {
+    fn pending_ports(&self) -> u32 {
+        let bits = unsafe { read_volatile(self.addr) };
+        (if self.active_low { !bits } else { bits }) as u32
+    }
+}
+
+/// Ports sharing an interrupt line, each with its own handler
+pub struct UartGroup<P, H, const N: usize> {
+    ports: [P; N],
+    handlers: [H; N],
+    next: usize,
+    max_rounds: usize,
+}
+
+impl<P: InterruptSource, H: PortHandler<P>, const N: usize> UartGroup<P, H, N> {
+    /// Create a group where `handlers[i]` handles the interrupts of `ports[i]`
+    pub fn new(ports: [P; N], handlers: [H; N]) -> Self {
+        Self {
+            ports,
+            handlers,
+            next: 0,
+            max_rounds: usize::MAX,
+        }
+    }
+
+    /// Give up servicing after `max_rounds` rounds over the ports, default unlimited
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Get the ports
+    pub fn ports(&self) -> &[P; N] {
+        &self.ports
+    }
+
+    /// Get a port and its handler
+    pub fn port_mut(&mut self, index: usize) -> (&mut P, &mut H) {
+        (&mut self.ports[index], &mut self.handlers[index])
+    }
+
+    /// Return the ports and their handlers
+    pub fn free(self) -> ([P; N], [H; N]) {
+        (self.ports, self.handlers)
+    }
+
+    /// Handle one interrupt of every pending port in `mask`, return how many were handled
+    fn round(&mut self, mask: u32) -> usize {
+        let mut handled = 0;
+        let start = self.next;
+        for i in (start..N).chain(0..start) {
+            if i < 32 && mask & (1 << i) == 0 {
+                continue;
+            }
+            if let Some(interrupt) = self.ports[i].pending_interrupt() {
+                self.handlers[i].handle(&self.ports[i], interrupt);
+                handled += 1;
+                // Start the next round after this port so no port starves the others
+                self.next = (i + 1) % N;
+            }
+        }
+        handled
+    }
+
+    /// Service every port until none has an interrupt pending, return how many were handled
+    ///
+    /// Each round reads the pending interrupt of every port and handles at most one per port.
+    pub fn service(&mut self) -> usize {
+        let mut handled = 0;
+        for _ in 0..self.max_rounds {
+            match self.round(!0) {
+                0 => break,
+                n => handled += n,
+            }
+        }
+        handled
+    }
+
+    /// Service the ports `status` reports pending until it reports none
+    ///
+    /// Only the first 32 ports can be reported, the others are always checked. Stops early if none
+    /// of the reported ports has an interrupt pending. Return how many interrupts were handled.
+    pub fn service_with_status(&mut self, status: &impl GlobalStatus) -> usize {
+        let mut handled = 0;
+        for _ in 0..self.max_rounds {
+            let mask = status.pending_ports();
+            if mask == 0 {
+                break;
+            }
+            // None of the reported ports is pending, e.g. a stale status bit: leave it to the next
+            // interrupt
+            match self.round(mask) {
+                0 => break,
+                n => handled += n,
+            }
+        }
+        handled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use std::vec::Vec;
+
+    use super::*;
+
+    struct FakePort<'a> {
+        id: usize,
+        pending: &'a Cell<u32>,
+        log: &'a RefCell<Vec<usize>>,
+    }
+
+    impl InterruptSource for FakePort<'_> {
+        type Interrupt = u32;
+
+        fn pending_interrupt(&self) -> Option<u32> {
+            let pending = self.pending.get();
+            (pending != 0).then_some(pending)
+        }
+    }
+
+    fn clear_one(port: &FakePort, pending: u32) {
+        port.log.borrow_mut().push(port.id);
+        port.pending.set(pending - 1);
+    }
+
+    fn group<'a>(
+        pending: &'a [Cell<u32>; 4],
+        log: &'a RefCell<Vec<usize>>,
+    ) -> UartGroup<FakePort<'a>, fn(&FakePort, u32), 4> {
+        let ports = [0, 1, 2, 3].map(|id| FakePort {
+            id,
+            pending: &pending[id],
+            log,
+        });
+        UartGroup::new(ports, [clear_one as fn(&FakePort, u32); 4])
+    }
+
+    #[test]
+    fn round_robin() {
+        let pending = [2, 0, 3, 1].map(Cell::new);
+        let log = RefCell::new(Vec::new());
+        let mut group = group(&pending, &log);
+
+        assert_eq!(group.service(), 6);
+        assert_eq!(*log.borrow(), [0, 2, 3, 0, 2, 2]);
+        assert_eq!(group.service(), 0);
+    }
+
+    #[test]
+    fn max_rounds() {
+        let pending = [0, 5, 0, 0].map(Cell::new);
+        let log = RefCell::new(Vec::new());
+        let mut group = group(&pending, &log).with_max_rounds(2);
+
+        assert_eq!(group.service(), 2);
+        assert_eq!(pending[1].get(), 3);
+    }
+
+    #[test]
+    fn global_status() {
+        let pending = [1, 1, 2, 0].map(Cell::new);
+        let log = RefCell::new(Vec::new());
+        let mut group = group(&pending, &log);
+
+        // Port 0 is pending but not reported
+        let status = || {
+            let mut mask = 0;
+            for (i, pending) in pending.iter().enumerate().skip(1) {
+                if pending.get() != 0 {
+                    mask |= 1 << i;
+                }
+            }
+            mask
+        };
+        assert_eq!(group.service_with_status(&status), 3);
+        assert_eq!(*log.borrow(), [1, 2, 2]);
+        assert_eq!(pending[0].get(), 1);
+
+        // Reports port 0 forever, which stops once it is no longer pending
+        let mut register = 0b1111_1110u8;
+        let register =
+            unsafe { StatusRegister::new(&mut register as *mut u8 as usize) }.with_active_low(true);
+        assert_eq!(group.service_with_status(&register), 1);
+        assert_eq!(pending[0].get(), 0);
+    }
+}
@@ -0,0 +1,436 @@
+/*!
+# hdlc
+
+HDLC-like framing (RFC 1662) over any [`Serial`], as used by PPP: frames between 0x7E flags,
+octet stuffing with 0x7D, an async control character map and an FCS-16 or FCS-32 trailer.
+
+```ignore
+let mut link = Hdlc::new(uart, [0; 1504]);
+link.send(&[0xff, 0x03, 0xc0, 0x21, ...]);
+// Once LCP has negotiated an ACCM
+link.set_config(HdlcConfig { tx_accm: 0, rx_accm: 0, ..link.config() });
+match link.poll() {
+    Some(Ok(frame)) => ppp_input(frame),
+    Some(Err(HdlcError::BadFcs)) => bad_fcs += 1,
+    _ => {}
+}
+```
+
+## REF
+
+- [RFC 1662: PPP in HDLC-like Framing](https://www.rfc-editor.org/rfc/rfc1662)
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::crc::{crc16_x25_update, crc32_update};
+use crate::serial::Serial;
+
+/// Flag sequence, delimiting frames
+pub const FLAG: u8 = 0x7e;
+/// Control escape, the next byte is XORed with 0x20
+pub const ESC: u8 = 0x7d;
+
+/// Good FCS-16 residue, over a frame and its FCS
+const FCS16_GOOD: u16 = 0xf0b8;
+/// Good FCS-32 residue, over a frame and its FCS
+const FCS32_GOOD: u32 = 0xdebb_20e3;
+
+/// Frame check sequence
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Fcs {
+    /// 16-bit FCS, the default
+    Fcs16,
+    /// 32-bit FCS, negotiated with the LCP FCS-Alternatives option
+    Fcs32,
+}
+
+impl Fcs {
+    /// Length of the trailer
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Fcs16 => 2,
+            Self::Fcs32 => 4,
+        }
+    }
+
+    /// Compute the trailer of `frame`, low byte first
+    fn compute(self, frame: &[u8]) -> ([u8; 4], usize) {
+        let mut trailer = [0; 4];
+        match self {
+            Self::Fcs16 => {
+                trailer[..2].copy_from_slice(&(!crc16_x25_update(!0, frame)).to_le_bytes())
+            }
+            Self::Fcs32 => trailer = (!crc32_update(!0, frame)).to_le_bytes(),
+        }
+        (trailer, self.size())
+    }
+
+    /// Whether `frame` ends with a good FCS
+    fn check(self, frame: &[u8]) -> bool {
+        match self {
+            Self::Fcs16 => crc16_x25_update(!0, frame) == FCS16_GOOD,
+            Self::Fcs32 => crc32_update(!0, frame) == FCS32_GOOD,
+        }
+    }
+}
+
+/// Framing parameters of an [`Hdlc`] link
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HdlcConfig {
+    pub fcs: Fcs,
+    /// Control characters 0x00 to 0x1F to escape on transmit, bit n for character n
+    pub tx_accm: u32,
+    /// Control characters 0x00 to 0x1F to drop when received unescaped
+    pub rx_accm: u32,
+}
+
+impl Default for HdlcConfig {
+    /// FCS-16 and every control character mapped, as before LCP negotiation
+    fn default() -> Self {
+        Self {
+            fcs: Fcs::Fcs16,
+            tx_accm: !0,
+            rx_accm: !0,
+        }
+    }
+}
+
+/// A frame that could not be received
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HdlcError {
+    /// The FCS doesn't match
+    BadFcs,
+    /// The sender aborted the frame with 0x7D 0x7E, or it was dropped with `HdlcDecoder::abort`
+    Aborted,
+    /// The frame is shorter than its FCS
+    Runt,
+    /// The frame didn't fit in the buffer; `len` is its length, FCS included
+    Oversize { len: usize },
+}
+
+impl Display for HdlcError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::BadFcs => f.write_str("bad FCS"),
+            Self::Aborted => f.write_str("frame aborted"),
+            Self::Runt => f.write_str("frame shorter than its FCS"),
+            Self::Oversize { len } => write!(f, "frame of {} bytes too large", len),
+        }
+    }
+}
+
+fn needs_escape(byte: u8, accm: u32) -> bool {
+    byte == FLAG || byte == ESC || (byte < 0x20 && accm & 1 << byte != 0)
+}
+
+/// Number of bytes `frame` takes once framed with `config`, both flags included
+pub fn encoded_len(frame: &[u8], config: &HdlcConfig) -> usize {
+    let (trailer, len) = config.fcs.compute(frame);
+    frame
+        .iter()
+        .chain(&trailer[..len])
+        .map(|byte| {
+            if needs_escape(*byte, config.tx_accm) {
+                2
+            } else {
+                1
+            }
+        })
+        .sum::<usize>()
+        + 2
+}
+
+/// Frame `frame` with `config`, passing each byte to `emit`
+fn encode_with(frame: &[u8], config: &HdlcConfig, mut emit: impl FnMut(u8)) {
+    let (trailer, len) = config.fcs.compute(frame);
+    emit(FLAG);
+    for byte in frame.iter().chain(&trailer[..len]) {
+        if needs_escape(*byte, config.tx_accm) {
+            emit(ESC);
+            emit(*byte ^ 0x20);
+        } else {
+            emit(*byte);
+        }
+    }
+    emit(FLAG);
+}
+
+/// Frame `frame` into `out` with `config`, returning the framed length
+///
+/// Returns `None` when `out` is shorter than [`encoded_len`].
+pub fn encode(frame: &[u8], config: &HdlcConfig, out: &mut [u8]) -> Option<usize> {
+    if out.len() < encoded_len(frame, config) {
+        return None;
+    }
+    let mut len = 0;
+    encode_with(frame, config, |byte| {
+        out[len] = byte;
+        len += 1;
+    });
+    Some(len)
+}
+
+/// # Incremental HDLC deframer
+///
+/// Fed one byte at a time, it decodes into `B`, a `[u8; N]` or a `&mut [u8]`, which must have
+/// room for the FCS. Frames are returned without it. Empty frames between flags are skipped.
+pub struct HdlcDecoder<B> {
+    buf: B,
+    len: usize,
+    escaped: bool,
+    fcs: Fcs,
+    accm: u32,
+}
+
+impl<B: AsMut<[u8]>> HdlcDecoder<B> {
+    pub fn new(buf: B, fcs: Fcs, rx_accm: u32) -> Self {
+        Self {
+            buf,
+            len: 0,
+            escaped: false,
+            fcs,
+            accm: rx_accm,
+        }
+    }
+
+    /// Give back the buffer
+    pub fn free(self) -> B {
+        self.buf
+    }
+
+    /// Whether a frame is partially received
+    pub fn is_idle(&self) -> bool {
+        self.len == 0 && !self.escaped
+    }
+
+    /// Drop the frame being received
+    ///
+    /// Returns `Err(HdlcError::Aborted)` if there was one.
+    pub fn abort(&mut self) -> Result<(), HdlcError> {
+        let idle = self.is_idle();
+        self.len = 0;
+        self.escaped = false;
+        if idle {
+            Ok(())
+        } else {
+            Err(HdlcError::Aborted)
+        }
+    }
+
+    /// Feed one byte, returning a frame or an error at a flag
+    ///
+    /// The frame stays valid until the next call.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], HdlcError>> {
+        match self.push_len(byte)? {
+            Ok(len) => Some(Ok(&self.buf.as_mut()[..len])),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// `push`, returning the length of the frame rather than borrowing it
+    fn push_len(&mut self, byte: u8) -> Option<Result<usize, HdlcError>> {
+        if byte == FLAG {
+            let len = core::mem::take(&mut self.len);
+            if core::mem::take(&mut self.escaped) {
+                return Some(Err(HdlcError::Aborted));
+            }
+            let buf = self.buf.as_mut();
+            return match len {
+                0 => None,
+                len if len > buf.len() => Some(Err(HdlcError::Oversize { len })),
+                len if len < self.fcs.size() => Some(Err(HdlcError::Runt)),
+                len if self.fcs.check(&buf[..len]) => Some(Ok(len - self.fcs.size())),
+                _ => Some(Err(HdlcError::BadFcs)),
+            };
+        }
+
+        let byte = if self.escaped {
+            self.escaped = false;
+            byte ^ 0x20
+        } else if byte == ESC {
+            self.escaped = true;
+            return None;
+        } else if byte < 0x20 && self.accm & 1 << byte != 0 {
+            // Inserted by a DCE along the way
+            return None;
+        } else {
+            byte
+        };
+
+        // Keep counting an oversized frame, so its length can be reported
+        if let Some(slot) = self.buf.as_mut().get_mut(self.len) {
+            *slot = byte;
+        }
+        self.len += 1;
+        None
+    }
+}
+
+/// # HDLC-like link over a [`Serial`]
+pub struct Hdlc<S, B> {
+    serial: S,
+    decoder: HdlcDecoder<B>,
+    config: HdlcConfig,
+}
+
+impl<S: Serial, B: AsMut<[u8]>> Hdlc<S, B> {
+    /// Default configuration, frames received are decoded into `buf`, see [`HdlcDecoder`]
+    pub fn new(serial: S, buf: B) -> Self {
+        let config = HdlcConfig::default();
+        Self {
+            serial,
+            decoder: HdlcDecoder::new(buf, config.fcs, config.rx_accm),
+            config,
+        }
+    }
+
+    pub fn with_config(mut self, config: HdlcConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    pub fn config(&self) -> HdlcConfig {
+        self.config
+    }
+
+    /// Change the framing, e.g. once LCP has negotiated it
+    ///
+    /// A frame partially received is kept, the new configuration applies to its remaining bytes.
+    pub fn set_config(&mut self, config: HdlcConfig) {
+        self.config = config;
+        self.decoder.fcs = config.fcs;
+        self.decoder.accm = config.rx_accm;
+    }
+
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Give back the serial and the buffer
+    pub fn free(self) -> (S, B) {
+        (self.serial, self.decoder.free())
+    }
+
+    /// Send `frame` with its FCS between two flags, waiting for room in the transmitter
+    pub fn send(&mut self, frame: &[u8]) {
+        let serial = &mut self.serial;
+        encode_with(frame, &self.config, |byte| serial.write_byte_blocking(byte));
+    }
+
+    /// Send an abort sequence, ending a frame partially sent by other means
+    pub fn send_abort(&mut self) {
+        self.serial.write_all(&[ESC, FLAG]);
+    }
+
+    fn poll_len(&mut self) -> Option<Result<usize, HdlcError>> {
+        while let Some(byte) = self.serial.read_byte() {
+            if let Some(result) = self.decoder.push_len(byte) {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    /// Decode the bytes available, returning as soon as a frame or an error ends
+    ///
+    /// The frame, without its FCS, stays valid until the next call.
+    pub fn poll(&mut self) -> Option<Result<&[u8], HdlcError>> {
+        let result = self.poll_len()?;
+        Some(result.map(|len| &self.decoder.buf.as_mut()[..len]))
+    }
+
+    /// Wait for the next frame or error, see `poll`
+    pub fn receive(&mut self) -> Result<&[u8], HdlcError> {
+        loop {
+            if let Some(result) = self.poll_len() {
+                return result.map(|len| &self.decoder.buf.as_mut()[..len]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::FakeSerial;
+    use std::vec::Vec;
+
+    const LCP: &[u8] = include_bytes!("../tests/hdlc/lcp.bin");
+    const IP_FCS32: &[u8] = include_bytes!("../tests/hdlc/ip-fcs32.bin");
+    const ERRORS: &[u8] = include_bytes!("../tests/hdlc/errors.bin");
+
+    const LCP_FRAME: &[u8] = &[
+        0xff, 0x03, 0xc0, 0x21, 0x01, 0x01, 0x00, 0x14, 0x01, 0x04, 0x05, 0xdc, 0x02, 0x06, 0x00,
+        0x00, 0x00, 0x00, 0x05, 0x06, 0x12, 0x34, 0x56, 0x78,
+    ];
+    const IP_FRAME: &[u8] = &[
+        0xff, 0x03, 0x00, 0x21, 0x45, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00,
+        0x00, 0x7f, 0x00, 0x00, 0x01, 0x7f, 0x00, 0x00, 0x01, 0x1f, 0x90, 0x1f, 0x90, 0x00, 0x08,
+        0x00, 0x00, 0x7e, 0x7d, 0x11, 0x03,
+    ];
+
+    const FCS32_ACCM0: HdlcConfig = HdlcConfig {
+        fcs: Fcs::Fcs32,
+        tx_accm: 0,
+        rx_accm: 0,
+    };
+
+    #[test]
+    fn reference_streams() {
+        let mut link = Hdlc::new(FakeSerial::new(LCP), [0; 64]);
+        assert_eq!(link.receive(), Ok(LCP_FRAME));
+        assert_eq!(link.poll(), None);
+
+        let mut link = Hdlc::new(FakeSerial::new(IP_FCS32), [0; 64]).with_config(FCS32_ACCM0);
+        assert_eq!(link.receive(), Ok(IP_FRAME));
+
+        let mut link = Hdlc::new(FakeSerial::new(ERRORS), [0; 64]);
+        assert_eq!(link.receive(), Err(HdlcError::Aborted));
+        assert_eq!(link.receive(), Err(HdlcError::BadFcs));
+        assert_eq!(link.receive(), Err(HdlcError::Runt));
+        assert_eq!(link.receive(), Ok(LCP_FRAME));
+    }
+
+    #[test]
+    fn encode_matches_reference_streams() {
+        let mut out = [0; 64];
+        let config = HdlcConfig::default();
+        assert_eq!(encode(LCP_FRAME, &config, &mut out), Some(LCP.len()));
+        assert_eq!(&out[..LCP.len()], LCP);
+        assert_eq!(encode(LCP_FRAME, &config, &mut out[..LCP.len() - 1]), None);
+
+        let mut link = Hdlc::new(FakeSerial::default(), [0; 8]).with_config(FCS32_ACCM0);
+        link.send(IP_FRAME);
+        assert_eq!(link.free().0.output, IP_FCS32);
+    }
+
+    #[test]
+    fn accm_and_oversize() {
+        let config = HdlcConfig {
+            tx_accm: 1 << 0x11 | 1 << 0x13,
+            rx_accm: 1 << 0x11 | 1 << 0x13,
+            ..HdlcConfig::default()
+        };
+        let mut framed = [0; 16];
+        let len = encode(&[0x11, 0x13, 0x01], &config, &mut framed).unwrap();
+        assert_eq!(&framed[1..6], [ESC, 0x31, ESC, 0x33, 0x01]);
+
+        // An XOFF inserted by a modem along the way is dropped, escaped ones are kept
+        let mut line: Vec<u8> = framed[..len].to_vec();
+        line.insert(3, 0x13);
+        let mut link = Hdlc::new(FakeSerial::new(&line), [0; 8]).with_config(config);
+        assert_eq!(link.receive(), Ok(&[0x11, 0x13, 0x01][..]));
+
+        let mut decoder = HdlcDecoder::new([0; 2], Fcs::Fcs16, !0);
+        for byte in [FLAG, 0x41, 0x42, 0x43] {
+            assert_eq!(decoder.push(byte), None);
+        }
+        assert_eq!(
+            decoder.push(FLAG),
+            Some(Err(HdlcError::Oversize { len: 3 }))
+        );
+    }
+}
@@ -0,0 +1,49 @@
+/*!
+# uart_ext
+
+This crate provide driver-independent helpers on top of the other crates in uart-rs.
+
+Each driver crate is an optional dependency, enabled by default through the features `uart8250`,
+`uart_xilinx` and `uart_sifive`.
+*/
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod burst;
+pub mod cobs;
+mod crc;
+pub mod dma;
+#[cfg(feature = "fdt")]
+pub mod fdt;
+pub mod group;
+pub mod hdlc;
+pub mod line_discipline;
+pub mod modbus;
+pub mod rsp;
+pub mod serial;
+pub mod slip;
+pub mod timeout;
+pub mod transfer;
+pub mod uart;
+pub mod xmodem;
+pub mod xonxoff;
+pub mod zmodem;
+
+pub use burst::BurstReceiver;
+pub use cobs::PacketUart;
+pub use dma::TxQueue;
+pub use group::UartGroup;
+pub use hdlc::Hdlc;
+pub use line_discipline::LineDiscipline;
+pub use modbus::ModbusRtu;
+pub use rsp::Rsp;
+pub use serial::Serial;
+pub use slip::Slip;
+pub use timeout::Monotonic;
+pub use uart::Uart;
+pub use xmodem::Xmodem;
+pub use xonxoff::XonXoff;
+pub use zmodem::Zmodem;
@@ -0,0 +1,367 @@
+/*!
+# line_discipline
+
+Cooked-mode terminal input and output over any [`Serial`], in the spirit of the Linux tty line
+discipline.
+
+```ignore
+let mut tty = LineDiscipline::new(uart).with_signal_handler(|signal| {
+    if signal == Signal::Interrupt {
+        abort_command();
+    }
+});
+let mut line = [0; 128];
+match tty.read_line(&mut line) {
+    Ok(len) => run(&line[..len]),
+    Err(LineError::EndOfFile) => logout(),
+    Err(LineError::Interrupted) => {}
+}
+```
+*/
+
+use core::fmt;
+
+use bitflags::bitflags;
+
+use crate::serial::{Serial, WouldBlock};
+
+/// Ctrl-C
+const VINTR: u8 = 0x03;
+/// Ctrl-D
+const VEOF: u8 = 0x04;
+/// Ctrl-H
+const BS: u8 = 0x08;
+/// Ctrl-U
+const VKILL: u8 = 0x15;
+const DEL: u8 = 0x7f;
+const BEL: u8 = 0x07;
+const CR: u8 = b'\r';
+const NL: u8 = b'\n';
+
+bitflags! {
+    /// Modes of a `LineDiscipline`, named after their `termios` counterparts
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LineFlags: u8 {
+        /// Canonical mode: input is edited and delivered a line at a time
+        const ICANON = 1 << 0;
+        /// Echo received characters back
+        const ECHO = 1 << 1;
+        /// Translate a received CR into NL
+        const ICRNL = 1 << 2;
+        /// Translate NL into CR NL on output
+        const ONLCR = 1 << 3;
+        /// Raise `Signal::Interrupt` on Ctrl-C
+        const ISIG = 1 << 4;
+    }
+}
+
+impl Default for LineFlags {
+    /// Canonical mode with echo, CR to NL on input, NL to CR NL on output and signals
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Signals raised by control characters
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Signal {
+    /// Ctrl-C, when `ISIG` is set
+    Interrupt,
+    /// Ctrl-D at the start of a line in canonical mode
+    EndOfFile,
+}
+
+/// Why `read_line` returned without a line
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LineError {
+    /// Ctrl-C discarded the line
+    Interrupted,
+    /// Ctrl-D on an empty line
+    EndOfFile,
+}
+
+/// # Line discipline
+///
+/// Wraps a [`Serial`] and applies the [`LineFlags`] to everything read and written through it.
+///
+/// In canonical mode, BS and DEL erase the last character and Ctrl-U the whole line. A line ends
+/// with NL, or with Ctrl-D when it is not empty, in which case no NL is stored. Characters that
+/// don't fit in the caller's buffer are dropped and answered with BEL.
+pub struct LineDiscipline<S, H = fn(Signal)> {
+    serial: S,
+    flags: LineFlags,
+    handler: H,
+    /// Length of the line being edited, kept across calls to `poll_line`
+    len: usize,
+}
+
+impl<S: Serial> LineDiscipline<S> {
+    /// Wrap `serial` with the default flags and no signal handler
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            flags: LineFlags::default(),
+            handler: |_| {},
+            len: 0,
+        }
+    }
+}
+
+impl<S: Serial, H: FnMut(Signal)> LineDiscipline<S, H> {
+    /// Call `handler` on Ctrl-C and Ctrl-D
+    pub fn with_signal_handler<F: FnMut(Signal)>(self, handler: F) -> LineDiscipline<S, F> {
+        LineDiscipline {
+            serial: self.serial,
+            flags: self.flags,
+            handler,
+            len: self.len,
+        }
+    }
+
+    /// Get the current flags
+    pub fn flags(&self) -> LineFlags {
+        self.flags
+    }
+
+    /// Set new flags, discarding any partially edited line
+    pub fn set_flags(&mut self, flags: LineFlags) {
+        self.flags = flags;
+        self.len = 0;
+    }
+
+    /// Get a mutable reference to the wrapped `Serial`
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Unwrap the `Serial`
+    pub fn free(self) -> S {
+        self.serial
+    }
+
+    /// Write `bytes`, applying `ONLCR`
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.output(*byte);
+        }
+    }
+
+    fn output(&mut self, byte: u8) {
+        if byte == NL && self.flags.contains(LineFlags::ONLCR) {
+            self.serial.write_byte_blocking(CR);
+        }
+        self.serial.write_byte_blocking(byte);
+    }
+
+    fn echo(&mut self, byte: u8) {
+        if self.flags.contains(LineFlags::ECHO) {
+            self.output(byte);
+        }
+    }
+
+    fn echo_erase(&mut self) {
+        if self.flags.contains(LineFlags::ECHO) {
+            self.serial.write_all(b"\x08 \x08");
+        }
+    }
+
+    /// Read a byte, applying `ICRNL`
+    fn input(&mut self) -> Option<u8> {
+        match self.serial.read_byte()? {
+            CR if self.flags.contains(LineFlags::ICRNL) => Some(NL),
+            byte => Some(byte),
+        }
+    }
+
+    /// Raw mode read: whatever is available, without editing
+    ///
+    /// `ICRNL`, `ECHO` and `ISIG` still apply. Returns how many bytes were read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < buf.len() {
+            let byte = match self.input() {
+                Some(byte) => byte,
+                None => break,
+            };
+            if byte == VINTR && self.flags.contains(LineFlags::ISIG) {
+                (self.handler)(Signal::Interrupt);
+                continue;
+            }
+            self.echo(byte);
+            buf[count] = byte;
+            count += 1;
+        }
+        count
+    }
+
+    /// Process the bytes available without waiting
+    ///
+    /// Returns `None` while the line is incomplete; the partial line stays in `buf`, which must be
+    /// passed again on the next call. In raw mode, this returns as soon as any byte is read.
+    pub fn poll_line(&mut self, buf: &mut [u8]) -> Option<Result<usize, LineError>> {
+        if !self.flags.contains(LineFlags::ICANON) {
+            return match self.read(buf) {
+                0 => None,
+                count => Some(Ok(count)),
+            };
+        }
+
+        while let Some(byte) = self.input() {
+            match byte {
+                VINTR if self.flags.contains(LineFlags::ISIG) => {
+                    self.len = 0;
+                    if self.flags.contains(LineFlags::ECHO) {
+                        self.serial.write_all(b"^C");
+                        self.output(NL);
+                    }
+                    (self.handler)(Signal::Interrupt);
+                    return Some(Err(LineError::Interrupted));
+                }
+                VEOF if self.len == 0 => {
+                    (self.handler)(Signal::EndOfFile);
+                    return Some(Err(LineError::EndOfFile));
+                }
+                VEOF => return Some(Ok(core::mem::take(&mut self.len))),
+                BS | DEL => {
+                    if self.len > 0 {
+                        self.len -= 1;
+                        self.echo_erase();
+                    }
+                }
+                VKILL => {
+                    while self.len > 0 {
+                        self.len -= 1;
+                        self.echo_erase();
+                    }
+                }
+                NL => {
+                    // Room for NL is always left, see below
+                    let len = core::mem::take(&mut self.len);
+                    self.echo(NL);
+                    return Some(Ok(match buf.get_mut(len) {
+                        Some(slot) => {
+                            *slot = NL;
+                            len + 1
+                        }
+                        None => 0,
+                    }));
+                }
+                byte if self.len + 1 < buf.len() => {
+                    buf[self.len] = byte;
+                    self.len += 1;
+                    self.echo(byte);
+                }
+                _ => self.echo_full(),
+            }
+        }
+        None
+    }
+
+    fn echo_full(&mut self) {
+        if self.flags.contains(LineFlags::ECHO) {
+            self.serial.write_byte_blocking(BEL);
+        }
+    }
+
+    /// Wait for a whole line and return its length, NL included
+    ///
+    /// See `poll_line`.
+    pub fn read_line(&mut self, buf: &mut [u8]) -> Result<usize, LineError> {
+        loop {
+            if let Some(result) = self.poll_line(buf) {
+                return result;
+            }
+        }
+    }
+}
+
+impl<S: Serial, H: FnMut(Signal)> fmt::Write for LineDiscipline<S, H> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<S: Serial, H> Serial for LineDiscipline<S, H> {
+    /// Reads bypass the line discipline
+    fn read_byte(&mut self) -> Option<u8> {
+        self.serial.read_byte()
+    }
+
+    /// Writes bypass the line discipline
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        self.serial.write_byte(byte)
+    }
+
+    fn flush(&mut self) {
+        self.serial.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::FakeSerial;
+    use core::fmt::Write;
+    use std::vec::Vec;
+
+    #[test]
+    fn canonical() {
+        let mut tty = LineDiscipline::new(FakeSerial::new(b"lz\x7fs -a\rxyz\x15pwd\n"));
+        let mut buf = [0; 16];
+
+        assert_eq!(tty.read_line(&mut buf), Ok(6));
+        assert_eq!(&buf[..6], b"ls -a\n");
+        assert_eq!(tty.read_line(&mut buf), Ok(4));
+        assert_eq!(&buf[..4], b"pwd\n");
+        assert_eq!(
+            tty.free().output,
+            b"lz\x08 \x08s -a\r\nxyz\x08 \x08\x08 \x08\x08 \x08pwd\r\n"
+        );
+    }
+
+    #[test]
+    fn partial_and_full_line() {
+        let mut tty = LineDiscipline::new(FakeSerial::new(b"abc"));
+        let mut buf = [0; 4];
+
+        assert_eq!(tty.poll_line(&mut buf), None);
+        tty.serial_mut().input.extend(b"de\n");
+        // Only 3 characters fit along with NL
+        assert_eq!(tty.poll_line(&mut buf), Some(Ok(4)));
+        assert_eq!(&buf, b"abc\n");
+        assert_eq!(tty.free().output, b"abc\x07\x07\r\n");
+    }
+
+    #[test]
+    fn signals() {
+        let mut signals = Vec::new();
+        let mut tty = LineDiscipline::new(FakeSerial::new(b"sleep\x03\x04ab\x04"))
+            .with_signal_handler(|signal| signals.push(signal));
+        let mut buf = [0; 16];
+
+        assert_eq!(tty.read_line(&mut buf), Err(LineError::Interrupted));
+        assert_eq!(tty.read_line(&mut buf), Err(LineError::EndOfFile));
+        // Ctrl-D ends a non-empty line without NL
+        assert_eq!(tty.read_line(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"ab");
+        assert!(tty.free().output.starts_with(b"sleep^C\r\n"));
+        assert_eq!(signals, [Signal::Interrupt, Signal::EndOfFile]);
+    }
+
+    #[test]
+    fn raw() {
+        let mut tty = LineDiscipline::new(FakeSerial::new(b"a\x7f\r\x15"));
+        tty.set_flags(LineFlags::ONLCR);
+        let mut buf = [0; 16];
+
+        assert_eq!(tty.read(&mut buf), 4);
+        assert_eq!(&buf[..4], b"a\x7f\r\x15");
+        assert_eq!(tty.poll_line(&mut buf), None);
+
+        write!(tty, "1\n2").unwrap();
+        assert_eq!(tty.free().output, b"1\r\n2");
+    }
+}
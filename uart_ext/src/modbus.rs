@@ -0,0 +1,934 @@
+/*!
+# modbus
+
+Modbus RTU over any [`Serial`]: frames delimited by silent intervals derived from the baud rate
+and character format, checked with a CRC-16, with a master issuing requests and a slave
+dispatching them to a [`Handler`].
+
+```ignore
+let timing = RtuTiming::from_uart8250(&uart, 1_843_200).unwrap();
+let mut bus = ModbusRtu::new(uart, clock, timing);
+let mut registers = [0; 4];
+bus.read_holding_registers(17, 0x6b, &mut registers)?;
+```
+
+Frames end after 3.5 character times of silence. By default this is measured with the clock,
+so the bus must be polled at least once per character time, and a gap of more than 1.5
+characters inside a frame is an error. With a 16550, [`FrameEnd::CharTimeout`] ends frames on
+the character timeout interrupt instead, see [`ModbusRtu::handle_interrupt`].
+
+## REF
+
+- [MODBUS over Serial Line Specification and Implementation Guide V1.02](https://modbus.org/docs/Modbus_over_serial_line_V1_02.pdf)
+- [MODBUS Application Protocol Specification V1.1b3](https://modbus.org/docs/Modbus_Application_Protocol_V1_1b3.pdf)
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+#[cfg(feature = "uart8250")]
+use uart8250::{InterruptType, MmioUart8250, Parity, StopBits};
+
+use crate::crc::crc16_modbus;
+use crate::serial::Serial;
+use crate::timeout::{Deadline, Monotonic};
+
+/// Largest RTU frame: address, PDU of up to 253 bytes and CRC
+pub const MAX_ADU: usize = 256;
+/// Largest PDU: function code and data
+pub const MAX_PDU: usize = 253;
+/// Unit address of broadcast requests, which get no response
+pub const BROADCAST: u8 = 0;
+
+const READ_COILS: u8 = 0x01;
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Bit set in the function code of exception responses
+const EXCEPTION: u8 = 0x80;
+
+/// # Silent intervals of Modbus RTU
+///
+/// Above 19200 baud, the specification fixes them at 750 µs and 1750 µs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RtuTiming {
+    /// Time to send one character
+    pub char_us: u32,
+    /// Longest silence allowed between two characters of a frame, 1.5 characters
+    pub inter_char_us: u32,
+    /// Silence ending a frame, 3.5 characters
+    pub inter_frame_us: u32,
+}
+
+impl RtuTiming {
+    /// Timing for `baud_rate` with `bits_per_char` bits per character, start and stop bits
+    /// included, e.g. 11 for 8E1
+    ///
+    /// # Panics
+    ///
+    /// If `baud_rate` is 0.
+    pub fn new(baud_rate: u32, bits_per_char: u32) -> Self {
+        let bits_us = |tenths: u32| (bits_per_char * tenths * 100_000).div_ceil(baud_rate);
+        if baud_rate > 19200 {
+            Self {
+                char_us: bits_us(10),
+                inter_char_us: 750,
+                inter_frame_us: 1750,
+            }
+        } else {
+            Self {
+                char_us: bits_us(10),
+                inter_char_us: bits_us(15),
+                inter_frame_us: bits_us(35),
+            }
+        }
+    }
+
+    /// Timing of the baud rate and character format `uart` is set to, with `clock` its input
+    /// clock
+    ///
+    /// 1.5 stop bits are counted as 2. Returns `None` if the divisor latch is 0 or too large
+    /// for `clock`, i.e. the baud rate works out as 0.
+    #[cfg(feature = "uart8250")]
+    pub fn from_uart8250(uart: &MmioUart8250, clock: usize) -> Option<Self> {
+        let baud_rate = uart
+            .get_baud_rate(clock)
+            .filter(|&baud_rate| baud_rate != 0)?;
+        let format = uart.frame_format();
+        let parity = match format.parity {
+            Parity::No => 0,
+            _ => 1,
+        };
+        let stop_bits = match format.stop_bits {
+            StopBits::One => 1,
+            StopBits::OnePointFive | StopBits::Two => 2,
+        };
+        let bits = 1 + format.data_bits.bits() + parity + stop_bits;
+        Some(Self::new(baud_rate as u32, bits as u32))
+    }
+}
+
+/// How the end of a frame is detected
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FrameEnd {
+    /// 3.5 characters of silence measured with the clock, and 1.5 characters between characters
+    /// enforced
+    Silence,
+    /// The 16550 character timeout interrupt, reported through `char_timeout`
+    ///
+    /// Bytes are read from the FIFO in bursts, so gaps between characters can't be checked. The
+    /// silence is still measured, as no timeout occurs when a frame ends with a full FIFO
+    /// trigger level.
+    CharTimeout,
+}
+
+/// A frame that could not be received
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RtuError {
+    /// The CRC doesn't match
+    BadCrc,
+    /// Silence of more than 1.5 characters inside the frame
+    Gap,
+    /// The frame is longer than `MAX_ADU`
+    Overflow,
+    /// The frame is too short to hold an address, a function code and a CRC
+    TooShort,
+}
+
+/// Exception codes of Modbus responses
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Exception {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    Other(u8),
+}
+
+impl From<u8> for Exception {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => Self::IllegalFunction,
+            2 => Self::IllegalDataAddress,
+            3 => Self::IllegalDataValue,
+            4 => Self::ServerDeviceFailure,
+            5 => Self::Acknowledge,
+            6 => Self::ServerDeviceBusy,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl From<Exception> for u8 {
+    fn from(exception: Exception) -> u8 {
+        match exception {
+            Exception::IllegalFunction => 1,
+            Exception::IllegalDataAddress => 2,
+            Exception::IllegalDataValue => 3,
+            Exception::ServerDeviceFailure => 4,
+            Exception::Acknowledge => 5,
+            Exception::ServerDeviceBusy => 6,
+            Exception::Other(code) => code,
+        }
+    }
+}
+
+/// Why a master request failed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ModbusError {
+    /// No response before the response timeout
+    Timeout,
+    /// The response could not be received
+    Frame(RtuError),
+    /// The slave answered with an exception
+    Exception(Exception),
+    /// The response doesn't match the request
+    UnexpectedResponse,
+}
+
+impl From<RtuError> for ModbusError {
+    fn from(error: RtuError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+impl Display for ModbusError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("timeout"),
+            Self::Frame(RtuError::BadCrc) => f.write_str("bad CRC"),
+            Self::Frame(RtuError::Gap) => f.write_str("gap inside frame"),
+            Self::Frame(RtuError::Overflow) => f.write_str("frame too long"),
+            Self::Frame(RtuError::TooShort) => f.write_str("frame too short"),
+            Self::Exception(exception) => write!(f, "exception {:?}", exception),
+            Self::UnexpectedResponse => f.write_str("unexpected response"),
+        }
+    }
+}
+
+/// # Data model of a Modbus slave
+///
+/// Every access is refused with `Exception::IllegalFunction` unless implemented. Requests on
+/// several items call these once per item, in order, and stop at the first exception.
+pub trait Handler {
+    fn read_coil(&mut self, _address: u16) -> Result<bool, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_discrete_input(&mut self, _address: u16) -> Result<bool, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_holding_register(&mut self, _address: u16) -> Result<u16, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_input_register(&mut self, _address: u16) -> Result<u16, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    fn write_coil(&mut self, _address: u16, _value: bool) -> Result<(), Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    fn write_register(&mut self, _address: u16, _value: u16) -> Result<(), Exception> {
+        Err(Exception::IllegalFunction)
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for &mut H {
+    fn read_coil(&mut self, address: u16) -> Result<bool, Exception> {
+        (**self).read_coil(address)
+    }
+
+    fn read_discrete_input(&mut self, address: u16) -> Result<bool, Exception> {
+        (**self).read_discrete_input(address)
+    }
+
+    fn read_holding_register(&mut self, address: u16) -> Result<u16, Exception> {
+        (**self).read_holding_register(address)
+    }
+
+    fn read_input_register(&mut self, address: u16) -> Result<u16, Exception> {
+        (**self).read_input_register(address)
+    }
+
+    fn write_coil(&mut self, address: u16, value: bool) -> Result<(), Exception> {
+        (**self).write_coil(address, value)
+    }
+
+    fn write_register(&mut self, address: u16, value: u16) -> Result<(), Exception> {
+        (**self).write_register(address, value)
+    }
+}
+
+fn be16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// Addresses `start..start + count`, if `count` is within `1..=max` and they don't wrap
+fn range(start: u16, count: u16, max: u16) -> Result<impl Iterator<Item = u16>, Exception> {
+    if count == 0 || count > max {
+        return Err(Exception::IllegalDataValue);
+    }
+    let end = start
+        .checked_add(count - 1)
+        .ok_or(Exception::IllegalDataAddress)?;
+    Ok(start..=end)
+}
+
+/// Execute the request `pdu` on `handler`, writing the response PDU to `response`
+fn dispatch<H: Handler>(
+    handler: &mut H,
+    pdu: &[u8],
+    response: &mut [u8; MAX_PDU],
+) -> Result<usize, Exception> {
+    let function = pdu[0];
+    let data = &pdu[1..];
+    response[0] = function;
+    match function {
+        READ_COILS | READ_DISCRETE_INPUTS if data.len() == 4 => {
+            let count = be16(&data[2..]);
+            let mut len = 2;
+            for (i, address) in range(be16(data), count, 2000)?.enumerate() {
+                let bit = match function {
+                    READ_COILS => handler.read_coil(address)?,
+                    _ => handler.read_discrete_input(address)?,
+                };
+                if i % 8 == 0 {
+                    response[len] = 0;
+                    len += 1;
+                }
+                response[len - 1] |= (bit as u8) << (i % 8);
+            }
+            response[1] = (len - 2) as u8;
+            Ok(len)
+        }
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS if data.len() == 4 => {
+            let count = be16(&data[2..]);
+            let mut len = 2;
+            for address in range(be16(data), count, 125)? {
+                let value = match function {
+                    READ_HOLDING_REGISTERS => handler.read_holding_register(address)?,
+                    _ => handler.read_input_register(address)?,
+                };
+                response[len..len + 2].copy_from_slice(&value.to_be_bytes());
+                len += 2;
+            }
+            response[1] = (len - 2) as u8;
+            Ok(len)
+        }
+        WRITE_SINGLE_COIL if data.len() == 4 => {
+            let value = match be16(&data[2..]) {
+                0xff00 => true,
+                0x0000 => false,
+                _ => return Err(Exception::IllegalDataValue),
+            };
+            handler.write_coil(be16(data), value)?;
+            response[..5].copy_from_slice(pdu);
+            Ok(5)
+        }
+        WRITE_SINGLE_REGISTER if data.len() == 4 => {
+            handler.write_register(be16(data), be16(&data[2..]))?;
+            response[..5].copy_from_slice(pdu);
+            Ok(5)
+        }
+        WRITE_MULTIPLE_COILS if data.len() >= 5 => {
+            let count = be16(&data[2..]);
+            let values = &data[5..];
+            if data[4] as usize != values.len() || values.len() != (count as usize).div_ceil(8) {
+                return Err(Exception::IllegalDataValue);
+            }
+            for (i, address) in range(be16(data), count, 1968)?.enumerate() {
+                handler.write_coil(address, values[i / 8] & 1 << (i % 8) != 0)?;
+            }
+            response[1..5].copy_from_slice(&data[..4]);
+            Ok(5)
+        }
+        WRITE_MULTIPLE_REGISTERS if data.len() >= 5 => {
+            let count = be16(&data[2..]);
+            let values = &data[5..];
+            if data[4] as usize != values.len() || values.len() != count as usize * 2 {
+                return Err(Exception::IllegalDataValue);
+            }
+            for (address, value) in range(be16(data), count, 123)?.zip(values.chunks(2)) {
+                handler.write_register(address, be16(value))?;
+            }
+            response[1..5].copy_from_slice(&data[..4]);
+            Ok(5)
+        }
+        READ_COILS..=WRITE_SINGLE_REGISTER | WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS => {
+            Err(Exception::IllegalDataValue)
+        }
+        _ => Err(Exception::IllegalFunction),
+    }
+}
+
+/// # Modbus RTU master or slave over a [`Serial`]
+pub struct ModbusRtu<S, M> {
+    serial: S,
+    clock: M,
+    timing: RtuTiming,
+    frame_end: FrameEnd,
+    response_timeout_us: u64,
+    buf: [u8; MAX_ADU],
+    len: usize,
+    gap: bool,
+    /// When the last byte of the frame being received arrived
+    last_rx_us: Option<u64>,
+    /// When the line was last busy, in either direction
+    last_activity_us: u64,
+}
+
+impl<S: Serial, M: Monotonic> ModbusRtu<S, M> {
+    /// Frames end on silence, and a master waits 1 s for responses
+    pub fn new(serial: S, mut clock: M, timing: RtuTiming) -> Self {
+        let now = clock.now_us();
+        Self {
+            serial,
+            clock,
+            timing,
+            frame_end: FrameEnd::Silence,
+            response_timeout_us: 1_000_000,
+            buf: [0; MAX_ADU],
+            len: 0,
+            gap: false,
+            last_rx_us: None,
+            last_activity_us: now,
+        }
+    }
+
+    pub fn with_frame_end(mut self, frame_end: FrameEnd) -> Self {
+        self.frame_end = frame_end;
+        self
+    }
+
+    /// How long a master waits for a response
+    pub fn with_response_timeout(mut self, timeout_us: u64) -> Self {
+        self.response_timeout_us = timeout_us;
+        self
+    }
+
+    pub fn timing(&self) -> RtuTiming {
+        self.timing
+    }
+
+    /// Change the timing, e.g. after changing the baud rate
+    pub fn set_timing(&mut self, timing: RtuTiming) {
+        self.timing = timing;
+    }
+
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Give back the serial and the clock
+    pub fn free(self) -> (S, M) {
+        (self.serial, self.clock)
+    }
+
+    /// Read the bytes available into the frame being received
+    fn receive_available(&mut self) {
+        while let Some(byte) = self.serial.read_byte() {
+            let now = self.clock.now_us();
+            if let Some(last) = self.last_rx_us {
+                let silence = now.wrapping_sub(last);
+                if self.frame_end == FrameEnd::Silence
+                    && silence > self.timing.inter_char_us as u64
+                    && silence < self.timing.inter_frame_us as u64
+                {
+                    self.gap = true;
+                }
+            }
+            if let Some(slot) = self.buf.get_mut(self.len) {
+                *slot = byte;
+            }
+            self.len += 1;
+            self.last_rx_us = Some(now);
+            self.last_activity_us = now;
+        }
+    }
+
+    /// End the frame being received, returning its length without the CRC
+    fn end_frame(&mut self) -> Option<Result<usize, RtuError>> {
+        self.last_rx_us.take()?;
+        let len = core::mem::take(&mut self.len);
+        let gap = core::mem::take(&mut self.gap);
+        Some(if len > MAX_ADU {
+            Err(RtuError::Overflow)
+        } else if len < 4 {
+            Err(RtuError::TooShort)
+        } else if gap {
+            Err(RtuError::Gap)
+        } else if crc16_modbus(0xffff, &self.buf[..len]) != 0 {
+            Err(RtuError::BadCrc)
+        } else {
+            Ok(len - 2)
+        })
+    }
+
+    fn poll_len(&mut self) -> Option<Result<usize, RtuError>> {
+        self.receive_available();
+        let last = self.last_rx_us?;
+        if self.clock.now_us().wrapping_sub(last) >= self.timing.inter_frame_us as u64 {
+            return self.end_frame();
+        }
+        None
+    }
+
+    /// Receive the bytes available, returning a frame once 3.5 characters of silence end it
+    ///
+    /// The frame, address included and CRC excluded, stays valid until the next call.
+    pub fn poll_frame(&mut self) -> Option<Result<&[u8], RtuError>> {
+        let result = self.poll_len()?;
+        Some(result.map(|len| &self.buf[..len]))
+    }
+
+    /// Receive the bytes left and end the frame, on a character timeout interrupt
+    pub fn char_timeout(&mut self) -> Option<Result<&[u8], RtuError>> {
+        self.receive_available();
+        let result = self.end_frame()?;
+        Some(result.map(|len| &self.buf[..len]))
+    }
+
+    /// Send `pdu` to `unit` once the line has been silent for 3.5 characters, and wait until it
+    /// has left the transmitter
+    pub fn send(&mut self, unit: u8, pdu: &[u8]) {
+        while self.clock.now_us().wrapping_sub(self.last_activity_us)
+            < self.timing.inter_frame_us as u64
+        {}
+        let crc = crc16_modbus(crc16_modbus(0xffff, &[unit]), pdu);
+        self.serial.write_byte_blocking(unit);
+        self.serial.write_all(pdu);
+        self.serial.write_all(&crc.to_le_bytes());
+        self.serial.flush();
+        self.last_activity_us = self.clock.now_us();
+    }
+
+    /// Send a request and wait for the response from the same unit
+    ///
+    /// Returns the length of the response PDU, which starts at `self.buf[1]`. Broadcast requests
+    /// return right away with 0.
+    fn transact(&mut self, unit: u8, pdu: &[u8]) -> Result<usize, ModbusError> {
+        // Whatever was received before the request can't be its response
+        self.receive_available();
+        self.last_rx_us = None;
+        self.len = 0;
+        self.gap = false;
+
+        self.send(unit, pdu);
+        if unit == BROADCAST {
+            return Ok(0);
+        }
+        let deadline = Deadline::new(&mut self.clock, self.response_timeout_us);
+        loop {
+            if let Some(result) = self.poll_len() {
+                let len = result?;
+                if self.buf[0] != unit {
+                    continue;
+                }
+                let response = &self.buf[1..len];
+                return if response[0] == pdu[0] | EXCEPTION && response.len() == 2 {
+                    Err(ModbusError::Exception(response[1].into()))
+                } else if response[0] != pdu[0] {
+                    Err(ModbusError::UnexpectedResponse)
+                } else {
+                    Ok(len - 1)
+                };
+            }
+            if self.last_rx_us.is_none() && deadline.is_expired(&mut self.clock) {
+                return Err(ModbusError::Timeout);
+            }
+        }
+    }
+
+    fn read_bits(
+        &mut self,
+        function: u8,
+        unit: u8,
+        address: u16,
+        out: &mut [bool],
+    ) -> Result<(), ModbusError> {
+        assert!((1..=2000).contains(&out.len()), "Invalid coil count");
+        let mut pdu = [function, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..].copy_from_slice(&(out.len() as u16).to_be_bytes());
+        let len = self.transact(unit, &pdu)?;
+        let bytes = out.len().div_ceil(8);
+        let response = &self.buf[1..1 + len];
+        if len != 2 + bytes || response[1] as usize != bytes {
+            return Err(ModbusError::UnexpectedResponse);
+        }
+        for (i, bit) in out.iter_mut().enumerate() {
+            *bit = response[2 + i / 8] & 1 << (i % 8) != 0;
+        }
+        Ok(())
+    }
+
+    fn read_registers(
+        &mut self,
+        function: u8,
+        unit: u8,
+        address: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        assert!((1..=125).contains(&out.len()), "Invalid register count");
+        let mut pdu = [function, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..].copy_from_slice(&(out.len() as u16).to_be_bytes());
+        let len = self.transact(unit, &pdu)?;
+        let response = &self.buf[1..1 + len];
+        if len != 2 + out.len() * 2 || response[1] as usize != out.len() * 2 {
+            return Err(ModbusError::UnexpectedResponse);
+        }
+        for (value, bytes) in out.iter_mut().zip(response[2..].chunks(2)) {
+            *value = be16(bytes);
+        }
+        Ok(())
+    }
+
+    /// Send a write request, checking the response echoes its first 5 bytes
+    fn write(&mut self, unit: u8, pdu: &[u8]) -> Result<(), ModbusError> {
+        let len = self.transact(unit, pdu)?;
+        if unit != BROADCAST && (len != 5 || self.buf[1..6] != pdu[..5]) {
+            return Err(ModbusError::UnexpectedResponse);
+        }
+        Ok(())
+    }
+
+    /// Read `out.len()` coils from `address`, function 0x01
+    ///
+    /// Panics if `out` holds 0 or more than 2000 coils.
+    pub fn read_coils(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [bool],
+    ) -> Result<(), ModbusError> {
+        self.read_bits(READ_COILS, unit, address, out)
+    }
+
+    /// Read `out.len()` discrete inputs from `address`, function 0x02
+    ///
+    /// Panics if `out` holds 0 or more than 2000 inputs.
+    pub fn read_discrete_inputs(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [bool],
+    ) -> Result<(), ModbusError> {
+        self.read_bits(READ_DISCRETE_INPUTS, unit, address, out)
+    }
+
+    /// Read `out.len()` holding registers from `address`, function 0x03
+    ///
+    /// Panics if `out` holds 0 or more than 125 registers.
+    pub fn read_holding_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        self.read_registers(READ_HOLDING_REGISTERS, unit, address, out)
+    }
+
+    /// Read `out.len()` input registers from `address`, function 0x04
+    ///
+    /// Panics if `out` holds 0 or more than 125 registers.
+    pub fn read_input_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        self.read_registers(READ_INPUT_REGISTERS, unit, address, out)
+    }
+
+    /// Write a coil, function 0x05
+    pub fn write_single_coil(
+        &mut self,
+        unit: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<(), ModbusError> {
+        let mut pdu = [WRITE_SINGLE_COIL, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        if value {
+            pdu[3] = 0xff;
+        }
+        self.write(unit, &pdu)
+    }
+
+    /// Write a holding register, function 0x06
+    pub fn write_single_register(
+        &mut self,
+        unit: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), ModbusError> {
+        let mut pdu = [WRITE_SINGLE_REGISTER, 0, 0, 0, 0];
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..].copy_from_slice(&value.to_be_bytes());
+        self.write(unit, &pdu)
+    }
+
+    /// Write `values.len()` coils from `address`, function 0x0F
+    ///
+    /// Panics if `values` holds 0 or more than 1968 coils.
+    pub fn write_multiple_coils(
+        &mut self,
+        unit: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), ModbusError> {
+        assert!((1..=1968).contains(&values.len()), "Invalid coil count");
+        let bytes = values.len().div_ceil(8);
+        let mut pdu = [0; MAX_PDU];
+        pdu[0] = WRITE_MULTIPLE_COILS;
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu[5] = bytes as u8;
+        for (i, value) in values.iter().enumerate() {
+            pdu[6 + i / 8] |= (*value as u8) << (i % 8);
+        }
+        self.write(unit, &pdu[..6 + bytes])
+    }
+
+    /// Write `values.len()` holding registers from `address`, function 0x10
+    ///
+    /// Panics if `values` holds 0 or more than 123 registers.
+    pub fn write_multiple_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError> {
+        assert!((1..=123).contains(&values.len()), "Invalid register count");
+        let mut pdu = [0; MAX_PDU];
+        pdu[0] = WRITE_MULTIPLE_REGISTERS;
+        pdu[1..3].copy_from_slice(&address.to_be_bytes());
+        pdu[3..5].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu[5] = (values.len() * 2) as u8;
+        for (i, value) in values.iter().enumerate() {
+            pdu[6 + 2 * i..8 + 2 * i].copy_from_slice(&value.to_be_bytes());
+        }
+        self.write(unit, &pdu[..6 + values.len() * 2])
+    }
+
+    /// Answer a request received for `unit` from `handler`
+    fn serve_len<H: Handler>(&mut self, unit: u8, handler: &mut H, len: usize) {
+        let address = self.buf[0];
+        if len < 2 || (address != unit && address != BROADCAST) {
+            return;
+        }
+        let mut response = [0; MAX_PDU];
+        let len = match dispatch(handler, &self.buf[1..len], &mut response) {
+            Ok(len) => len,
+            Err(exception) => {
+                response[0] = self.buf[1] | EXCEPTION;
+                response[1] = exception.into();
+                2
+            }
+        };
+        if address != BROADCAST {
+            self.send(unit, &response[..len]);
+        }
+    }
+
+    /// As slave `unit`, receive the bytes available and answer a complete request with `handler`
+    ///
+    /// Returns `Some(Ok(()))` once a frame has been received, whether it was addressed to `unit`
+    /// or not.
+    pub fn serve<H: Handler>(&mut self, unit: u8, mut handler: H) -> Option<Result<(), RtuError>> {
+        let len = match self.poll_len()? {
+            Ok(len) => len,
+            Err(error) => return Some(Err(error)),
+        };
+        self.serve_len(unit, &mut handler, len);
+        Some(Ok(()))
+    }
+}
+
+#[cfg(feature = "uart8250")]
+impl<M: Monotonic> ModbusRtu<MmioUart8250<'_>, M> {
+    /// Service a 16550 interrupt, returning a frame when it ends
+    ///
+    /// Received data drains the FIFO; with [`FrameEnd::CharTimeout`], a character timeout ends
+    /// the frame. The interrupts must be enabled, and `poll_frame` called as well to catch
+    /// frames ending with a full FIFO trigger level.
+    pub fn handle_interrupt(&mut self) -> Option<Result<&[u8], RtuError>> {
+        match self.serial.read_interrupt_type()? {
+            InterruptType::Timeout if self.frame_end == FrameEnd::CharTimeout => {
+                self.char_timeout()
+            }
+            _ => self.poll_frame(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::{pipe, FakeSerial, StdClock};
+    use std::vec::Vec;
+
+    #[test]
+    fn timing() {
+        // 8E1 at 9600 baud
+        let timing = RtuTiming::new(9600, 11);
+        assert_eq!(timing.char_us, 1146);
+        assert_eq!(timing.inter_char_us, 1719);
+        assert_eq!(timing.inter_frame_us, 4011);
+        assert_eq!(RtuTiming::new(115200, 10).inter_frame_us, 1750);
+    }
+
+    #[cfg(feature = "uart8250")]
+    #[test]
+    fn timing_from_uart8250() {
+        // Divisor 12, 8N1
+        let mut fake_registers: [u8; 8] = [12, 0, 0, 0b0000_0011, 0, 0, 0, 0];
+        let uart = unsafe { MmioUart8250::new(&mut fake_registers as *mut u8 as usize) };
+
+        let timing = RtuTiming::from_uart8250(&uart, 1_843_200).unwrap();
+        assert_eq!(timing, RtuTiming::new(9600, 10));
+        // A clock slower than 16 times the divisor gives 0 baud
+        assert_eq!(RtuTiming::from_uart8250(&uart, 100), None);
+    }
+
+    #[test]
+    fn request_frame() {
+        let timing = RtuTiming::new(115200, 10);
+        let mut bus = ModbusRtu::new(FakeSerial::default(), StdClock::new(), timing)
+            .with_response_timeout(10_000);
+        assert_eq!(
+            bus.read_holding_registers(1, 0, &mut [0; 2]),
+            Err(ModbusError::Timeout)
+        );
+        // The example of the serial line specification
+        assert_eq!(
+            bus.free().0.output,
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xc4, 0x0b]
+        );
+
+        let mut bus = ModbusRtu::new(FakeSerial::default(), StdClock::new(), timing)
+            .with_frame_end(FrameEnd::CharTimeout);
+        bus.serial_mut()
+            .input
+            .extend([0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xc4, 0x0b]);
+        assert_eq!(bus.poll_frame(), None);
+        assert_eq!(
+            bus.char_timeout(),
+            Some(Ok(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x02][..]))
+        );
+        bus.serial_mut()
+            .input
+            .extend([0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xc4, 0x0c]);
+        assert_eq!(bus.char_timeout(), Some(Err(RtuError::BadCrc)));
+        assert_eq!(bus.char_timeout(), None);
+    }
+
+    #[derive(Default)]
+    struct Device {
+        coils: [bool; 16],
+        registers: [u16; 8],
+    }
+
+    impl Handler for Device {
+        fn read_coil(&mut self, address: u16) -> Result<bool, Exception> {
+            self.coils
+                .get(address as usize)
+                .copied()
+                .ok_or(Exception::IllegalDataAddress)
+        }
+
+        fn read_holding_register(&mut self, address: u16) -> Result<u16, Exception> {
+            self.registers
+                .get(address as usize)
+                .copied()
+                .ok_or(Exception::IllegalDataAddress)
+        }
+
+        fn write_coil(&mut self, address: u16, value: bool) -> Result<(), Exception> {
+            let coil = self
+                .coils
+                .get_mut(address as usize)
+                .ok_or(Exception::IllegalDataAddress)?;
+            *coil = value;
+            Ok(())
+        }
+
+        fn write_register(&mut self, address: u16, value: u16) -> Result<(), Exception> {
+            let register = self
+                .registers
+                .get_mut(address as usize)
+                .ok_or(Exception::IllegalDataAddress)?;
+            *register = value;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn master_slave() {
+        let (master, slave) = pipe();
+        let timing = RtuTiming::new(115200, 10);
+        let slave = std::thread::spawn(move || {
+            let mut bus = ModbusRtu::new(slave, StdClock::new(), timing);
+            let mut device = Device::default();
+            let mut frames = 0;
+            // One frame per request below, the one for unit 9 included
+            while frames < 9 {
+                if let Some(result) = bus.serve(17, &mut device) {
+                    result.unwrap();
+                    frames += 1;
+                }
+            }
+            device
+        });
+
+        let mut bus = ModbusRtu::new(master, StdClock::new(), timing);
+        bus.write_multiple_registers(17, 2, &[0x1234, 0x5678])
+            .unwrap();
+        bus.write_single_register(17, 7, 0xbeef).unwrap();
+        let mut registers = [0; 4];
+        bus.read_holding_registers(17, 1, &mut registers).unwrap();
+        assert_eq!(registers, [0, 0x1234, 0x5678, 0]);
+
+        bus.write_multiple_coils(
+            17,
+            3,
+            &[true, false, true, true, false, false, false, false, true],
+        )
+        .unwrap();
+        bus.write_single_coil(17, 0, true).unwrap();
+        let mut coils = [false; 13];
+        bus.read_coils(17, 0, &mut coils).unwrap();
+        let expected: Vec<bool> = [1, 0, 0, 1, 0, 1, 1, 0, 0, 0, 0, 1, 0]
+            .iter()
+            .map(|bit| *bit == 1)
+            .collect();
+        assert_eq!(coils[..], expected[..]);
+
+        assert_eq!(
+            bus.read_holding_registers(17, 6, &mut registers),
+            Err(ModbusError::Exception(Exception::IllegalDataAddress))
+        );
+        assert_eq!(
+            bus.read_input_registers(17, 0, &mut registers),
+            Err(ModbusError::Exception(Exception::IllegalFunction))
+        );
+        bus = bus.with_response_timeout(20_000);
+        assert_eq!(bus.write_single_coil(9, 0, true), Err(ModbusError::Timeout));
+
+        let device = slave.join().unwrap();
+        assert_eq!(device.registers[7], 0xbeef);
+        assert!(device.coils[11]);
+    }
+}
@@ -0,0 +1,710 @@
+/*!
+# rsp
+
+Transport of the GDB Remote Serial Protocol over any [`Serial`]: `$data#xx` packets with their
+acknowledgements, run-length encoding and Ctrl-C interrupts. With the `gdbstub` feature,
+[`GdbConnection`] plugs a driver into the `gdbstub` crate instead, which does its own framing.
+
+```ignore
+let mut gdb = Rsp::new(uart, clock, [0; 4096]);
+loop {
+    match gdb.poll() {
+        Some(Ok(RspEvent::Packet(packet))) => handle(packet),
+        Some(Ok(RspEvent::Interrupt)) => stop(),
+        _ => {}
+    }
+}
+// While the target runs
+if gdb.poll_interrupt() {
+    stop();
+}
+```
+
+## REF
+
+- [GDB Remote Serial Protocol](https://sourceware.org/gdb/current/onlinedocs/gdb.html/Overview.html)
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::serial::Serial;
+use crate::timeout::{Deadline, Monotonic};
+
+/// Starts a packet
+const START: u8 = b'$';
+/// Ends the data of a packet, before the checksum
+const END: u8 = b'#';
+/// Escapes the next byte, XORed with 0x20
+const ESCAPE: u8 = b'}';
+/// Repeats the previous byte, count given by the next one
+const RUN: u8 = b'*';
+/// Ctrl-C, sent by GDB outside packets to stop the target
+pub const INTERRUPT: u8 = 0x03;
+const ACK: u8 = b'+';
+const NACK: u8 = b'-';
+
+/// Run lengths are sent as `count + 29`, a printable character
+const RUN_OFFSET: u8 = 29;
+/// Longest run in one `*`, as 126 is the last printable character
+const MAX_RUN: usize = (126 - RUN_OFFSET) as usize;
+
+/// Something received from GDB
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RspEvent<'a> {
+    /// A packet with a good checksum, unescaped and expanded
+    Packet(&'a [u8]),
+    /// Ctrl-C
+    Interrupt,
+    /// `+`, the last packet was received
+    Ack,
+    /// `-`, the last packet must be sent again
+    Nack,
+}
+
+/// A packet that could not be received or sent
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RspError {
+    /// The checksum doesn't match, or isn't hexadecimal
+    BadChecksum,
+    /// A run length without a previous byte, or with a count below 3
+    BadRunLength,
+    /// The packet didn't fit in the buffer; `len` is its length once expanded
+    Oversize { len: usize },
+    /// GDB didn't acknowledge a packet after `retries` attempts
+    NotAcknowledged,
+}
+
+impl Display for RspError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::BadChecksum => f.write_str("bad checksum"),
+            Self::BadRunLength => f.write_str("bad run length"),
+            Self::Oversize { len } => write!(f, "packet of {} bytes too large", len),
+            Self::NotAcknowledged => f.write_str("packet not acknowledged"),
+        }
+    }
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte, START | END | ESCAPE | RUN)
+}
+
+/// Write `data` as a packet, with run-length encoding if `rle`
+///
+/// Bytes needing an escape are never run-length encoded.
+pub fn write_packet<S: Serial + ?Sized>(serial: &mut S, data: &[u8], rle: bool) {
+    let mut sum = 0u8;
+    let mut emit = |serial: &mut S, byte: u8| {
+        sum = sum.wrapping_add(byte);
+        serial.write_byte_blocking(byte);
+    };
+    serial.write_byte_blocking(START);
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        if needs_escape(byte) {
+            emit(serial, ESCAPE);
+            emit(serial, byte ^ 0x20);
+            continue;
+        }
+        emit(serial, byte);
+        if !rle {
+            continue;
+        }
+        let mut repeats = data[i..].iter().take_while(|next| **next == byte).count();
+        i += repeats;
+        while repeats >= 3 {
+            // Counts of 6 and 7 would be sent as '#' and '$'
+            let count = match repeats.min(MAX_RUN) {
+                6 | 7 => 5,
+                count => count,
+            };
+            emit(serial, RUN);
+            emit(serial, count as u8 + RUN_OFFSET);
+            repeats -= count;
+        }
+        for _ in 0..repeats {
+            emit(serial, byte);
+        }
+    }
+    serial.write_byte_blocking(END);
+    let hex = b"0123456789abcdef";
+    serial.write_byte_blocking(hex[(sum >> 4) as usize]);
+    serial.write_byte_blocking(hex[(sum & 0xf) as usize]);
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    /// Between packets
+    Idle,
+    Data,
+    Escape,
+    Run,
+    /// First checksum digit
+    Checksum,
+    /// Second checksum digit, after the first one's value
+    Checksum2(u8),
+}
+
+/// What `push_len` found, with packets as a length into the buffer
+#[derive(Copy, Clone)]
+enum Raw {
+    Packet(usize),
+    Interrupt,
+    Ack,
+    Nack,
+}
+
+/// # Incremental RSP packet decoder
+///
+/// Fed one byte at a time, it decodes into `B`, a `[u8; N]` or a `&mut [u8]`, whose length is
+/// the largest packet accepted once run lengths are expanded.
+pub struct RspDecoder<B> {
+    buf: B,
+    len: usize,
+    state: State,
+    sum: u8,
+    error: Option<RspError>,
+    /// Check packets without storing them, so the buffer isn't overwritten
+    discard: bool,
+}
+
+impl<B: AsMut<[u8]>> RspDecoder<B> {
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            state: State::Idle,
+            sum: 0,
+            error: None,
+            discard: false,
+        }
+    }
+
+    /// Give back the buffer
+    pub fn free(self) -> B {
+        self.buf
+    }
+
+    /// Whether a packet is partially received
+    pub fn is_idle(&self) -> bool {
+        self.state == State::Idle
+    }
+
+    /// Feed one byte, returning what it completes
+    ///
+    /// The packet stays valid until the next call.
+    pub fn push(&mut self, byte: u8) -> Option<Result<RspEvent<'_>, RspError>> {
+        Some(match self.push_len(byte)? {
+            Ok(Raw::Packet(len)) => Ok(RspEvent::Packet(&self.buf.as_mut()[..len])),
+            Ok(Raw::Interrupt) => Ok(RspEvent::Interrupt),
+            Ok(Raw::Ack) => Ok(RspEvent::Ack),
+            Ok(Raw::Nack) => Ok(RspEvent::Nack),
+            Err(error) => Err(error),
+        })
+    }
+
+    fn store(&mut self, byte: u8) {
+        // Keep counting an oversized packet, so its length can be reported
+        match self.buf.as_mut().get_mut(self.len) {
+            Some(slot) if self.error.is_none() && !self.discard => *slot = byte,
+            Some(_) => {}
+            None => {
+                self.error.get_or_insert(RspError::Oversize { len: 0 });
+            }
+        }
+        self.len += 1;
+    }
+
+    fn push_len(&mut self, byte: u8) -> Option<Result<Raw, RspError>> {
+        match self.state {
+            State::Idle => {
+                return match byte {
+                    START => {
+                        self.state = State::Data;
+                        self.len = 0;
+                        self.sum = 0;
+                        self.error = None;
+                        None
+                    }
+                    INTERRUPT => Some(Ok(Raw::Interrupt)),
+                    ACK => Some(Ok(Raw::Ack)),
+                    NACK => Some(Ok(Raw::Nack)),
+                    // Line noise, or the console output GDB ignores as well
+                    _ => None,
+                };
+            }
+            State::Checksum => {
+                self.state = match hex_digit(byte) {
+                    Some(digit) => State::Checksum2(digit),
+                    None => State::Checksum2(0xff),
+                };
+                return None;
+            }
+            State::Checksum2(high) => {
+                self.state = State::Idle;
+                let expected = hex_digit(byte)
+                    .filter(|_| high != 0xff)
+                    .map(|low| high << 4 | low);
+                return Some(match self.error.take() {
+                    _ if expected != Some(self.sum) => Err(RspError::BadChecksum),
+                    Some(RspError::Oversize { .. }) => Err(RspError::Oversize { len: self.len }),
+                    Some(error) => Err(error),
+                    None => Ok(Raw::Packet(self.len)),
+                });
+            }
+            _ if byte == START => {
+                // GDB gave up on the previous packet
+                self.state = State::Idle;
+                return self.push_len(byte);
+            }
+            _ => {}
+        }
+
+        if byte == END && self.state == State::Data {
+            self.state = State::Checksum;
+            return None;
+        }
+        self.sum = self.sum.wrapping_add(byte);
+        match self.state {
+            State::Data if byte == ESCAPE => self.state = State::Escape,
+            State::Data if byte == RUN => self.state = State::Run,
+            State::Data => self.store(byte),
+            State::Escape => {
+                self.state = State::Data;
+                self.store(byte ^ 0x20);
+            }
+            State::Run => {
+                self.state = State::Data;
+                let previous = self
+                    .len
+                    .checked_sub(1)
+                    .and_then(|i| self.buf.as_mut().get(i));
+                match (previous.copied(), byte.checked_sub(RUN_OFFSET)) {
+                    (Some(previous), Some(count)) if count >= 3 => {
+                        (0..count).for_each(|_| self.store(previous))
+                    }
+                    _ => {
+                        self.error.get_or_insert(RspError::BadRunLength);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        None
+    }
+}
+
+/// # GDB remote serial protocol link
+///
+/// Packets received are acknowledged, or rejected to have GDB send them again, unless no-ack
+/// mode has been negotiated with `QStartNoAckMode`.
+pub struct Rsp<S, M, B> {
+    serial: S,
+    clock: M,
+    decoder: RspDecoder<B>,
+    no_ack: bool,
+    rle: bool,
+    retries: u8,
+    ack_timeout_us: u64,
+    /// A packet received by `poll_interrupt` or `send`, returned by the next `poll`
+    pending: Option<Result<Raw, RspError>>,
+}
+
+impl<S: Serial, M: Monotonic, B: AsMut<[u8]>> Rsp<S, M, B> {
+    /// Packets received are decoded into `buf`, see [`RspDecoder`]
+    pub fn new(serial: S, clock: M, buf: B) -> Self {
+        Self {
+            serial,
+            clock,
+            decoder: RspDecoder::new(buf),
+            no_ack: false,
+            rle: false,
+            retries: 3,
+            ack_timeout_us: 2_000_000,
+            pending: None,
+        }
+    }
+
+    /// Run-length encode the packets sent
+    pub fn with_rle(mut self) -> Self {
+        self.rle = true;
+        self
+    }
+
+    /// How many times a packet is sent before giving up, 3 by default
+    pub fn with_retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait for GDB to acknowledge a packet before sending it again, 2 s by default
+    /// as GDB's `remotetimeout`
+    pub fn with_ack_timeout(mut self, timeout_us: u64) -> Self {
+        self.ack_timeout_us = timeout_us;
+        self
+    }
+
+    /// Stop sending and waiting for acknowledgements, after replying `OK` to `QStartNoAckMode`
+    pub fn set_no_ack(&mut self, no_ack: bool) {
+        self.no_ack = no_ack;
+    }
+
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Give back the serial and the buffer
+    pub fn free(self) -> (S, B) {
+        (self.serial, self.decoder.free())
+    }
+
+    /// Send `data` as a packet, and wait for GDB to acknowledge it
+    ///
+    /// The packet is sent again when GDB rejects it or doesn't answer within the ack timeout.
+    /// A Ctrl-C or a packet received meanwhile is kept for `poll_interrupt` and `poll`; a second
+    /// packet is rejected for GDB to send again later.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), RspError> {
+        for _ in 0..self.retries.max(1) {
+            write_packet(&mut self.serial, data, self.rle);
+            if self.no_ack {
+                return Ok(());
+            }
+            let deadline = Deadline::new(&mut self.clock, self.ack_timeout_us);
+            loop {
+                let byte = match self.serial.read_byte() {
+                    Some(byte) => byte,
+                    None if deadline.is_expired(&mut self.clock) => break,
+                    None => continue,
+                };
+                match self.decode(byte) {
+                    Some(Ok(Raw::Ack)) => return Ok(()),
+                    Some(Ok(Raw::Nack)) => break,
+                    Some(raw) => self.hold(raw),
+                    None => {}
+                }
+            }
+        }
+        Err(RspError::NotAcknowledged)
+    }
+
+    /// Feed a byte to the decoder, without storing packets while one is held for `poll`
+    fn decode(&mut self, byte: u8) -> Option<Result<Raw, RspError>> {
+        self.decoder.discard = matches!(self.pending, Some(Ok(Raw::Packet(_))) | Some(Err(_)));
+        self.decoder.push_len(byte)
+    }
+
+    /// Keep what was received outside `poll` for the next `poll`
+    fn hold(&mut self, raw: Result<Raw, RspError>) {
+        match raw {
+            // A packet waiting is more important than a Ctrl-C, which GDB sends again anyway
+            Ok(Raw::Interrupt) => {
+                if self.pending.is_none() {
+                    self.pending = Some(raw);
+                }
+            }
+            // Stray acknowledgements
+            Ok(Raw::Ack) | Ok(Raw::Nack) => {}
+            // Its data was discarded, have GDB send it again
+            _ if self.decoder.discard => {
+                if !self.no_ack {
+                    self.serial.write_byte_blocking(NACK);
+                }
+            }
+            Ok(Raw::Packet(len)) => {
+                self.acknowledge(&Ok(len));
+                self.pending = Some(raw);
+            }
+            Err(error) => {
+                self.acknowledge(&Err(error));
+                self.pending = Some(raw);
+            }
+        }
+    }
+
+    /// Answer a packet received with `+` or `-`
+    fn acknowledge(&mut self, result: &Result<usize, RspError>) {
+        if self.no_ack {
+            return;
+        }
+        match result {
+            Ok(_) => self.serial.write_byte_blocking(ACK),
+            Err(RspError::BadChecksum) => self.serial.write_byte_blocking(NACK),
+            // The packet made it, but can't be handled; GDB will see it unanswered
+            Err(_) => self.serial.write_byte_blocking(ACK),
+        }
+    }
+
+    /// Receive the bytes available, returning as soon as something completes
+    ///
+    /// `Ack` and `Nack` are only returned outside of `send`. The packet stays valid until the
+    /// next call.
+    pub fn poll(&mut self) -> Option<Result<RspEvent<'_>, RspError>> {
+        let raw = match self.pending.take() {
+            Some(raw) => raw,
+            None => loop {
+                let byte = self.serial.read_byte()?;
+                match self.decode(byte) {
+                    Some(Ok(Raw::Packet(len))) => {
+                        self.acknowledge(&Ok(len));
+                        break Ok(Raw::Packet(len));
+                    }
+                    Some(Err(error)) => {
+                        self.acknowledge(&Err(error));
+                        break Err(error);
+                    }
+                    Some(raw) => break raw,
+                    None => {}
+                }
+            },
+        };
+        Some(match raw {
+            Ok(Raw::Packet(len)) => Ok(RspEvent::Packet(&self.decoder.buf.as_mut()[..len])),
+            Ok(Raw::Interrupt) => Ok(RspEvent::Interrupt),
+            Ok(Raw::Ack) => Ok(RspEvent::Ack),
+            Ok(Raw::Nack) => Ok(RspEvent::Nack),
+            Err(error) => Err(error),
+        })
+    }
+
+    /// Check for Ctrl-C without blocking, while the target runs
+    ///
+    /// Other bytes are decoded as usual; a packet completed here is returned by the next `poll`.
+    pub fn poll_interrupt(&mut self) -> bool {
+        if let Some(Ok(Raw::Interrupt)) = self.pending {
+            self.pending = None;
+            return true;
+        }
+        while self.pending.is_none() {
+            let byte = match self.serial.read_byte() {
+                Some(byte) => byte,
+                None => break,
+            };
+            match self.decode(byte) {
+                Some(Ok(Raw::Interrupt)) => return true,
+                Some(raw) => self.hold(raw),
+                None => {}
+            }
+        }
+        false
+    }
+}
+
+#[cfg(feature = "gdbstub")]
+pub use self::connection::GdbConnection;
+
+#[cfg(feature = "gdbstub")]
+mod connection {
+    use core::convert::Infallible;
+
+    use gdbstub::conn::{Connection, ConnectionExt};
+
+    use super::INTERRUPT;
+    use crate::serial::Serial;
+
+    /// # `gdbstub` connection over a [`Serial`]
+    ///
+    /// `peek` never blocks, so `gdbstub` can check for Ctrl-C while the target runs, as can
+    /// `poll_interrupt`.
+    pub struct GdbConnection<S> {
+        serial: S,
+        peeked: Option<u8>,
+    }
+
+    impl<S: Serial> GdbConnection<S> {
+        pub fn new(serial: S) -> Self {
+            Self {
+                serial,
+                peeked: None,
+            }
+        }
+
+        /// Give back the serial
+        pub fn free(self) -> S {
+            self.serial
+        }
+
+        /// Consume a pending Ctrl-C, without blocking
+        pub fn poll_interrupt(&mut self) -> bool {
+            if self.peek() == Ok(Some(INTERRUPT)) {
+                self.peeked = None;
+                return true;
+            }
+            false
+        }
+    }
+
+    impl<S: Serial> Connection for GdbConnection<S> {
+        type Error = Infallible;
+
+        fn write(&mut self, byte: u8) -> Result<(), Self::Error> {
+            self.serial.write_byte_blocking(byte);
+            Ok(())
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+            self.serial.write_all(buf);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.serial.flush();
+            Ok(())
+        }
+    }
+
+    impl<S: Serial> ConnectionExt for GdbConnection<S> {
+        fn read(&mut self) -> Result<u8, Self::Error> {
+            if let Some(byte) = self.peeked.take() {
+                return Ok(byte);
+            }
+            loop {
+                if let Some(byte) = self.serial.read_byte() {
+                    return Ok(byte);
+                }
+            }
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, Self::Error> {
+            if self.peeked.is_none() {
+                self.peeked = self.serial.read_byte();
+            }
+            Ok(self.peeked)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::{FakeSerial, StdClock};
+    use std::vec::Vec;
+
+    fn packet(rle: bool, data: &[u8]) -> Vec<u8> {
+        let mut serial = FakeSerial::default();
+        write_packet(&mut serial, data, rle);
+        serial.output
+    }
+
+    #[test]
+    fn encode() {
+        // From the GDB manual
+        assert_eq!(packet(false, b"OK"), b"$OK#9a");
+        assert_eq!(packet(true, b"0000"), b"$0* #7a");
+        assert_eq!(packet(false, b"a$}"), b"$a}\x04}]#bc");
+        // Runs of 6 and 7 are split, runs past 97 too
+        assert_eq!(packet(true, b"xxxxxxx"), b"$x*\"x#3c");
+        assert_eq!(packet(true, &[b'f'; 100])[..7], *b"$f*~ff#");
+    }
+
+    #[test]
+    fn decode() {
+        let mut decoder = RspDecoder::new([0; 128]);
+        for data in [
+            &b"OK"[..],
+            b"m1000,4",
+            b"X1000,3:$#}",
+            &[b'0'; 100],
+            b"xxxxxxxxxxx",
+        ] {
+            for rle in [false, true] {
+                let encoded = packet(rle, data);
+                let (last, rest) = encoded.split_last().unwrap();
+                for byte in rest {
+                    assert_eq!(decoder.push(*byte), None);
+                }
+                assert_eq!(decoder.push(*last), Some(Ok(RspEvent::Packet(data))));
+            }
+        }
+
+        let mut feed = |bytes: &[u8]| {
+            let mut last = None;
+            for byte in bytes {
+                last = decoder.push(*byte).map(|event| event.map(|_| ()));
+            }
+            last
+        };
+        assert_eq!(feed(b"$OK#9b"), Some(Err(RspError::BadChecksum)));
+        assert_eq!(feed(b"$OK#zz"), Some(Err(RspError::BadChecksum)));
+        assert_eq!(feed(b"$*%#4f"), Some(Err(RspError::BadRunLength)));
+        assert_eq!(feed(b"$O$OK#9a"), Some(Ok(())));
+        assert_eq!(feed(b"x+"), Some(Ok(())));
+    }
+
+    #[test]
+    fn link() {
+        let mut gdb = Rsp::new(
+            FakeSerial::new(b"+$g#67$g#00\x03"),
+            StdClock::new(),
+            [0; 64],
+        );
+        gdb.send(b"S05").unwrap();
+        assert_eq!(gdb.poll(), Some(Ok(RspEvent::Packet(b"g"))));
+        assert_eq!(gdb.poll(), Some(Err(RspError::BadChecksum)));
+        assert!(gdb.poll_interrupt());
+        assert!(!gdb.poll_interrupt());
+
+        // A packet arriving while the target runs waits for `poll`
+        gdb.serial_mut().input.extend(b"$?#3f");
+        assert!(!gdb.poll_interrupt());
+        assert_eq!(gdb.poll(), Some(Ok(RspEvent::Packet(b"?"))));
+
+        gdb.serial_mut().input.extend(b"-\x03+");
+        gdb.send(b"OK").unwrap();
+        assert!(gdb.poll_interrupt());
+        assert_eq!(gdb.serial_mut().input.len(), 0);
+
+        gdb.set_no_ack(true);
+        gdb.send(b"OK").unwrap();
+        assert_eq!(gdb.free().0.output, b"$S05#b8+-+$OK#9a$OK#9a$OK#9a");
+    }
+
+    #[test]
+    fn send_while_receiving() {
+        let mut gdb = Rsp::new(FakeSerial::default(), StdClock::new(), [0; 64])
+            .with_retries(2)
+            .with_ack_timeout(1000);
+
+        // GDB is gone
+        assert_eq!(gdb.send(b"OK"), Err(RspError::NotAcknowledged));
+        assert_eq!(gdb.serial_mut().output, b"$OK#9a$OK#9a");
+        gdb.serial_mut().output.clear();
+
+        // A packet arriving before the ack is kept for `poll`
+        gdb.serial_mut().input.extend(b"$g#67+");
+        gdb.send(b"OK").unwrap();
+        assert_eq!(gdb.poll(), Some(Ok(RspEvent::Packet(b"g"))));
+        assert_eq!(gdb.serial_mut().output, b"$OK#9a+");
+        gdb.serial_mut().output.clear();
+
+        // With a packet already waiting, another one is rejected and doesn't overwrite it
+        gdb.serial_mut().input.extend(b"$?#3f");
+        assert!(!gdb.poll_interrupt());
+        gdb.serial_mut().input.extend(b"$m0,4#fd+");
+        gdb.send(b"OK").unwrap();
+        assert_eq!(gdb.poll(), Some(Ok(RspEvent::Packet(b"?"))));
+        assert_eq!(gdb.serial_mut().output, b"+$OK#9a-");
+    }
+
+    #[cfg(feature = "gdbstub")]
+    #[test]
+    fn connection() {
+        use gdbstub::conn::{Connection, ConnectionExt};
+
+        let mut conn = GdbConnection::new(FakeSerial::new(b"$\x03"));
+        assert!(!conn.poll_interrupt());
+        assert_eq!(conn.peek(), Ok(Some(b'$')));
+        assert_eq!(conn.read(), Ok(b'$'));
+        assert!(conn.poll_interrupt());
+        assert_eq!(conn.peek(), Ok(None));
+        conn.write_all(b"+").unwrap();
+        assert_eq!(conn.free().output, b"+");
+    }
+}
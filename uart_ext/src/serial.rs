@@ -0,0 +1,281 @@
+/*!
+# serial
+
+Byte I/O shared by the drivers of the other crates, which the helpers in this crate are built on.
+*/
+
+#[cfg(feature = "uart8250")]
+use uart8250::MmioUart8250;
+#[cfg(feature = "uart_sifive")]
+use uart_sifive::MmioUartSifive;
+#[cfg(feature = "uart_xilinx")]
+use uart_xilinx::{MmioUartAxi16550, MmioUartAxiLite, MmioUartXpsLite};
+
+use crate::uart::Uart;
+
+/// The transmitter can't take another byte yet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WouldBlock;
+
+/// # Non-blocking byte I/O
+///
+/// Implemented by every driver and by [`Uart`], and by `&mut` references to them.
+pub trait Serial {
+    /// Read a byte, `None` when no data is ready
+    fn read_byte(&mut self) -> Option<u8>;
+
+    /// Write a byte, `Err(WouldBlock)` when the transmitter is full
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock>;
+
+    /// Wait until every byte written has left the transmitter
+    ///
+    /// Does nothing for drivers that can't tell.
+    fn flush(&mut self) {}
+
+    /// Write a byte, waiting for room in the transmitter
+    fn write_byte_blocking(&mut self, byte: u8) {
+        while self.write_byte(byte).is_err() {}
+    }
+
+    /// Write all of `bytes`, waiting for room in the transmitter
+    fn write_all(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte_blocking(*byte);
+        }
+    }
+}
+
+impl<T: Serial + ?Sized> Serial for &mut T {
+    fn read_byte(&mut self) -> Option<u8> {
+        (**self).read_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        (**self).write_byte(byte)
+    }
+
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
+
+#[cfg(feature = "uart8250")]
+impl Serial for MmioUart8250<'_> {
+    fn read_byte(&mut self) -> Option<u8> {
+        MmioUart8250::read_byte(self)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        MmioUart8250::write_byte(self, byte).map_err(|_| WouldBlock)
+    }
+
+    fn flush(&mut self) {
+        MmioUart8250::flush(self)
+    }
+}
+
+#[cfg(feature = "uart_xilinx")]
+impl Serial for MmioUartAxi16550<'_> {
+    fn read_byte(&mut self) -> Option<u8> {
+        MmioUartAxi16550::read_byte(self)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        if !self.is_transmitter_holding_register_empty() {
+            return Err(WouldBlock);
+        }
+        MmioUartAxi16550::write_byte(self, byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        while !self.is_data_holding_registers_empty() {}
+    }
+}
+
+#[cfg(feature = "uart_xilinx")]
+macro_rules! impl_serial_lite {
+    ($uart:ty) => {
+        impl Serial for $uart {
+            fn read_byte(&mut self) -> Option<u8> {
+                <$uart>::read_byte(self)
+            }
+
+            fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+                if self.is_tx_fifo_full() {
+                    return Err(WouldBlock);
+                }
+                <$uart>::write_byte(self, byte);
+                Ok(())
+            }
+
+            fn flush(&mut self) {
+                while !self.is_tx_fifo_empty() {}
+            }
+        }
+    };
+}
+
+#[cfg(feature = "uart_xilinx")]
+impl_serial_lite!(MmioUartXpsLite);
+#[cfg(feature = "uart_xilinx")]
+impl_serial_lite!(MmioUartAxiLite);
+
+#[cfg(feature = "uart_sifive")]
+impl Serial for MmioUartSifive {
+    fn read_byte(&mut self) -> Option<u8> {
+        MmioUartSifive::read_byte(self)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        if self.is_tx_fifo_full() {
+            return Err(WouldBlock);
+        }
+        MmioUartSifive::write_byte(self, byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        MmioUartSifive::flush(self)
+    }
+}
+
+impl Serial for Uart {
+    fn read_byte(&mut self) -> Option<u8> {
+        match *self {
+            #[cfg(feature = "uart8250")]
+            Uart::Uart8250(ref mut uart) => Serial::read_byte(uart),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::Axi16550(ref mut uart) => Serial::read_byte(uart),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::XpsLite(ref mut uart) => Serial::read_byte(uart),
+            #[cfg(feature = "uart_sifive")]
+            Uart::Sifive(ref mut uart) => Serial::read_byte(uart),
+        }
+    }
+
+    // `Uart` has no variants when no driver is enabled
+    #[cfg_attr(
+        not(any(feature = "uart8250", feature = "uart_xilinx", feature = "uart_sifive")),
+        allow(unused_variables)
+    )]
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        match *self {
+            #[cfg(feature = "uart8250")]
+            Uart::Uart8250(ref mut uart) => Serial::write_byte(uart, byte),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::Axi16550(ref mut uart) => Serial::write_byte(uart, byte),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::XpsLite(ref mut uart) => Serial::write_byte(uart, byte),
+            #[cfg(feature = "uart_sifive")]
+            Uart::Sifive(ref mut uart) => Serial::write_byte(uart, byte),
+        }
+    }
+
+    fn flush(&mut self) {
+        match *self {
+            #[cfg(feature = "uart8250")]
+            Uart::Uart8250(ref mut uart) => Serial::flush(uart),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::Axi16550(ref mut uart) => Serial::flush(uart),
+            #[cfg(feature = "uart_xilinx")]
+            Uart::XpsLite(ref mut uart) => Serial::flush(uart),
+            #[cfg(feature = "uart_sifive")]
+            Uart::Sifive(ref mut uart) => Serial::flush(uart),
+        }
+    }
+}
+
+/// In-memory `Serial` for the tests of this crate: reads come from `input`, writes go to `output`
+#[cfg(test)]
+pub(crate) mod fake {
+    extern crate std;
+
+    use std::collections::VecDeque;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::Instant;
+    use std::vec::Vec;
+
+    use super::{Serial, WouldBlock};
+    use crate::timeout::Monotonic;
+
+    #[derive(Default)]
+    pub(crate) struct FakeSerial {
+        pub(crate) input: VecDeque<u8>,
+        pub(crate) output: Vec<u8>,
+    }
+
+    impl FakeSerial {
+        pub(crate) fn new(input: &[u8]) -> Self {
+            Self {
+                input: input.iter().copied().collect(),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Serial for FakeSerial {
+        fn read_byte(&mut self) -> Option<u8> {
+            self.input.pop_front()
+        }
+
+        fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+            self.output.push(byte);
+            Ok(())
+        }
+    }
+
+    /// One end of a full-duplex in-memory line, see `pipe`
+    pub(crate) struct PipeEnd {
+        tx: Sender<u8>,
+        rx: Receiver<u8>,
+        written: usize,
+        /// Invert the byte written at this index, to simulate line noise
+        pub(crate) flip: Option<usize>,
+    }
+
+    /// Two connected `Serial`s, which can be moved to different threads
+    pub(crate) fn pipe() -> (PipeEnd, PipeEnd) {
+        let (a_tx, b_rx) = channel();
+        let (b_tx, a_rx) = channel();
+        let end = |tx, rx| PipeEnd {
+            tx,
+            rx,
+            written: 0,
+            flip: None,
+        };
+        (end(a_tx, a_rx), end(b_tx, b_rx))
+    }
+
+    impl Serial for PipeEnd {
+        fn read_byte(&mut self) -> Option<u8> {
+            self.rx.try_recv().ok()
+        }
+
+        fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+            let byte = if self.flip == Some(self.written) {
+                !byte
+            } else {
+                byte
+            };
+            self.written += 1;
+            // The other end may be gone already, like a disconnected cable
+            let _ = self.tx.send(byte);
+            Ok(())
+        }
+    }
+
+    pub(crate) struct StdClock(Instant);
+
+    impl StdClock {
+        pub(crate) fn new() -> Self {
+            Self(Instant::now())
+        }
+    }
+
+    impl Monotonic for StdClock {
+        fn now_us(&mut self) -> u64 {
+            self.0.elapsed().as_micros() as u64
+        }
+    }
+}
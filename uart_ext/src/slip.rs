@@ -0,0 +1,464 @@
+/*!
+# slip
+
+SLIP framing (RFC 1055) over any [`Serial`], and a smoltcp `phy::Device` on top of it with the
+`smoltcp` feature.
+
+```ignore
+let mut slip = Slip::new(uart, [0; 1006]);
+slip.write_frame(&packet);
+if let Some(Ok(frame)) = slip.poll() {
+    handle(frame);
+}
+```
+
+## REF
+
+- [RFC 1055: A Nonstandard for Transmission of IP Datagrams over Serial Lines](https://www.rfc-editor.org/rfc/rfc1055)
+*/
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::serial::Serial;
+
+/// Frame delimiter
+pub const END: u8 = 0xc0;
+/// Escape
+pub const ESC: u8 = 0xdb;
+/// END inside a frame, after ESC
+pub const ESC_END: u8 = 0xdc;
+/// ESC inside a frame, after ESC
+pub const ESC_ESC: u8 = 0xdd;
+
+/// The largest frame RFC 1055 suggests supporting
+pub const DEFAULT_MTU: usize = 1006;
+
+/// A frame that could not be decoded
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SlipError {
+    /// The frame didn't fit in the buffer; `len` is its decoded length
+    Oversize { len: usize },
+    /// The frame ended in the middle of an escape, or was abandoned with `SlipDecoder::abort`
+    Truncated,
+    /// ESC was followed by something other than ESC_END or ESC_ESC
+    BadEscape,
+}
+
+impl Display for SlipError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Oversize { len } => write!(f, "SLIP frame of {} bytes too large", len),
+            Self::Truncated => f.write_str("SLIP frame truncated"),
+            Self::BadEscape => f.write_str("bad SLIP escape"),
+        }
+    }
+}
+
+/// Number of bytes `frame` takes once encoded, both END included
+pub fn encoded_len(frame: &[u8]) -> usize {
+    frame
+        .iter()
+        .map(|byte| if matches!(*byte, END | ESC) { 2 } else { 1 })
+        .sum::<usize>()
+        + 2
+}
+
+/// Encode `frame` into `out` between two END, returning the encoded length
+///
+/// Returns `None` when `out` is shorter than [`encoded_len`].
+pub fn encode(frame: &[u8], out: &mut [u8]) -> Option<usize> {
+    if out.len() < encoded_len(frame) {
+        return None;
+    }
+    let mut len = 0;
+    let mut push = |byte| {
+        out[len] = byte;
+        len += 1;
+    };
+    push(END);
+    for byte in frame {
+        match *byte {
+            END => {
+                push(ESC);
+                push(ESC_END);
+            }
+            ESC => {
+                push(ESC);
+                push(ESC_ESC);
+            }
+            byte => push(byte),
+        }
+    }
+    push(END);
+    Some(len)
+}
+
+/// Write `frame` to `serial` between two END, waiting for room in the transmitter
+///
+/// The leading END flushes any line noise the receiver has accumulated, as RFC 1055 suggests.
+pub fn write_frame<S: Serial + ?Sized>(serial: &mut S, frame: &[u8]) {
+    serial.write_byte_blocking(END);
+    for byte in frame {
+        match *byte {
+            END => serial.write_all(&[ESC, ESC_END]),
+            ESC => serial.write_all(&[ESC, ESC_ESC]),
+            byte => serial.write_byte_blocking(byte),
+        }
+    }
+    serial.write_byte_blocking(END);
+}
+
+/// # Incremental SLIP decoder
+///
+/// Fed one byte at a time, it decodes into `B`, a `[u8; N]` or a `&mut [u8]`, whose length is
+/// the largest frame accepted. Empty frames, e.g. the END sent before every frame, are skipped.
+pub struct SlipDecoder<B> {
+    buf: B,
+    len: usize,
+    escaped: bool,
+    /// An error seen in the current frame, reported at its END
+    error: Option<SlipError>,
+}
+
+impl<B: AsMut<[u8]>> SlipDecoder<B> {
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            escaped: false,
+            error: None,
+        }
+    }
+
+    /// Give back the buffer
+    pub fn free(self) -> B {
+        self.buf
+    }
+
+    /// Whether a frame is partially decoded
+    pub fn is_idle(&self) -> bool {
+        self.len == 0 && !self.escaped && self.error.is_none()
+    }
+
+    /// Drop the frame being decoded, e.g. when the line has been idle for too long
+    ///
+    /// Returns `Err(SlipError::Truncated)` if there was one.
+    pub fn abort(&mut self) -> Result<(), SlipError> {
+        let idle = self.is_idle();
+        self.len = 0;
+        self.escaped = false;
+        self.error = None;
+        if idle {
+            Ok(())
+        } else {
+            Err(SlipError::Truncated)
+        }
+    }
+
+    /// Feed one byte, returning a frame or an error when it ends
+    ///
+    /// The frame stays valid until the next call.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], SlipError>> {
+        match self.push_len(byte)? {
+            Ok(len) => Some(Ok(&self.buf.as_mut()[..len])),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// `push`, returning the length of the frame rather than borrowing it
+    fn push_len(&mut self, byte: u8) -> Option<Result<usize, SlipError>> {
+        if byte == END {
+            let len = core::mem::replace(&mut self.len, 0);
+            let escaped = core::mem::replace(&mut self.escaped, false);
+            return match self.error.take() {
+                Some(SlipError::Oversize { .. }) => Some(Err(SlipError::Oversize { len })),
+                Some(error) => Some(Err(error)),
+                None if escaped => Some(Err(SlipError::Truncated)),
+                None if len == 0 => None,
+                None => Some(Ok(len)),
+            };
+        }
+
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                ESC_END => END,
+                ESC_ESC => ESC,
+                _ => {
+                    self.error.get_or_insert(SlipError::BadEscape);
+                    return None;
+                }
+            }
+        } else if byte == ESC {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+
+        // Keep counting an oversized frame, so its length can be reported
+        match self.buf.as_mut().get_mut(self.len) {
+            Some(slot) if self.error.is_none() => *slot = byte,
+            Some(_) => {}
+            None => {
+                self.error.get_or_insert(SlipError::Oversize { len: 0 });
+            }
+        }
+        self.len += 1;
+        None
+    }
+}
+
+/// # SLIP link over a [`Serial`]
+pub struct Slip<S, B> {
+    serial: S,
+    decoder: SlipDecoder<B>,
+}
+
+impl<S: Serial, B: AsMut<[u8]>> Slip<S, B> {
+    /// Frames received are decoded into `buf`, see [`SlipDecoder`]
+    pub fn new(serial: S, buf: B) -> Self {
+        Self {
+            serial,
+            decoder: SlipDecoder::new(buf),
+        }
+    }
+
+    /// Give back the serial and the buffer
+    pub fn free(self) -> (S, B) {
+        (self.serial, self.decoder.free())
+    }
+
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    pub fn decoder_mut(&mut self) -> &mut SlipDecoder<B> {
+        &mut self.decoder
+    }
+
+    /// Decode the bytes available, returning as soon as a frame or an error ends
+    pub fn poll(&mut self) -> Option<Result<&[u8], SlipError>> {
+        while let Some(byte) = self.serial.read_byte() {
+            match self.decoder.push_len(byte) {
+                Some(Ok(len)) => return Some(Ok(&self.decoder.buf.as_mut()[..len])),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// Send `frame`, see [`write_frame`]
+    pub fn write_frame(&mut self, frame: &[u8]) {
+        write_frame(&mut self.serial, frame)
+    }
+}
+
+#[cfg(feature = "smoltcp")]
+pub use self::device::SlipDevice;
+
+#[cfg(feature = "smoltcp")]
+mod device {
+    use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+    use smoltcp::time::Instant;
+
+    use super::{write_frame, Slip};
+    use crate::serial::Serial;
+
+    /// # smoltcp `phy::Device` over SLIP
+    ///
+    /// Received packets are decoded in the buffer of the [`Slip`], packets to send are built in
+    /// `tx`. The MTU is the smaller of the two. Frames with errors are dropped.
+    pub struct SlipDevice<S, B> {
+        slip: Slip<S, B>,
+        tx: B,
+    }
+
+    impl<S: Serial, B: AsMut<[u8]> + AsRef<[u8]>> SlipDevice<S, B> {
+        pub fn new(slip: Slip<S, B>, tx: B) -> Self {
+            Self { slip, tx }
+        }
+
+        /// Give back the link and the transmit buffer
+        pub fn free(self) -> (Slip<S, B>, B) {
+            (self.slip, self.tx)
+        }
+    }
+
+    pub struct RxToken<'a> {
+        frame: &'a [u8],
+    }
+
+    impl phy::RxToken for RxToken<'_> {
+        fn consume<R, F>(self, f: F) -> R
+        where
+            F: FnOnce(&[u8]) -> R,
+        {
+            f(self.frame)
+        }
+    }
+
+    pub struct TxToken<'a, S> {
+        serial: &'a mut S,
+        buf: &'a mut [u8],
+    }
+
+    impl<S: Serial> phy::TxToken for TxToken<'_, S> {
+        fn consume<R, F>(self, len: usize, f: F) -> R
+        where
+            F: FnOnce(&mut [u8]) -> R,
+        {
+            let packet = &mut self.buf[..len];
+            let result = f(packet);
+            write_frame(self.serial, packet);
+            result
+        }
+    }
+
+    impl<S: Serial, B: AsMut<[u8]> + AsRef<[u8]>> Device for SlipDevice<S, B> {
+        type RxToken<'a>
+            = RxToken<'a>
+        where
+            Self: 'a;
+        type TxToken<'a>
+            = TxToken<'a, S>
+        where
+            Self: 'a;
+
+        fn receive(
+            &mut self,
+            _timestamp: Instant,
+        ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+            let Slip { serial, decoder } = &mut self.slip;
+            while let Some(byte) = serial.read_byte() {
+                if let Some(Ok(len)) = decoder.push_len(byte) {
+                    let rx = RxToken {
+                        frame: &decoder.buf.as_mut()[..len],
+                    };
+                    let tx = TxToken {
+                        serial,
+                        buf: self.tx.as_mut(),
+                    };
+                    return Some((rx, tx));
+                }
+            }
+            None
+        }
+
+        fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+            Some(TxToken {
+                serial: &mut self.slip.serial,
+                buf: self.tx.as_mut(),
+            })
+        }
+
+        fn capabilities(&self) -> DeviceCapabilities {
+            let mut capabilities = DeviceCapabilities::default();
+            capabilities.medium = Medium::Ip;
+            capabilities.max_transmission_unit = self
+                .tx
+                .as_ref()
+                .len()
+                .min(self.slip.decoder.buf.as_ref().len());
+            capabilities
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::fake::FakeSerial;
+
+    #[test]
+    fn encode_decode() {
+        let frame = [0x45, END, 0x00, ESC, ESC_END, END];
+        let mut encoded = [0; 16];
+        let len = encode(&frame, &mut encoded).unwrap();
+        assert_eq!(len, encoded_len(&frame));
+        assert_eq!(
+            &encoded[..len],
+            [END, 0x45, ESC, ESC_END, 0x00, ESC, ESC_ESC, ESC_END, ESC, ESC_END, END]
+        );
+        assert_eq!(encode(&frame, &mut [0; 10]), None);
+
+        let mut decoder = SlipDecoder::new([0; 8]);
+        let (last, rest) = encoded[..len].split_last().unwrap();
+        for byte in rest {
+            assert_eq!(decoder.push(*byte), None);
+        }
+        assert_eq!(decoder.push(*last), Some(Ok(&frame[..])));
+        assert!(decoder.is_idle());
+    }
+
+    #[test]
+    fn errors() {
+        let mut decoder = SlipDecoder::new([0; 4]);
+        let mut feed = |bytes: &[u8]| {
+            let mut last = None;
+            for byte in bytes {
+                last = decoder
+                    .push(*byte)
+                    .map(|frame| frame.map(|frame| frame.len()));
+            }
+            last
+        };
+
+        assert_eq!(
+            feed(&[1, 2, 3, 4, 5, 6, END]),
+            Some(Err(SlipError::Oversize { len: 6 }))
+        );
+        // The decoder recovers at the next frame
+        assert_eq!(feed(&[1, 2, 3, 4, END]), Some(Ok(4)));
+        assert_eq!(feed(&[1, ESC, END]), Some(Err(SlipError::Truncated)));
+        assert_eq!(
+            feed(&[1, ESC, 0x00, 2, END]),
+            Some(Err(SlipError::BadEscape))
+        );
+        assert_eq!(feed(&[END, END]), None);
+
+        assert_eq!(feed(&[1, 2]), None);
+        assert_eq!(decoder.abort(), Err(SlipError::Truncated));
+        assert_eq!(decoder.abort(), Ok(()));
+    }
+
+    #[test]
+    fn link() {
+        let mut slip = Slip::new(
+            FakeSerial::new(&[0x01, END, END, 0x02, ESC, ESC_ESC, END, 0x03]),
+            [0; 16],
+        );
+        slip.write_frame(&[END, 0x10]);
+
+        assert_eq!(slip.poll(), Some(Ok(&[0x01][..])));
+        assert_eq!(slip.poll(), Some(Ok(&[0x02, ESC][..])));
+        assert_eq!(slip.poll(), None);
+        let (serial, _) = slip.free();
+        assert_eq!(serial.output, [END, ESC, ESC_END, 0x10, END]);
+    }
+
+    #[cfg(feature = "smoltcp")]
+    #[test]
+    fn device() {
+        use smoltcp::phy::{Device, Medium, RxToken, TxToken};
+        use smoltcp::time::Instant;
+
+        let slip = Slip::new(
+            FakeSerial::new(&[END, 0x45, 0x00, ESC, ESC_END, END]),
+            [0; 32],
+        );
+        let mut device = SlipDevice::new(slip, [0; 32]);
+        assert_eq!(device.capabilities().medium, Medium::Ip);
+        assert_eq!(device.capabilities().max_transmission_unit, 32);
+
+        let (rx, tx) = device.receive(Instant::ZERO).unwrap();
+        rx.consume(|frame| assert_eq!(frame, [0x45, 0x00, END]));
+        tx.consume(2, |buf| buf.copy_from_slice(&[ESC, 0x01]));
+        assert!(device.receive(Instant::ZERO).is_none());
+
+        let (slip, _) = device.free();
+        let (serial, _) = slip.free();
+        assert_eq!(serial.output, [END, ESC, ESC_ESC, 0x01, END]);
+    }
+}
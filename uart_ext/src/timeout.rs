@@ -0,0 +1,27 @@
+/*!
+# timeout
+
+Time source for the protocols in this crate that give up after a while
+*/
+
+use crate::serial::Serial;
+
+pub(crate) use uart_common::Deadline;
+pub use uart_common::Monotonic;
+
+/// Read a byte, giving up after `timeout_us` microseconds
+pub(crate) fn read_byte_timeout<S: Serial, M: Monotonic>(
+    serial: &mut S,
+    clock: &mut M,
+    timeout_us: u64,
+) -> Option<u8> {
+    let deadline = Deadline::new(clock, timeout_us);
+    loop {
+        if let Some(byte) = serial.read_byte() {
+            return Some(byte);
+        }
+        if deadline.is_expired(clock) {
+            return None;
+        }
+    }
+}
@@ -0,0 +1,105 @@
+/*!
+# transfer
+
+Where the file transfer protocols in this crate read the data they send and write the data they
+receive, so they work without an allocator.
+*/
+
+/// A file announced by the sender, when the protocol carries one
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FileInfo<'a> {
+    pub name: &'a str,
+    /// Size in bytes, if the sender knows it
+    pub size: Option<u64>,
+}
+
+/// How far a transfer has gone, given to progress callbacks
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// Bytes of the current file transferred so far
+    pub bytes: u64,
+    /// Size of the current file, if known
+    pub total: Option<u64>,
+}
+
+/// The data to send
+pub trait Source {
+    /// Fill `buf` with the next bytes, returning how many. 0 means the end of the data.
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+}
+
+impl Source for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len());
+        buf[..len].copy_from_slice(&self[..len]);
+        *self = &self[len..];
+        len
+    }
+}
+
+/// A `Sink` can't take the data, which cancels the transfer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SinkError;
+
+/// Where received data goes
+pub trait Sink {
+    /// A new file starts, for protocols that carry file information
+    fn open(&mut self, _info: &FileInfo) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Bytes of the current file already present, after `open`
+    ///
+    /// Protocols that can resume an interrupted transfer ask the sender to start there.
+    fn offset(&mut self) -> u64 {
+        0
+    }
+
+    /// Append `data` to the current file
+    fn write(&mut self, data: &[u8]) -> Result<(), SinkError>;
+
+    /// The current file is complete
+    fn close(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// A `Sink` filling a slice, e.g. a firmware staging area
+///
+/// Every file received is appended to the same slice.
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// The bytes written so far
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Number of bytes written so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<(), SinkError> {
+        let end = self.len + data.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(SinkError)?
+            .copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+/*!
+# uart
+
+A single type over the drivers of the other crates
+*/
+
+#[cfg(feature = "uart8250")]
+use uart8250::MmioUart8250;
+#[cfg(feature = "uart_sifive")]
+use uart_sifive::MmioUartSifive;
+#[cfg(feature = "uart_xilinx")]
+use uart_xilinx::{MmioUartAxi16550, MmioUartXpsLite};
+
+/// Any of the uarts supported by the enabled driver crates
+///
+/// Returned when the kind of uart is only known at runtime, e.g. when probing a device tree.
+pub enum Uart {
+    #[cfg(feature = "uart8250")]
+    Uart8250(MmioUart8250<'static>),
+    #[cfg(feature = "uart_xilinx")]
+    Axi16550(MmioUartAxi16550<'static>),
+    #[cfg(feature = "uart_xilinx")]
+    XpsLite(MmioUartXpsLite),
+    #[cfg(feature = "uart_sifive")]
+    Sifive(MmioUartSifive),
+}
@@ -0,0 +1,750 @@
+/*!
+# xmodem
+
+XMODEM (checksum, CRC and 1K) and YMODEM batch transfers over any [`Serial`].
+
+```ignore
+let mut xmodem = Xmodem::new(uart, clock).with_progress(|progress| {
+    show(progress.bytes);
+    true
+});
+let mut sink = SliceSink::new(staging_area);
+let len = xmodem.receive(&mut sink)?;
+```
+
+## REF
+
+- [XMODEM/YMODEM PROTOCOL REFERENCE, Chuck Forsberg](http://pauillac.inria.fr/~doligez/zmodem/ymodem.txt)
+*/
+
+use core::fmt::{self, Display, Formatter};
+use core::str;
+
+use crate::crc::crc16_xmodem;
+use crate::serial::Serial;
+use crate::timeout::{read_byte_timeout, Monotonic};
+use crate::transfer::{FileInfo, Progress, Sink, Source};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Pads the last block
+const SUB: u8 = 0x1a;
+/// Sent instead of NAK by a receiver asking for CRC
+const CRC_START: u8 = b'C';
+
+/// Number of unanswered `C` before a receiver falls back to checksum
+const CRC_ATTEMPTS: u8 = 3;
+
+/// Flavour of XMODEM
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// 128-byte blocks with an 8-bit checksum
+    Checksum,
+    /// 128-byte blocks with CRC-16
+    Crc,
+    /// 1024-byte blocks with CRC-16, 128-byte blocks for short tails
+    Crc1k,
+}
+
+/// Settings of a transfer
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// What a receiver asks for, and the largest blocks a sender uses
+    ///
+    /// A sender follows the receiver: it uses checksum when asked with NAK even in CRC modes.
+    /// A receiver asking for CRC falls back to checksum after 3 unanswered `C`.
+    pub mode: Mode,
+    /// How many times a block is retried, or the handshake repeated, before giving up
+    pub retries: u8,
+    /// How long to wait for a block, a response or a handshake
+    pub timeout_us: u64,
+    /// How long to wait for each byte inside a block
+    pub byte_timeout_us: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Crc1k,
+            retries: 10,
+            timeout_us: 10_000_000,
+            byte_timeout_us: 1_000_000,
+        }
+    }
+}
+
+/// An error ending a transfer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The other side stopped answering
+    Timeout,
+    /// A block was corrupted more times than `Config::retries`
+    TooManyErrors,
+    /// The other side sent CAN CAN
+    Cancelled,
+    /// The progress callback asked to stop
+    Aborted,
+    /// A block arrived out of sequence
+    OutOfSequence,
+    /// The sink refused the data
+    Sink,
+    /// A YMODEM header could not be parsed, or the file name is too long to send
+    BadHeader,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Timeout => "transfer timed out",
+            Self::TooManyErrors => "too many errors",
+            Self::Cancelled => "transfer cancelled by the remote",
+            Self::Aborted => "transfer aborted",
+            Self::OutOfSequence => "block out of sequence",
+            Self::Sink => "sink refused the data",
+            Self::BadHeader => "bad YMODEM header",
+        })
+    }
+}
+
+enum Packet {
+    Block { number: u8, len: usize },
+    Eot,
+    Cancel,
+}
+
+enum PacketError {
+    Timeout,
+    Corrupt,
+}
+
+enum Response {
+    Ack,
+    Nak,
+    Timeout,
+}
+
+/// # XMODEM and YMODEM endpoint
+///
+/// Sends or receives over `serial`, using `clock` for timeouts. The progress callback is called
+/// after every block and stops the transfer by returning `false`.
+pub struct Xmodem<S, M, P = fn(Progress) -> bool> {
+    serial: S,
+    clock: M,
+    config: Config,
+    progress: P,
+}
+
+impl<S: Serial, M: Monotonic> Xmodem<S, M> {
+    pub fn new(serial: S, clock: M) -> Self {
+        Self {
+            serial,
+            clock,
+            config: Config::default(),
+            progress: |_| true,
+        }
+    }
+}
+
+impl<S: Serial, M: Monotonic, P: FnMut(Progress) -> bool> Xmodem<S, M, P> {
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_progress<F: FnMut(Progress) -> bool>(self, progress: F) -> Xmodem<S, M, F> {
+        Xmodem {
+            serial: self.serial,
+            clock: self.clock,
+            config: self.config,
+            progress,
+        }
+    }
+
+    /// Give back the serial and the clock
+    pub fn free(self) -> (S, M) {
+        (self.serial, self.clock)
+    }
+
+    /// Tell the other side to stop with CAN CAN
+    pub fn cancel(&mut self) {
+        self.serial.write_all(&[CAN, CAN]);
+    }
+
+    fn read(&mut self, timeout_us: u64) -> Option<u8> {
+        read_byte_timeout(&mut self.serial, &mut self.clock, timeout_us)
+    }
+
+    /// Drop everything until the line has been idle for a byte timeout
+    fn purge(&mut self) {
+        while self.read(self.config.byte_timeout_us).is_some() {}
+    }
+
+    fn report(&mut self, bytes: u64, total: Option<u64>) -> Result<(), Error> {
+        if (self.progress)(Progress { bytes, total }) {
+            Ok(())
+        } else {
+            self.cancel();
+            Err(Error::Aborted)
+        }
+    }
+
+    /// Send an XMODEM transfer of everything in `source`, returning how many bytes were sent
+    pub fn send<T: Source + ?Sized>(&mut self, source: &mut T) -> Result<u64, Error> {
+        let crc = self.wait_start()?;
+        self.send_data(source, crc, None)
+    }
+
+    /// Receive an XMODEM transfer into `sink`, returning how many bytes were received
+    ///
+    /// XMODEM doesn't carry the size, so the padding of the last block is written to the sink.
+    pub fn receive<T: Sink + ?Sized>(&mut self, sink: &mut T) -> Result<u64, Error> {
+        let mut crc = self.config.mode != Mode::Checksum;
+        let mut buf = [0; 1024];
+        let first = self.solicit(&mut buf, &mut crc, true)?;
+        self.receive_data(sink, first, crc, None, &mut buf)
+    }
+
+    /// Send one file of a YMODEM batch, returning how many bytes were sent
+    ///
+    /// Call [`finish_ymodem`](Self::finish_ymodem) after the last file.
+    pub fn send_ymodem<T: Source + ?Sized>(
+        &mut self,
+        info: &FileInfo,
+        source: &mut T,
+    ) -> Result<u64, Error> {
+        // name NUL size NUL, in a 128-byte block if it fits
+        let mut header = [0; 1024];
+        let name = info.name.as_bytes();
+        if name.is_empty() || name.contains(&0) || name.len() + 22 > header.len() {
+            return Err(Error::BadHeader);
+        }
+        header[..name.len()].copy_from_slice(name);
+        let mut len = name.len() + 1;
+        if let Some(size) = info.size {
+            len += write_decimal(&mut header[len..], size);
+        }
+        let block = if len < 128 { 128 } else { 1024 };
+
+        let crc = self.wait_start()?;
+        self.send_block(0, &header[..block], crc)?;
+        let crc = self.wait_start()?;
+        self.send_data(source, crc, info.size)
+    }
+
+    /// End a YMODEM batch with an empty header
+    pub fn finish_ymodem(&mut self) -> Result<(), Error> {
+        let crc = self.wait_start()?;
+        self.send_block(0, &[0; 128], crc)
+    }
+
+    /// Receive a YMODEM batch into `sink`, returning how many files were received
+    ///
+    /// Each file is announced with `Sink::open` and truncated to the size in its header.
+    pub fn receive_ymodem<T: Sink + ?Sized>(&mut self, sink: &mut T) -> Result<usize, Error> {
+        let mut buf = [0; 1024];
+        let mut files = 0;
+        loop {
+            let mut crc = true;
+            let len = match self.solicit(&mut buf, &mut crc, false)? {
+                Packet::Block { number: 0, len } => len,
+                Packet::Cancel => return Err(Error::Cancelled),
+                // The EOT of the previous file again, our ACK got lost
+                Packet::Eot => {
+                    self.serial.write_byte_blocking(ACK);
+                    continue;
+                }
+                Packet::Block { .. } => {
+                    self.cancel();
+                    return Err(Error::OutOfSequence);
+                }
+            };
+            if buf[0] == 0 {
+                self.serial.write_byte_blocking(ACK);
+                return Ok(files);
+            }
+
+            let size = match parse_header(&buf[..len]) {
+                Some(info) => {
+                    if sink.open(&info).is_err() {
+                        self.cancel();
+                        return Err(Error::Sink);
+                    }
+                    info.size
+                }
+                None => {
+                    self.cancel();
+                    return Err(Error::BadHeader);
+                }
+            };
+            self.serial.write_byte_blocking(ACK);
+
+            let first = self.solicit(&mut buf, &mut crc, false)?;
+            self.receive_data(sink, first, crc, size, &mut buf)?;
+            sink.close().map_err(|_| Error::Sink)?;
+            files += 1;
+        }
+    }
+
+    /// Wait for the receiver to ask for the transfer, returning whether it wants CRC
+    fn wait_start(&mut self) -> Result<bool, Error> {
+        let mut errors = 0;
+        loop {
+            match self.read(self.config.timeout_us) {
+                Some(CRC_START) => return Ok(true),
+                Some(NAK) => return Ok(false),
+                Some(CAN) => {
+                    if self.read(self.config.byte_timeout_us) == Some(CAN) {
+                        return Err(Error::Cancelled);
+                    }
+                }
+                // Line noise, or the leftovers of a previous transfer
+                Some(_) => {}
+                None => {
+                    errors += 1;
+                    if errors > self.config.retries {
+                        return Err(Error::Timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    fn wait_response(&mut self) -> Result<Response, Error> {
+        loop {
+            match self.read(self.config.timeout_us) {
+                Some(ACK) => return Ok(Response::Ack),
+                // A late handshake asks for the block again as well
+                Some(NAK) | Some(CRC_START) => return Ok(Response::Nak),
+                Some(CAN) => {
+                    if self.read(self.config.byte_timeout_us) == Some(CAN) {
+                        return Err(Error::Cancelled);
+                    }
+                }
+                Some(_) => {}
+                None => return Ok(Response::Timeout),
+            }
+        }
+    }
+
+    /// Send `data` as block `number` until it is acknowledged
+    ///
+    /// `data` must be 128 or 1024 bytes long.
+    fn send_block(&mut self, number: u8, data: &[u8], crc: bool) -> Result<(), Error> {
+        let header = if data.len() == 1024 { STX } else { SOH };
+        let mut errors = 0;
+        loop {
+            self.serial.write_all(&[header, number, !number]);
+            self.serial.write_all(data);
+            if crc {
+                self.serial.write_all(&crc16_xmodem(0, data).to_be_bytes());
+            } else {
+                self.serial.write_byte_blocking(checksum(data));
+            }
+
+            let error = match self.wait_response()? {
+                Response::Ack => return Ok(()),
+                Response::Nak => Error::TooManyErrors,
+                Response::Timeout => Error::Timeout,
+            };
+            errors += 1;
+            if errors > self.config.retries {
+                self.cancel();
+                return Err(error);
+            }
+        }
+    }
+
+    /// Send the data blocks and EOT
+    fn send_data<T: Source + ?Sized>(
+        &mut self,
+        source: &mut T,
+        crc: bool,
+        total: Option<u64>,
+    ) -> Result<u64, Error> {
+        let block = if crc && self.config.mode == Mode::Crc1k {
+            1024
+        } else {
+            128
+        };
+        let mut buf = [0; 1024];
+        let mut number: u8 = 1;
+        let mut sent = 0;
+        loop {
+            let len = fill(source, &mut buf[..block]);
+            if len == 0 {
+                break;
+            }
+            let block = if len <= 128 { 128 } else { block };
+            buf[len..block].fill(SUB);
+            self.send_block(number, &buf[..block], crc)?;
+            number = number.wrapping_add(1);
+            sent += len as u64;
+            self.report(sent, total)?;
+        }
+
+        let mut errors = 0;
+        loop {
+            self.serial.write_byte_blocking(EOT);
+            let error = match self.wait_response()? {
+                Response::Ack => return Ok(sent),
+                Response::Nak => Error::TooManyErrors,
+                Response::Timeout => Error::Timeout,
+            };
+            errors += 1;
+            if errors > self.config.retries {
+                self.cancel();
+                return Err(error);
+            }
+        }
+    }
+
+    /// Read a packet, the header byte included
+    fn read_packet(&mut self, buf: &mut [u8; 1024], crc: bool) -> Result<Packet, PacketError> {
+        let len = match self.read(self.config.timeout_us) {
+            Some(SOH) => 128,
+            Some(STX) => 1024,
+            Some(EOT) => return Ok(Packet::Eot),
+            Some(CAN) => {
+                return match self.read(self.config.byte_timeout_us) {
+                    Some(CAN) => Ok(Packet::Cancel),
+                    _ => Err(PacketError::Corrupt),
+                }
+            }
+            Some(_) => return Err(PacketError::Corrupt),
+            None => return Err(PacketError::Timeout),
+        };
+
+        let byte_timeout_us = self.config.byte_timeout_us;
+        let mut next = || self.read(byte_timeout_us).ok_or(PacketError::Corrupt);
+        let number = next()?;
+        let complement = next()?;
+        for byte in buf[..len].iter_mut() {
+            *byte = next()?;
+        }
+        let valid = if crc {
+            let expected = u16::from_be_bytes([next()?, next()?]);
+            crc16_xmodem(0, &buf[..len]) == expected
+        } else {
+            checksum(&buf[..len]) == next()?
+        };
+        if valid && number == !complement {
+            Ok(Packet::Block { number, len })
+        } else {
+            Err(PacketError::Corrupt)
+        }
+    }
+
+    /// Send the handshake until a packet arrives
+    ///
+    /// With `fallback`, switch from CRC to checksum after `CRC_ATTEMPTS` tries.
+    fn solicit(
+        &mut self,
+        buf: &mut [u8; 1024],
+        crc: &mut bool,
+        fallback: bool,
+    ) -> Result<Packet, Error> {
+        let mut errors = 0;
+        loop {
+            self.serial
+                .write_byte_blocking(if *crc { CRC_START } else { NAK });
+            match self.read_packet(buf, *crc) {
+                Ok(packet) => return Ok(packet),
+                Err(PacketError::Corrupt) => self.purge(),
+                Err(PacketError::Timeout) => {}
+            }
+            errors += 1;
+            if fallback && *crc && errors == CRC_ATTEMPTS {
+                *crc = false;
+            }
+            if errors > self.config.retries {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Receive data blocks until EOT, starting with `first`
+    fn receive_data<T: Sink + ?Sized>(
+        &mut self,
+        sink: &mut T,
+        first: Packet,
+        crc: bool,
+        size: Option<u64>,
+        buf: &mut [u8; 1024],
+    ) -> Result<u64, Error> {
+        let mut expected: u8 = 1;
+        let mut received: u64 = 0;
+        let mut errors = 0;
+        let mut packet = Ok(first);
+        loop {
+            match packet {
+                Ok(Packet::Block { number, len }) if number == expected => {
+                    let len = match size {
+                        Some(size) => (len as u64).min(size.saturating_sub(received)) as usize,
+                        None => len,
+                    };
+                    if sink.write(&buf[..len]).is_err() {
+                        self.cancel();
+                        return Err(Error::Sink);
+                    }
+                    received += len as u64;
+                    self.report(received, size)?;
+                    self.serial.write_byte_blocking(ACK);
+                    expected = expected.wrapping_add(1);
+                    errors = 0;
+                }
+                // The previous block again, our ACK got lost
+                Ok(Packet::Block { number, .. }) if number == expected.wrapping_sub(1) => {
+                    self.serial.write_byte_blocking(ACK);
+                }
+                Ok(Packet::Block { .. }) => {
+                    self.cancel();
+                    return Err(Error::OutOfSequence);
+                }
+                Ok(Packet::Eot) => {
+                    self.serial.write_byte_blocking(ACK);
+                    return Ok(received);
+                }
+                Ok(Packet::Cancel) => return Err(Error::Cancelled),
+                Err(error) => {
+                    errors += 1;
+                    if errors > self.config.retries {
+                        self.cancel();
+                        return Err(match error {
+                            PacketError::Timeout => Error::Timeout,
+                            PacketError::Corrupt => Error::TooManyErrors,
+                        });
+                    }
+                    if let PacketError::Corrupt = error {
+                        self.purge();
+                    }
+                    self.serial.write_byte_blocking(NAK);
+                }
+            }
+            packet = self.read_packet(buf, crc);
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Read from `source` until `buf` is full or the source is exhausted
+fn fill<T: Source + ?Sized>(source: &mut T, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    while len < buf.len() {
+        match source.read(&mut buf[len..]) {
+            0 => break,
+            count => len += count,
+        }
+    }
+    len
+}
+
+/// Write `value` in decimal at the start of `buf`, returning how many digits were written
+fn write_decimal(buf: &mut [u8], mut value: u64) -> usize {
+    let mut digits = [0; 20];
+    let mut len = 0;
+    loop {
+        digits[len] = b'0' + (value % 10) as u8;
+        len += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    for (byte, digit) in buf.iter_mut().zip(digits[..len].iter().rev()) {
+        *byte = *digit;
+    }
+    len
+}
+
+/// Parse `name NUL [size [mtime [mode ...]]] NUL`
+fn parse_header(block: &[u8]) -> Option<FileInfo<'_>> {
+    let name_len = block.iter().position(|byte| *byte == 0)?;
+    let name = str::from_utf8(&block[..name_len]).ok()?;
+    let rest = &block[name_len + 1..];
+    let digits = rest
+        .iter()
+        .position(|byte| !byte.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let size = str::from_utf8(&rest[..digits]).ok()?.parse().ok();
+    Some(FileInfo { name, size })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::{pipe, StdClock};
+    use crate::transfer::{SinkError, SliceSink};
+    use std::string::String;
+    use std::thread;
+    use std::vec::Vec;
+
+    fn config(mode: Mode) -> Config {
+        Config {
+            mode,
+            retries: 5,
+            timeout_us: 200_000,
+            byte_timeout_us: 20_000,
+        }
+    }
+
+    fn image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    fn xmodem(receiver_mode: Mode, sender_mode: Mode, len: usize, flip: Option<usize>) {
+        let data = image(len);
+        let (mut a, b) = pipe();
+        a.flip = flip;
+        let expected = data.clone();
+        let sender = thread::spawn(move || {
+            let mut xmodem = Xmodem::new(a, StdClock::new()).with_config(config(sender_mode));
+            xmodem.send(&mut &data[..])
+        });
+
+        let mut buf = [0; 4096];
+        let mut sink = SliceSink::new(&mut buf);
+        let mut xmodem = Xmodem::new(b, StdClock::new()).with_config(config(receiver_mode));
+        let received = xmodem.receive(&mut sink).unwrap();
+        assert_eq!(sender.join().unwrap(), Ok(len as u64));
+
+        // Padded to the block size with SUB
+        assert_eq!(received as usize, sink.len());
+        assert_eq!(&sink.data()[..len], &expected[..]);
+        assert!(sink.data()[len..].iter().all(|byte| *byte == SUB));
+    }
+
+    #[test]
+    fn checksum() {
+        xmodem(Mode::Checksum, Mode::Crc1k, 300, None);
+    }
+
+    #[test]
+    fn crc() {
+        xmodem(Mode::Crc, Mode::Crc, 1000, None);
+    }
+
+    #[test]
+    fn crc_1k() {
+        // 2 blocks of 1K then a 128-byte tail
+        xmodem(Mode::Crc1k, Mode::Crc1k, 2100, None);
+    }
+
+    #[test]
+    fn retry_corrupted_block() {
+        xmodem(Mode::Crc1k, Mode::Crc1k, 2100, Some(1500));
+    }
+
+    #[test]
+    fn ymodem_batch() {
+        let first = image(3000);
+        let second = image(5);
+        let (a, b) = pipe();
+        let files = [first.clone(), second.clone()];
+        let sender = thread::spawn(move || {
+            let mut xmodem = Xmodem::new(a, StdClock::new()).with_config(config(Mode::Crc1k));
+            for (name, data) in ["first.bin", "second.bin"].iter().zip(files.iter()) {
+                let info = FileInfo {
+                    name,
+                    size: Some(data.len() as u64),
+                };
+                xmodem.send_ymodem(&info, &mut &data[..])?;
+            }
+            xmodem.finish_ymodem()
+        });
+
+        #[derive(Default)]
+        struct Files(Vec<(String, Option<u64>, Vec<u8>)>);
+        impl Sink for Files {
+            fn open(&mut self, info: &FileInfo) -> Result<(), SinkError> {
+                self.0.push((info.name.into(), info.size, Vec::new()));
+                Ok(())
+            }
+            fn write(&mut self, data: &[u8]) -> Result<(), SinkError> {
+                self.0
+                    .last_mut()
+                    .ok_or(SinkError)?
+                    .2
+                    .extend_from_slice(data);
+                Ok(())
+            }
+        }
+
+        let mut sink = Files::default();
+        let mut progress = Vec::new();
+        let mut xmodem = Xmodem::new(b, StdClock::new())
+            .with_config(config(Mode::Crc1k))
+            .with_progress(|p| {
+                progress.push(p);
+                true
+            });
+        assert_eq!(xmodem.receive_ymodem(&mut sink), Ok(2));
+        assert_eq!(sender.join().unwrap(), Ok(()));
+
+        assert_eq!(sink.0[0], ("first.bin".into(), Some(3000), first));
+        // Truncated to the size in the header
+        assert_eq!(sink.0[1], ("second.bin".into(), Some(5), second));
+        assert_eq!(
+            progress[..3],
+            [1024, 2048, 3000].map(|bytes| Progress {
+                bytes,
+                total: Some(3000)
+            })
+        );
+    }
+
+    #[test]
+    fn cancel() {
+        let data = image(2000);
+        let (a, b) = pipe();
+        let sender = thread::spawn(move || {
+            let mut xmodem = Xmodem::new(a, StdClock::new()).with_config(config(Mode::Crc));
+            xmodem.send(&mut &data[..])
+        });
+
+        // The receiver gives up after the first block
+        let mut buf = [0; 4096];
+        let mut xmodem = Xmodem::new(b, StdClock::new())
+            .with_config(config(Mode::Crc))
+            .with_progress(|p| p.bytes < 128);
+        assert_eq!(
+            xmodem.receive(&mut SliceSink::new(&mut buf)),
+            Err(Error::Aborted)
+        );
+        assert_eq!(sender.join().unwrap(), Err(Error::Cancelled));
+    }
+
+    #[test]
+    fn timeout() {
+        let (a, _b) = pipe();
+        let mut xmodem = Xmodem::new(a, StdClock::new()).with_config(config(Mode::Crc));
+        assert_eq!(xmodem.send(&mut &[0u8; 10][..]), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn header() {
+        let mut block = [0; 128];
+        block[..15].copy_from_slice(b"a.bin\x001234 777\x00");
+        assert_eq!(
+            parse_header(&block),
+            Some(FileInfo {
+                name: "a.bin",
+                size: Some(1234)
+            })
+        );
+        assert_eq!(parse_header(b"a.bin\0\0").unwrap().size, None);
+
+        let mut buf = [0; 20];
+        assert_eq!(write_decimal(&mut buf, 0), 1);
+        assert_eq!(write_decimal(&mut buf, u64::MAX), 20);
+        assert_eq!(&buf, b"18446744073709551615");
+    }
+}
@@ -0,0 +1,285 @@
+/*!
+# xonxoff
+
+Software flow control over any [`Serial`]. Received XOFF (DC3) pauses writing and XON (DC1)
+resumes it. Received data is kept in a buffer, and XOFF is sent when it fills up to the high
+watermark, XON once it has been read down to the low watermark.
+
+```ignore
+let mut serial = XonXoff::new(uart, [0; 64]).with_config(XonXoffConfig {
+    high: 48,
+    low: 16,
+    ..XonXoffConfig::default()
+});
+
+// Call often enough that the peer's XOFF is seen and the buffer doesn't overflow, e.g. from the
+// receive interrupt
+serial.poll();
+while let Some(byte) = serial.read_byte() {
+    // ...
+}
+serial.write_all(b"hello");
+```
+
+`XonXoff` is itself a [`Serial`], so the other helpers of this crate can run on top of it. Flow
+control characters are sent even while writing is paused.
+*/
+
+use crate::serial::{Serial, WouldBlock};
+
+/// DC1, resume transmission
+pub const XON: u8 = 0x11;
+/// DC3, pause transmission
+pub const XOFF: u8 = 0x13;
+
+/// Characters and watermarks of [`XonXoff`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct XonXoffConfig {
+    /// Character resuming transmission
+    pub xon: u8,
+    /// Character pausing transmission
+    pub xoff: u8,
+    /// Send XOFF when this many bytes are buffered
+    pub high: usize,
+    /// Send XON when the buffer has been read down to this many bytes
+    pub low: usize,
+    /// Also deliver received XON and XOFF as data
+    pub passthrough: bool,
+}
+
+impl Default for XonXoffConfig {
+    /// DC1 and DC3, watermarks at 3/4 and 1/4 of a 64 byte buffer, no passthrough
+    fn default() -> Self {
+        Self {
+            xon: XON,
+            xoff: XOFF,
+            high: 48,
+            low: 16,
+            passthrough: false,
+        }
+    }
+}
+
+/// A [`Serial`] with XON/XOFF flow control and a receive buffer
+pub struct XonXoff<S, B> {
+    serial: S,
+    buf: B,
+    head: usize,
+    len: usize,
+    config: XonXoffConfig,
+    /// The peer sent XOFF
+    paused: bool,
+    /// We sent XOFF
+    throttled: bool,
+    /// XON or XOFF waiting for room in the transmitter
+    pending: Option<u8>,
+    overflow: bool,
+}
+
+impl<S: Serial, B: AsMut<[u8]>> XonXoff<S, B> {
+    /// Add flow control to `serial`, buffering received data in `buf`
+    pub fn new(serial: S, buf: B) -> Self {
+        Self {
+            serial,
+            buf,
+            head: 0,
+            len: 0,
+            config: XonXoffConfig::default(),
+            paused: false,
+            throttled: false,
+            pending: None,
+            overflow: false,
+        }
+    }
+
+    /// Use `config` instead of the default
+    pub fn with_config(mut self, config: XonXoffConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> XonXoffConfig {
+        self.config
+    }
+
+    /// Set the configuration
+    pub fn set_config(&mut self, config: XonXoffConfig) {
+        self.config = config;
+    }
+
+    /// Get the wrapped serial
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Return the serial and the buffer, dropping any buffered data
+    pub fn free(self) -> (S, B) {
+        (self.serial, self.buf)
+    }
+
+    /// Whether the peer has paused our transmission
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether we have paused the peer's transmission
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Number of bytes buffered
+    pub fn buffered(&self) -> usize {
+        self.len
+    }
+
+    /// Whether received data was dropped because the buffer was full, clearing the flag
+    pub fn take_overflow(&mut self) -> bool {
+        core::mem::take(&mut self.overflow)
+    }
+
+    /// Drop buffered data, resume our transmission and send XON
+    ///
+    /// XON is sent even if we never sent XOFF, in case the peer lost track of it.
+    pub fn reset(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.paused = false;
+        self.throttled = false;
+        self.overflow = false;
+        self.pending = Some(self.config.xon);
+        self.send_pending();
+    }
+
+    /// Read everything the serial has received, handling flow control characters
+    pub fn poll(&mut self) {
+        while let Some(byte) = self.serial.read_byte() {
+            let control = byte == self.config.xon || byte == self.config.xoff;
+            if byte == self.config.xoff {
+                self.paused = true;
+            } else if byte == self.config.xon {
+                self.paused = false;
+            }
+            if !control || self.config.passthrough {
+                self.store(byte);
+            }
+        }
+        if !self.throttled && self.len >= self.config.high {
+            self.throttled = true;
+            self.pending = Some(self.config.xoff);
+        }
+        self.send_pending();
+    }
+
+    fn store(&mut self, byte: u8) {
+        let buf = self.buf.as_mut();
+        if self.len == buf.len() {
+            self.overflow = true;
+            return;
+        }
+        buf[(self.head + self.len) % buf.len()] = byte;
+        self.len += 1;
+    }
+
+    fn send_pending(&mut self) {
+        if let Some(byte) = self.pending {
+            if self.serial.write_byte(byte).is_ok() {
+                self.pending = None;
+            }
+        }
+    }
+}
+
+impl<S: Serial, B: AsMut<[u8]>> Serial for XonXoff<S, B> {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.poll();
+        if self.len == 0 {
+            return None;
+        }
+        let buf = self.buf.as_mut();
+        let byte = buf[self.head];
+        self.head = (self.head + 1) % buf.len();
+        self.len -= 1;
+        if self.throttled && self.len <= self.config.low {
+            self.throttled = false;
+            self.pending = Some(self.config.xon);
+            self.send_pending();
+        }
+        Some(byte)
+    }
+
+    /// `Err(WouldBlock)` while the peer has paused us or a flow control character is waiting
+    fn write_byte(&mut self, byte: u8) -> Result<(), WouldBlock> {
+        self.poll();
+        if self.paused || self.pending.is_some() {
+            return Err(WouldBlock);
+        }
+        self.serial.write_byte(byte)
+    }
+
+    fn flush(&mut self) {
+        self.serial.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::fake::FakeSerial;
+
+    fn xonxoff(input: &[u8]) -> XonXoff<FakeSerial, [u8; 8]> {
+        XonXoff::new(FakeSerial::new(input), [0; 8]).with_config(XonXoffConfig {
+            high: 6,
+            low: 2,
+            ..XonXoffConfig::default()
+        })
+    }
+
+    #[test]
+    fn pause_resume() {
+        let mut serial = xonxoff(&[b'a', XOFF, b'b']);
+
+        assert_eq!(serial.write_byte(b'x'), Err(WouldBlock));
+        assert!(serial.is_paused());
+        serial.serial_mut().input.push_back(XON);
+        assert_eq!(serial.write_byte(b'y'), Ok(()));
+        assert_eq!(serial.serial_mut().output, b"y");
+        assert_eq!(serial.read_byte(), Some(b'a'));
+        assert_eq!(serial.read_byte(), Some(b'b'));
+        assert_eq!(serial.read_byte(), None);
+
+        let mut serial = xonxoff(&[b'a', XOFF, XON]).with_config(XonXoffConfig {
+            xon: b'+',
+            xoff: b'-',
+            passthrough: true,
+            ..XonXoffConfig::default()
+        });
+        serial.serial_mut().input.extend(b"-b");
+        serial.poll();
+        assert!(serial.is_paused());
+        assert_eq!(serial.buffered(), 5);
+    }
+
+    #[test]
+    fn watermarks() {
+        let mut serial = xonxoff(b"0123456789");
+
+        serial.poll();
+        assert!(serial.is_throttled());
+        assert!(serial.take_overflow());
+        assert_eq!(serial.serial_mut().output, [XOFF]);
+        for expected in b"01234" {
+            assert_eq!(serial.read_byte(), Some(*expected));
+        }
+        assert!(serial.is_throttled());
+        assert_eq!(serial.read_byte(), Some(b'5'));
+        assert!(!serial.is_throttled());
+        assert_eq!(serial.serial_mut().output, [XOFF, XON]);
+
+        serial.serial_mut().input.extend(b"9876543");
+        serial.poll();
+        serial.reset();
+        assert_eq!(serial.buffered(), 0);
+        assert_eq!(serial.serial_mut().output, [XOFF, XON, XOFF, XON]);
+    }
+}
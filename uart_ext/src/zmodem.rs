@@ -0,0 +1,775 @@
+/*!
+# zmodem
+
+Streaming ZMODEM receiver over any [`Serial`], written for `sz` from lrzsz.
+
+The tests feed it sender streams modelled on the lrzsz source, not recorded from `sz`, so
+interoperability with a real `sz` is not covered by them yet.
+
+Data subpackets are checked with CRC-16 or CRC-32, whichever the sender picks, and written to the
+[`Sink`] as soon as they are verified. A transfer resumes at [`Sink::offset`], and the receive
+window advertised in ZRINIT makes the sender wait for acknowledgements.
+
+```ignore
+let mut zmodem = Zmodem::new(uart, clock);
+let mut buf = [0; 1024];
+let files = zmodem.receive(&mut flash_sink, &mut buf)?;
+```
+
+## REF
+
+- [The ZMODEM Inter Application File Transfer Protocol, Chuck Forsberg](http://pauillac.inria.fr/~doligez/zmodem/zmodem.txt)
+*/
+
+use core::fmt::{self, Display, Formatter};
+use core::str;
+
+use crate::crc::{crc16_xmodem, crc32_update};
+use crate::serial::Serial;
+use crate::timeout::{read_byte_timeout, Monotonic};
+use crate::transfer::{FileInfo, Progress, Sink};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const CAN: u8 = 0x18;
+const BS: u8 = 0x08;
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+/// Header formats, after ZPAD ZDLE
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+/// Frame types
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZCAN: u8 = 16;
+
+/// Subpacket ends, after ZDLE
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// ZRINIT capabilities in ZF0
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+
+/// Consecutive CAN that cancel a session
+const CANCEL_COUNT: usize = 5;
+/// Bytes skipped while looking for a header before giving up
+const MAX_GARBAGE: usize = 4096;
+
+/// Settings of a transfer
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Receive window advertised to the sender, 0 to let it stream the whole file
+    ///
+    /// The sender waits for an acknowledgement every `window` bytes.
+    pub window: u16,
+    /// How many times a header is re-requested before giving up
+    pub retries: u8,
+    /// How long to wait for a header
+    pub timeout_us: u64,
+    /// How long to wait for each byte inside a header or subpacket
+    pub byte_timeout_us: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window: 0,
+            retries: 10,
+            timeout_us: 10_000_000,
+            byte_timeout_us: 1_000_000,
+        }
+    }
+}
+
+/// An error ending a transfer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The sender stopped answering
+    Timeout,
+    /// Headers or subpackets were corrupted more times than `Config::retries`
+    TooManyErrors,
+    /// The sender cancelled the session
+    Cancelled,
+    /// The progress callback asked to stop
+    Aborted,
+    /// The sink refused the data
+    Sink,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Timeout => "transfer timed out",
+            Self::TooManyErrors => "too many errors",
+            Self::Cancelled => "transfer cancelled by the remote",
+            Self::Aborted => "transfer aborted",
+            Self::Sink => "sink refused the data",
+        })
+    }
+}
+
+enum ReadError {
+    Timeout,
+    Corrupt,
+    Cancelled,
+}
+
+/// A byte read through the ZDLE escaping
+enum Escaped {
+    Byte(u8),
+    /// ZDLE followed by a subpacket end
+    End(u8),
+}
+
+#[derive(Copy, Clone)]
+struct Header {
+    kind: u8,
+    /// ZP0..ZP3, ZP0 being the lowest byte of a position and ZF0 the same byte as ZP3
+    data: [u8; 4],
+    crc32: bool,
+}
+
+impl Header {
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+}
+
+/// What to send again when the sender goes quiet
+#[derive(Copy, Clone)]
+enum Request {
+    Init,
+    Position,
+}
+
+/// # ZMODEM receiver
+///
+/// Receives over `serial`, using `clock` for timeouts. The progress callback is called after
+/// every data subpacket and stops the transfer by returning `false`.
+pub struct Zmodem<S, M, P = fn(Progress) -> bool> {
+    serial: S,
+    clock: M,
+    config: Config,
+    progress: P,
+}
+
+impl<S: Serial, M: Monotonic> Zmodem<S, M> {
+    pub fn new(serial: S, clock: M) -> Self {
+        Self {
+            serial,
+            clock,
+            config: Config::default(),
+            progress: |_| true,
+        }
+    }
+}
+
+impl<S: Serial, M: Monotonic, P: FnMut(Progress) -> bool> Zmodem<S, M, P> {
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_progress<F: FnMut(Progress) -> bool>(self, progress: F) -> Zmodem<S, M, F> {
+        Zmodem {
+            serial: self.serial,
+            clock: self.clock,
+            config: self.config,
+            progress,
+        }
+    }
+
+    /// Give back the serial and the clock
+    pub fn free(self) -> (S, M) {
+        (self.serial, self.clock)
+    }
+
+    /// Cancel the session the way lrzsz does: 10 CAN then 10 backspaces
+    pub fn cancel(&mut self) {
+        self.serial.write_all(&[CAN; 10]);
+        self.serial.write_all(&[BS; 10]);
+    }
+
+    /// Receive a batch of files into `sink`, returning how many were received
+    ///
+    /// `buf` holds one data subpacket: 1024 bytes for `sz`, 8192 for `sz --8k`. Files the sink
+    /// refuses to open are skipped.
+    pub fn receive<T: Sink + ?Sized>(
+        &mut self,
+        sink: &mut T,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut files = 0;
+        let mut errors = 0;
+        let mut position = 0;
+        let mut size = None;
+        let mut in_file = false;
+        let mut request = Request::Init;
+        self.send_zrinit();
+
+        loop {
+            let header = match self.read_header() {
+                Ok(header) => header,
+                Err(ReadError::Cancelled) => return Err(Error::Cancelled),
+                Err(error) => {
+                    errors += 1;
+                    if errors > self.config.retries {
+                        self.cancel();
+                        return Err(match error {
+                            ReadError::Timeout => Error::Timeout,
+                            _ => Error::TooManyErrors,
+                        });
+                    }
+                    match request {
+                        Request::Init => self.send_zrinit(),
+                        Request::Position => self.send_position(ZRPOS, position),
+                    }
+                    continue;
+                }
+            };
+
+            match header.kind {
+                ZRQINIT => self.send_zrinit(),
+                ZSINIT => {
+                    // The attention string is of no use to us
+                    match self.read_subpacket(buf, header.crc32) {
+                        Ok(_) => self.send_position(ZACK, 0),
+                        Err(ReadError::Cancelled) => return Err(Error::Cancelled),
+                        Err(_) => self.send_header(ZNAK, [0; 4]),
+                    }
+                }
+                ZFILE => {
+                    let len = match self.read_subpacket(buf, header.crc32) {
+                        Ok((len, _)) => len,
+                        Err(ReadError::Cancelled) => return Err(Error::Cancelled),
+                        Err(_) => {
+                            self.send_header(ZNAK, [0; 4]);
+                            continue;
+                        }
+                    };
+                    let opened = match parse_file_info(&buf[..len]) {
+                        Some(info) => {
+                            size = info.size;
+                            sink.open(&info).is_ok()
+                        }
+                        None => false,
+                    };
+                    if opened {
+                        in_file = true;
+                        position = sink.offset();
+                        request = Request::Position;
+                        self.send_position(ZRPOS, position);
+                    } else {
+                        self.send_header(ZSKIP, [0; 4]);
+                    }
+                }
+                ZDATA if in_file => {
+                    if header.position() != position {
+                        // Data we already have, or a gap: ask again from where we are
+                        errors += 1;
+                        self.send_position(ZRPOS, position);
+                        continue;
+                    }
+                    match self.receive_frame(sink, buf, header.crc32, &mut position, size) {
+                        Ok(()) => errors = 0,
+                        Err(Some(error)) => return Err(error),
+                        Err(None) => {
+                            errors += 1;
+                            if errors > self.config.retries {
+                                self.cancel();
+                                return Err(Error::TooManyErrors);
+                            }
+                            self.send_position(ZRPOS, position);
+                        }
+                    }
+                }
+                // Without a file, the sender has lost track: start over
+                ZDATA => self.send_zrinit(),
+                // A ZEOF for another position is stale, the sender sends the data again
+                ZEOF if in_file && header.position() == position => {
+                    sink.close().map_err(|_| Error::Sink)?;
+                    files += 1;
+                    in_file = false;
+                    request = Request::Init;
+                    self.send_zrinit();
+                }
+                ZFIN => {
+                    self.send_header(ZFIN, [0; 4]);
+                    // "Over and out", which may never come
+                    for _ in 0..2 {
+                        if self.read(self.config.byte_timeout_us).is_none() {
+                            break;
+                        }
+                    }
+                    return Ok(files);
+                }
+                ZCAN | ZABORT => return Err(Error::Cancelled),
+                ZNAK => match request {
+                    Request::Init => self.send_zrinit(),
+                    Request::Position => self.send_position(ZRPOS, position),
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Receive the subpackets of one ZDATA frame
+    ///
+    /// `Err(None)` means a corrupted subpacket, to be asked again with ZRPOS.
+    fn receive_frame<T: Sink + ?Sized>(
+        &mut self,
+        sink: &mut T,
+        buf: &mut [u8],
+        crc32: bool,
+        position: &mut u64,
+        size: Option<u64>,
+    ) -> Result<(), Option<Error>> {
+        loop {
+            let (len, end) = match self.read_subpacket(buf, crc32) {
+                Ok(subpacket) => subpacket,
+                Err(ReadError::Cancelled) => return Err(Some(Error::Cancelled)),
+                Err(_) => return Err(None),
+            };
+            if sink.write(&buf[..len]).is_err() {
+                self.cancel();
+                return Err(Some(Error::Sink));
+            }
+            *position += len as u64;
+            if !(self.progress)(Progress {
+                bytes: *position,
+                total: size,
+            }) {
+                self.cancel();
+                return Err(Some(Error::Aborted));
+            }
+
+            match end {
+                ZCRCG => {}
+                ZCRCQ => self.send_position(ZACK, *position),
+                ZCRCW => {
+                    self.send_position(ZACK, *position);
+                    return Ok(());
+                }
+                // ZCRCE
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read(&mut self, timeout_us: u64) -> Option<u8> {
+        read_byte_timeout(&mut self.serial, &mut self.clock, timeout_us)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, ReadError> {
+        self.read(self.config.byte_timeout_us)
+            .ok_or(ReadError::Timeout)
+    }
+
+    /// Read a byte through the ZDLE escaping, dropping flow control characters
+    fn read_escaped(&mut self) -> Result<Escaped, ReadError> {
+        loop {
+            match self.read_byte()? {
+                ZDLE => break,
+                byte if byte & 0x7f == XON || byte & 0x7f == XOFF => {}
+                byte => return Ok(Escaped::Byte(byte)),
+            }
+        }
+        let mut cans = 1;
+        loop {
+            match self.read_byte()? {
+                CAN => {
+                    cans += 1;
+                    if cans >= CANCEL_COUNT {
+                        return Err(ReadError::Cancelled);
+                    }
+                }
+                end @ (ZCRCE | ZCRCG | ZCRCQ | ZCRCW) => return Ok(Escaped::End(end)),
+                ZRUB0 => return Ok(Escaped::Byte(0x7f)),
+                ZRUB1 => return Ok(Escaped::Byte(0xff)),
+                byte if byte & 0x7f == XON || byte & 0x7f == XOFF => {}
+                byte if byte & 0x60 == 0x40 => return Ok(Escaped::Byte(byte ^ 0x40)),
+                _ => return Err(ReadError::Corrupt),
+            }
+        }
+    }
+
+    fn read_escaped_byte(&mut self) -> Result<u8, ReadError> {
+        match self.read_escaped()? {
+            Escaped::Byte(byte) => Ok(byte),
+            Escaped::End(_) => Err(ReadError::Corrupt),
+        }
+    }
+
+    /// Read two hex digits, ignoring parity
+    fn read_hex_byte(&mut self) -> Result<u8, ReadError> {
+        let mut digit = || match self.read_byte()? & 0x7f {
+            digit @ b'0'..=b'9' => Ok(digit - b'0'),
+            digit @ b'a'..=b'f' => Ok(digit - b'a' + 10),
+            _ => Err(ReadError::Corrupt),
+        };
+        Ok(digit()? << 4 | digit()?)
+    }
+
+    /// Skip to the next header and read it
+    fn read_header(&mut self) -> Result<Header, ReadError> {
+        let mut garbage = 0;
+        let mut cans = 0;
+        let format = loop {
+            let byte = self
+                .read(self.config.timeout_us)
+                .ok_or(ReadError::Timeout)?;
+            if byte == CAN {
+                cans += 1;
+                if cans >= CANCEL_COUNT {
+                    return Err(ReadError::Cancelled);
+                }
+            } else {
+                cans = 0;
+            }
+
+            if byte & 0x7f == ZPAD {
+                let mut byte = self.read_byte()?;
+                while byte & 0x7f == ZPAD {
+                    byte = self.read_byte()?;
+                }
+                if byte == ZDLE {
+                    if let format @ (ZBIN | ZHEX | ZBIN32) = self.read_byte()? {
+                        break format;
+                    }
+                }
+            }
+            garbage += 1;
+            if garbage > MAX_GARBAGE {
+                return Err(ReadError::Corrupt);
+            }
+        };
+
+        let mut bytes = [0; 5];
+        let valid = match format {
+            ZHEX => {
+                for byte in bytes.iter_mut() {
+                    *byte = self.read_hex_byte()?;
+                }
+                let crc = u16::from_be_bytes([self.read_hex_byte()?, self.read_hex_byte()?]);
+                // CR LF, the LF usually with its parity bit set
+                if self.read_byte()? & 0x7f == b'\r' {
+                    self.read_byte()?;
+                }
+                crc16_xmodem(0, &bytes) == crc
+            }
+            ZBIN => {
+                for byte in bytes.iter_mut() {
+                    *byte = self.read_escaped_byte()?;
+                }
+                let crc =
+                    u16::from_be_bytes([self.read_escaped_byte()?, self.read_escaped_byte()?]);
+                crc16_xmodem(0, &bytes) == crc
+            }
+            _ => {
+                for byte in bytes.iter_mut() {
+                    *byte = self.read_escaped_byte()?;
+                }
+                let mut crc = [0; 4];
+                for byte in crc.iter_mut() {
+                    *byte = self.read_escaped_byte()?;
+                }
+                !crc32_update(!0, &bytes) == u32::from_le_bytes(crc)
+            }
+        };
+        if !valid {
+            return Err(ReadError::Corrupt);
+        }
+        Ok(Header {
+            kind: bytes[0],
+            data: [bytes[1], bytes[2], bytes[3], bytes[4]],
+            crc32: format == ZBIN32,
+        })
+    }
+
+    /// Read a data subpacket into `buf`, returning its length and how it ended
+    fn read_subpacket(&mut self, buf: &mut [u8], crc32: bool) -> Result<(usize, u8), ReadError> {
+        let mut len = 0;
+        let end = loop {
+            match self.read_escaped()? {
+                Escaped::Byte(byte) => {
+                    *buf.get_mut(len).ok_or(ReadError::Corrupt)? = byte;
+                    len += 1;
+                }
+                Escaped::End(end) => break end,
+            }
+        };
+
+        let valid = if crc32 {
+            let mut crc = [0; 4];
+            for byte in crc.iter_mut() {
+                *byte = self.read_escaped_byte()?;
+            }
+            let expected = crc32_update(!0, &buf[..len]);
+            !crc32_update(expected, &[end]) == u32::from_le_bytes(crc)
+        } else {
+            let crc = u16::from_be_bytes([self.read_escaped_byte()?, self.read_escaped_byte()?]);
+            crc16_xmodem(crc16_xmodem(0, &buf[..len]), &[end]) == crc
+        };
+        if valid {
+            Ok((len, end))
+        } else {
+            Err(ReadError::Corrupt)
+        }
+    }
+
+    /// Send a hex header, the kind the receiver side of lrzsz sends
+    fn send_header(&mut self, kind: u8, data: [u8; 4]) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let mut bytes = [kind, data[0], data[1], data[2], data[3], 0, 0];
+        let crc = crc16_xmodem(0, &bytes[..5]).to_be_bytes();
+        bytes[5..].copy_from_slice(&crc);
+
+        self.serial.write_all(&[ZPAD, ZPAD, ZDLE, ZHEX]);
+        for byte in bytes {
+            self.serial
+                .write_all(&[HEX[(byte >> 4) as usize], HEX[(byte & 0xf) as usize]]);
+        }
+        self.serial.write_all(b"\r\x8a");
+        if kind != ZACK && kind != ZFIN {
+            self.serial.write_byte_blocking(XON);
+        }
+    }
+
+    fn send_position(&mut self, kind: u8, position: u64) {
+        self.send_header(kind, (position as u32).to_le_bytes());
+    }
+
+    fn send_zrinit(&mut self) {
+        let [window_low, window_high] = self.config.window.to_le_bytes();
+        self.send_header(
+            ZRINIT,
+            [window_low, window_high, 0, CANFDX | CANOVIO | CANFC32],
+        );
+    }
+}
+
+/// Parse `name NUL [size [mtime [mode ...]]] NUL`
+fn parse_file_info(data: &[u8]) -> Option<FileInfo<'_>> {
+    let name_len = data.iter().position(|byte| *byte == 0)?;
+    let name = str::from_utf8(&data[..name_len]).ok()?;
+    let rest = &data[name_len + 1..];
+    let digits = rest
+        .iter()
+        .position(|byte| !byte.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let size = str::from_utf8(&rest[..digits]).ok()?.parse().ok();
+    Some(FileInfo { name, size })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::serial::fake::{FakeSerial, StdClock};
+    use crate::transfer::SinkError;
+    use std::string::String;
+    use std::vec::Vec;
+
+    const BATCH: &[u8] = include_bytes!("../tests/zmodem/batch.bin");
+    const RESUME: &[u8] = include_bytes!("../tests/zmodem/resume.bin");
+    const WINDOW: &[u8] = include_bytes!("../tests/zmodem/window.bin");
+    const CRC_ERROR: &[u8] = include_bytes!("../tests/zmodem/crc-error.bin");
+    const CANCEL: &[u8] = include_bytes!("../tests/zmodem/cancel.bin");
+
+    /// The image sent in the streams of `tests/zmodem`, which are synthetic, see its README
+    fn image() -> Vec<u8> {
+        (0..3000).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    #[derive(Default)]
+    struct Files {
+        files: Vec<(String, Option<u64>, Vec<u8>)>,
+        closed: usize,
+        /// Bytes of the first file already there, for resuming
+        present: u64,
+    }
+
+    impl Sink for Files {
+        fn open(&mut self, info: &FileInfo) -> Result<(), SinkError> {
+            self.files.push((info.name.into(), info.size, Vec::new()));
+            Ok(())
+        }
+
+        fn offset(&mut self) -> u64 {
+            self.present
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), SinkError> {
+            self.files
+                .last_mut()
+                .ok_or(SinkError)?
+                .2
+                .extend_from_slice(data);
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), SinkError> {
+            self.closed += 1;
+            Ok(())
+        }
+    }
+
+    fn receive(
+        stream: &[u8],
+        config: Config,
+        sink: &mut Files,
+    ) -> (Result<usize, Error>, Vec<(u8, u64)>) {
+        let mut zmodem = Zmodem::new(FakeSerial::new(stream), StdClock::new()).with_config(config);
+        let mut buf = [0; 1024];
+        let result = zmodem.receive(sink, &mut buf);
+        let (serial, _) = zmodem.free();
+        (result, headers(&serial.output))
+    }
+
+    fn config() -> Config {
+        Config {
+            window: 0,
+            retries: 3,
+            timeout_us: 50_000,
+            byte_timeout_us: 10_000,
+        }
+    }
+
+    /// Decode the hex headers sent by the receiver into (type, position)
+    fn headers(mut output: &[u8]) -> Vec<(u8, u64)> {
+        let mut headers = Vec::new();
+        while let Some(start) = output.windows(4).position(|w| w == b"**\x18B") {
+            let hex = str::from_utf8(&output[start + 4..start + 18]).unwrap();
+            let byte = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+            let bytes = [byte(0), byte(1), byte(2), byte(3), byte(4)];
+            assert_eq!(
+                crc16_xmodem(0, &bytes),
+                (byte(5) as u16) << 8 | byte(6) as u16
+            );
+            headers.push((
+                bytes[0],
+                u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as u64,
+            ));
+            output = &output[start + 18..];
+        }
+        headers
+    }
+
+    #[test]
+    fn batch() {
+        let mut sink = Files::default();
+        let (result, headers) = receive(BATCH, config(), &mut sink);
+        assert_eq!(result, Ok(2));
+        assert_eq!(sink.closed, 2);
+        assert_eq!(
+            sink.files[0],
+            ("hello.txt".into(), Some(15), b"Hello, ZMODEM!\n".to_vec())
+        );
+        assert_eq!(sink.files[1], ("image.bin".into(), Some(3000), image()));
+
+        let zrinit = (ZRINIT, (((CANFDX | CANOVIO | CANFC32) as u64) << 24));
+        assert_eq!(
+            headers,
+            [
+                zrinit,
+                zrinit,
+                (ZRPOS, 0),
+                zrinit,
+                (ZRPOS, 0),
+                zrinit,
+                (ZFIN, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn resume() {
+        let mut sink = Files {
+            present: 1000,
+            ..Files::default()
+        };
+        let (result, headers) = receive(RESUME, config(), &mut sink);
+        assert_eq!(result, Ok(1));
+        assert_eq!(sink.files[0].2, image()[1000..]);
+        assert_eq!(headers[2], (ZRPOS, 1000));
+    }
+
+    #[test]
+    fn window() {
+        let mut sink = Files::default();
+        let config = Config {
+            window: 512,
+            ..config()
+        };
+        let (result, headers) = receive(WINDOW, config, &mut sink);
+        assert_eq!(result, Ok(1));
+        assert_eq!(sink.files[0].2, image());
+
+        // The window is advertised in ZP0 and ZP1
+        assert_eq!(headers[0], (ZRINIT, 0x2300_0200));
+        let acks: Vec<u64> = headers
+            .iter()
+            .filter(|(kind, _)| *kind == ZACK)
+            .map(|(_, position)| *position)
+            .collect();
+        assert_eq!(acks, (1..=11).map(|i| i * 256).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn crc_error() {
+        let mut sink = Files::default();
+        let mut progress = Vec::new();
+        let mut zmodem = Zmodem::new(FakeSerial::new(CRC_ERROR), StdClock::new())
+            .with_config(config())
+            .with_progress(|p| {
+                progress.push(p.bytes);
+                true
+            });
+        let mut buf = [0; 1024];
+        assert_eq!(zmodem.receive(&mut sink, &mut buf), Ok(1));
+        let (serial, _) = zmodem.free();
+
+        assert_eq!(sink.files[0].2, image());
+        assert_eq!(progress, [1024, 2048, 3000]);
+        // Asked again from 1024 after the bad subpacket
+        assert_eq!(headers(&serial.output)[2..4], [(ZRPOS, 0), (ZRPOS, 1024)]);
+    }
+
+    #[test]
+    fn cancelled() {
+        let mut sink = Files::default();
+        let (result, _) = receive(CANCEL, config(), &mut sink);
+        assert_eq!(result, Err(Error::Cancelled));
+        assert_eq!(sink.files[0].2, image()[..1024]);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut sink = Files::default();
+        let (result, headers) = receive(b"", config(), &mut sink);
+        assert_eq!(result, Err(Error::Timeout));
+        // ZRINIT, then again on every timeout
+        assert_eq!(headers.len(), 4);
+    }
+}
@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""Write the PPP frames the hdlc tests decode, framed as RFC 1662 describes.
+
+The FCS-16 uses the table of RFC 1662 Appendix C.1, the FCS-32 the one zlib implements.
+"""
+
+import binascii
+
+FLAG, ESC = 0x7E, 0x7D
+
+
+def fcs16_table():
+    table = []
+    for b in range(256):
+        v = b
+        for _ in range(8):
+            v = (v >> 1) ^ 0x8408 if v & 1 else v >> 1
+        table.append(v)
+    return table
+
+
+FCSTAB = fcs16_table()
+
+
+def fcs16(data):
+    fcs = 0xFFFF
+    for byte in data:
+        fcs = (fcs >> 8) ^ FCSTAB[(fcs ^ byte) & 0xFF]
+    return (fcs ^ 0xFFFF).to_bytes(2, "little")
+
+
+def fcs32(data):
+    return (binascii.crc32(data) & 0xFFFFFFFF).to_bytes(4, "little")
+
+
+def stuff(data, accm):
+    out = bytearray()
+    for byte in data:
+        if byte in (FLAG, ESC) or (byte < 0x20 and accm >> byte & 1):
+            out += bytes([ESC, byte ^ 0x20])
+        else:
+            out.append(byte)
+    return bytes(out)
+
+
+def frame(data, fcs=fcs16, accm=0xFFFFFFFF):
+    return bytes([FLAG]) + stuff(data + fcs(data), accm) + bytes([FLAG])
+
+
+# LCP Configure-Request: MRU 1500, ACCM 0, magic number 0x12345678
+LCP = bytes.fromhex("ff03c021 0101 0014 010405dc 020600000000 050612345678")
+# IPv4 header of a UDP datagram, with a flag and an escape in the payload
+IP = bytes.fromhex("ff030021 4500001c00004000401100007f0000017f000001 1f901f90000800007e7d1103")
+
+files = {
+    "lcp.bin": frame(LCP),
+    "ip-fcs32.bin": frame(IP, fcs=fcs32, accm=0),
+    # Aborted, bad FCS, too short for an FCS, then a good frame
+    "errors.bin": bytes([FLAG]) + stuff(LCP[:6], 0xFFFFFFFF) + bytes([ESC, FLAG])
+    + frame(LCP)[:-4] + bytes([0x55]) + frame(LCP)[-3:]
+    + bytes([FLAG, 0x41, FLAG])
+    + frame(LCP),
+}
+
+for name, data in files.items():
+    with open(name, "wb") as f:
+        f.write(data)
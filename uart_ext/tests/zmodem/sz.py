@@ -0,0 +1,167 @@
+#!/usr/bin/env python3
+"""Write synthetic sender streams for the sessions the zmodem tests replay.
+
+They model what `sz` (lrzsz 0.12.20) sends, following its source, but are not recordings of
+it; see README.md for how to record real sessions. Only the sender side is generated: the tests
+feed it to the receiver and check its answers.
+"""
+
+import binascii
+
+ZPAD, ZDLE = 0x2A, 0x18
+ZRQINIT, ZFILE, ZDATA, ZEOF, ZFIN = 0, 4, 10, 11, 8
+ZCRCE, ZCRCG, ZCRCQ, ZCRCW = b"hijk"
+
+
+def crc16(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x1021 if crc & 0x8000 else crc << 1) & 0xFFFF
+    return crc
+
+
+def crc32(data):
+    return binascii.crc32(data) & 0xFFFFFFFF
+
+
+def escape(data):
+    """zsendline: ZDLE, DLE, XON, XOFF with or without parity, and CR after '@'"""
+    out = bytearray()
+    last = 0
+    for byte in data:
+        if byte in (ZDLE, 0x10, 0x11, 0x13, 0x90, 0x91, 0x93) or (
+            byte & 0x7F == 0x0D and last & 0x7F == 0x40
+        ):
+            out += bytes([ZDLE, byte ^ 0x40])
+        else:
+            out.append(byte)
+        last = byte
+    return bytes(out)
+
+
+def hex_header(kind, pos=0):
+    body = bytes([kind]) + pos.to_bytes(4, "little")
+    body += crc16(body).to_bytes(2, "big")
+    out = bytes([ZPAD, ZPAD, ZDLE]) + b"B" + body.hex().encode() + b"\r\x8a"
+    if kind not in (3, ZFIN):
+        out += b"\x11"
+    return out
+
+
+def bin32_header(kind, data=b"\0\0\0\0"):
+    body = bytes([kind]) + data
+    return bytes([ZPAD, ZDLE]) + b"C" + escape(body + crc32(body).to_bytes(4, "little"))
+
+
+def pos(offset):
+    return offset.to_bytes(4, "little")
+
+
+def subpacket(data, end):
+    crc = crc32(data + bytes([end]))
+    return escape(data) + bytes([ZDLE, end]) + escape(crc.to_bytes(4, "little"))
+
+
+def zfile(name, size, left=1, total=None):
+    info = name.encode() + b"\0" + (
+        "%d %o %o 0 %d %d" % (size, 0o14123456765, 0o100644, left, total or size)
+    ).encode() + b"\0"
+    return bin32_header(ZFILE, b"\0\0\0\0") + subpacket(info, ZCRCW)
+
+
+def data_frames(data, start=0, block=1024, ends=None):
+    """ZDATA at `start` then the subpackets, ZCRCG and a final ZCRCE unless `ends` says"""
+    out = bin32_header(ZDATA, pos(start))
+    offset = start
+    index = 0
+    while offset < len(data):
+        chunk = data[offset : offset + block]
+        last = offset + len(chunk) >= len(data)
+        end = ends(index, last) if ends else (ZCRCE if last else ZCRCG)
+        out += subpacket(chunk, end)
+        offset += len(chunk)
+        index += 1
+        if end == ZCRCW and not last:
+            out += bin32_header(ZDATA, pos(offset))
+    return out
+
+
+def eof(size):
+    return bin32_header(ZEOF, pos(size))
+
+
+def start():
+    return b"rz\r" + hex_header(ZRQINIT)
+
+
+def finish():
+    return hex_header(ZFIN) + b"OO"
+
+
+def pattern(size):
+    """Every byte value, so all the escapes show up"""
+    return bytes((i * 7 + i // 256) & 0xFF for i in range(size))
+
+
+def write(name, stream):
+    with open(name, "wb") as f:
+        f.write(stream)
+
+
+hello = b"Hello, ZMODEM!\n"
+image = pattern(3000)
+small = pattern(700)
+
+write(
+    "batch.bin",
+    start()
+    + zfile("hello.txt", len(hello), 2, len(hello) + len(image))
+    + data_frames(hello)
+    + eof(len(hello))
+    + zfile("image.bin", len(image))
+    + data_frames(image)
+    + eof(len(image))
+    + finish(),
+)
+
+# sz -r, after the receiver asked to resume at 1000
+write(
+    "resume.bin",
+    start() + zfile("image.bin", len(image)) + data_frames(image, 1000) + eof(len(image)) + finish(),
+)
+
+# A receive window of 512 bytes: ZCRCQ asks for a ZACK, ZCRCW waits for it
+write(
+    "window.bin",
+    start()
+    + zfile("image.bin", len(image))
+    + data_frames(image, block=256, ends=lambda i, last: ZCRCE if last else (ZCRCW if i % 2 else ZCRCQ))
+    + eof(len(image))
+    + finish(),
+)
+
+# The second subpacket is hit by noise: the third is already on its way when sz sees the ZRPOS
+good = data_frames(image)
+first = bin32_header(ZDATA, pos(0)) + subpacket(image[:1024], ZCRCG)
+second = bytearray(subpacket(image[1024:2048], ZCRCG))
+second[100] ^= 0x01
+third = subpacket(image[2048:], ZCRCE)
+write(
+    "crc-error.bin",
+    start()
+    + zfile("image.bin", len(image))
+    + first
+    + bytes(second)
+    + third
+    + data_frames(image, 1024)
+    + eof(len(image))
+    + finish(),
+)
+
+# Ctrl-C in sz: canit() sends 10 CAN and 10 backspaces
+write(
+    "cancel.bin",
+    start() + zfile("image.bin", len(image)) + first + b"\x18" * 10 + b"\x08" * 10,
+)
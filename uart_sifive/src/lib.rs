@@ -13,7 +13,20 @@ A simple struct and helper function for Sifive UART (`sifive,uart0`).
 #[macro_use]
 extern crate bitflags;
 
+pub use uart_common::baud;
+#[cfg(feature = "fmt")]
+pub use uart_common::output;
 pub mod registers;
+pub mod timeout;
 pub mod uart;
 
-pub use uart::MmioUartSifive;
+pub use baud::{BaudRate, ClockSource};
+#[cfg(feature = "fmt")]
+pub use output::OutputFlags;
+#[cfg(feature = "embedded")]
+pub use timeout::DelayMonotonic;
+pub use timeout::{Monotonic, ReceiveError, TransmitError};
+pub use uart::{MmioUartSifive, RegisterDump, UartState};
+
+#[cfg(feature = "stats")]
+pub use uart_common::UartStats;
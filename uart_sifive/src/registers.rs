@@ -1,4 +1,4 @@
-use volatile_register::{RO, RW, WO};
+use volatile_register::{RO, RW};
 
 /// # UART Registers
 #[repr(C)]
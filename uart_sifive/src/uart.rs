@@ -1,19 +1,30 @@
 use super::registers::Registers;
+use crate::timeout::{Deadline, Monotonic, ReceiveError, TransmitError};
+use core::cell::Cell;
+use core::fmt;
+use uart_common::{BaudRate, ClockSource};
+#[cfg(feature = "fmt")]
+use uart_common::{Output, OutputFlags};
+#[cfg(feature = "stats")]
+use uart_common::{StatsCounter, UartStats};
 
 bitflags! {
     /// TxData Register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TxData: u32 {
         const FULL = 1 << 31;
         // const DATA = 0b1111_1111;
     }
 
     /// RxData Register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct RxData: u32 {
         const EMPTY = 1 << 31;
         // const DATA = 0b1111_1111;
     }
 
     /// TxControl Register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TxControl: u32 {
         const ENABLE = 0b01;
         const NSTOP  = 0b10;
@@ -21,6 +32,7 @@ bitflags! {
     }
 
     /// RxControl Register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct RxControl: u32 {
         const ENABLE = 0b01;
         const NSTOP  = 0b10;
@@ -28,6 +40,7 @@ bitflags! {
     }
 
     /// This sturct use for `ie` and `ip` register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct InterruptRegister: u32 {
         const RXWM = 0b10;
         const TXWM = 0b01;
@@ -38,11 +51,54 @@ bitflags! {
     // }
 }
 
+/// A snapshot of every programmable register of the UART.
+///
+/// Produced by [`MmioUartSifive::save`] and written back by [`MmioUartSifive::restore`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UartState {
+    pub txctrl: u32,
+    pub rxctrl: u32,
+    pub ie: u32,
+    pub div: u32,
+}
+
+/// Every register of the UART decoded into named fields, as returned by
+/// [`MmioUartSifive::dump`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RegisterDump {
+    /// Whether the tx FIFO is full (txdata\[31\])
+    pub tx_fifo_full: bool,
+    /// Whether the transmitter is enabled (txctrl\[0\])
+    pub tx_enabled: bool,
+    /// Number of stop bits (txctrl\[1\])
+    pub stop_bits: u8,
+    /// Tx watermark, `ip.txwm` is raised below this many entries (txctrl\[18:16\])
+    pub tx_watermark: u8,
+    /// Whether the receiver is enabled (rxctrl\[0\])
+    pub rx_enabled: bool,
+    /// Rx watermark, `ip.rxwm` is raised above this many entries (rxctrl\[18:16\])
+    pub rx_watermark: u8,
+    /// Enabled interrupts
+    pub ie: InterruptRegister,
+    /// Pending interrupts
+    pub ip: InterruptRegister,
+    /// Baud rate divisor
+    pub div: u32,
+    /// rxdata, only read when asked for. `None` if the rx FIFO was empty
+    pub rx: Option<u8>,
+}
+
 /// # MMIO version of Sifive UART
 ///
 /// **Noticed** This hasn't been tested.
 pub struct MmioUartSifive {
     reg_pointer: *mut Registers,
+    /// Baud rate div was last computed for
+    baud_rate: Cell<Option<BaudRate>>,
+    #[cfg(feature = "fmt")]
+    output: Output,
+    #[cfg(feature = "stats")]
+    stats: StatsCounter,
 }
 
 impl MmioUartSifive {
@@ -50,9 +106,26 @@ impl MmioUartSifive {
     pub const fn new(base_address: usize) -> Self {
         Self {
             reg_pointer: base_address as _,
+            baud_rate: Cell::new(None),
+            #[cfg(feature = "fmt")]
+            output: Output::new(),
+            #[cfg(feature = "stats")]
+            stats: StatsCounter::new(),
         }
     }
 
+    /// Set the translation the `fmt::Write` impl applies to its output, none by default
+    #[cfg(feature = "fmt")]
+    pub fn set_output_flags(&self, flags: OutputFlags) {
+        self.output.set_flags(flags)
+    }
+
+    /// Get the translation the `fmt::Write` impl applies to its output
+    #[cfg(feature = "fmt")]
+    pub fn output_flags(&self) -> OutputFlags {
+        self.output.flags()
+    }
+
     #[allow(clippy::mut_from_ref)]
     fn reg(&self) -> &mut Registers {
         unsafe { &mut *self.reg_pointer }
@@ -65,10 +138,13 @@ impl MmioUartSifive {
 
     /// Read a byte
     pub fn read_byte(&self) -> Option<u8> {
+        // Reading rxdata pops the FIFO, so the data must come from the same read as the flag
         let rx = self.read_rx();
         let rx_empty = RxData::from_bits_truncate(rx).contains(RxData::EMPTY);
         if !rx_empty {
-            Some(self.read_rx() as u8)
+            #[cfg(feature = "stats")]
+            self.stats.count_rx(1);
+            Some(rx as u8)
         } else {
             None
         }
@@ -76,9 +152,70 @@ impl MmioUartSifive {
 
     /// Write a byte
     pub fn write_byte(&self, value: u8) {
+        #[cfg(feature = "stats")]
+        self.stats.count_tx(1);
         self.write_tx(value as u32)
     }
 
+    /// Get a snapshot of the traffic counters
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> UartStats {
+        self.stats.get()
+    }
+
+    /// Reset all counters to zero
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
+
+    /// Save the state of the uart so that it can be reprogrammed later by `restore`
+    pub fn save(&self) -> UartState {
+        UartState {
+            txctrl: self.read_txctrl(),
+            rxctrl: self.read_rxctrl(),
+            ie: self.read_ie().bits(),
+            div: self.read_div(),
+        }
+    }
+
+    /// Reprogram the uart with a state returned by `save`
+    ///
+    /// Interrupts are masked until every other register has been written.
+    pub fn restore(&self, state: &UartState) {
+        self.disable_interrupt();
+        // The saved div may be for another rate than the one remembered
+        self.baud_rate.set(None);
+        self.write_div(state.div);
+        self.write_txctrl(state.txctrl);
+        self.write_rxctrl(state.rxctrl);
+        self.write_ie(state.ie);
+    }
+
+    /// Read every register and decode it into a `RegisterDump`
+    ///
+    /// rxdata is only read when `read_rx` is true, since that pops the rx FIFO.
+    pub fn dump(&self, read_rx: bool) -> RegisterDump {
+        let txctrl = self.read_txctrl();
+        let rxctrl = self.read_rxctrl();
+        RegisterDump {
+            tx_fifo_full: self.is_tx_fifo_full(),
+            tx_enabled: txctrl & TxControl::ENABLE.bits() != 0,
+            stop_bits: if txctrl & TxControl::NSTOP.bits() != 0 {
+                2
+            } else {
+                1
+            },
+            tx_watermark: ((txctrl >> 16) & 0b111) as u8,
+            rx_enabled: rxctrl & RxControl::ENABLE.bits() != 0,
+            rx_watermark: ((rxctrl >> 16) & 0b111) as u8,
+            ie: self.read_ie(),
+            ip: self.read_ip(),
+            div: self.read_div(),
+            rx: if read_rx { self.read_byte() } else { None },
+        }
+    }
+
     /// Read Rx FIFO
     #[inline]
     pub fn read_rx(&self) -> u32 {
@@ -151,6 +288,32 @@ impl MmioUartSifive {
         unsafe { self.reg().div.write(value) }
     }
 
+    /// Set div for `baud_rate` at the current frequency of `clock`
+    pub fn set_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C, baud_rate: BaudRate) {
+        // The baud rate is the input clock divided by div + 1, as close as div allows when the
+        // clock is slower than the baud rate
+        let div = (clock.frequency_hz() / baud_rate.bps()).saturating_sub(1);
+        self.write_div(div as u32);
+        self.baud_rate.set(Some(baud_rate));
+    }
+
+    /// Get the baud rate div was last set for, by `set_baud_rate`
+    ///
+    /// This is the rate asked for, not the one div actually gives
+    pub fn baud_rate(&self) -> Option<BaudRate> {
+        self.baud_rate.get()
+    }
+
+    /// Recompute and write div after the frequency of `clock` changed, e.g. on a DVFS
+    /// transition, so the uart keeps its baud rate
+    ///
+    /// Return the baud rate, or `None` without touching the uart if none was set
+    pub fn reapply_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C) -> Option<BaudRate> {
+        let baud_rate = self.baud_rate.get()?;
+        self.set_baud_rate(clock, baud_rate);
+        Some(baud_rate)
+    }
+
     /// Check if tx FIFO is full
     pub fn is_tx_fifo_full(&self) -> bool {
         TxData::from_bits_truncate(self.read_tx()).contains(TxData::FULL)
@@ -186,6 +349,24 @@ impl MmioUartSifive {
         self.write_rxctrl(self.read_rxctrl() & !RxControl::ENABLE.bits())
     }
 
+    /// Set the rx watermark (0..=7)
+    ///
+    /// The read interrupt is pending while the rx FIFO holds more than `count` entries.
+    pub fn set_rx_watermark(&self, count: u8) {
+        let rxctrl = self.read_rxctrl() & !(0b111 << 16);
+        self.write_rxctrl(rxctrl | ((count as u32 & 0b111) << 16))
+    }
+
+    /// Get the rx watermark
+    pub fn rx_watermark(&self) -> u8 {
+        ((self.read_rxctrl() >> 16) & 0b111) as u8
+    }
+
+    /// Check if the rx FIFO is above the rx watermark
+    pub fn is_rx_watermark_pending(&self) -> bool {
+        self.read_ip().contains(InterruptRegister::RXWM)
+    }
+
     /// Disable all interrupt
     pub fn disable_interrupt(&self) {
         self.write_ie(0)
@@ -193,12 +374,12 @@ impl MmioUartSifive {
 
     /// Enable read interrupt (and keep other bit in ie register)
     pub fn enable_read_interrupt(&self) {
-        self.write_ie((self.read_ie() | InterruptRegister::RXWM).bits() as u32)
+        self.write_ie((self.read_ie() | InterruptRegister::RXWM).bits())
     }
 
     /// Enable write interrupt (and keep other bit in ie register)
     pub fn enable_write_interrupt(&self) {
-        self.write_ie((self.read_ie() | InterruptRegister::TXWM).bits() as u32)
+        self.write_ie((self.read_ie() | InterruptRegister::TXWM).bits())
     }
 
     /// Read a slice
@@ -227,4 +408,259 @@ impl MmioUartSifive {
         }
         count
     }
+
+    /// Read bytes until `buf` is full or `timeout_us` microseconds have passed on `clock`
+    ///
+    /// Return how many bytes were read, or `ReceiveError::Timeout` if none arrived in time
+    pub fn read_timeout<M: Monotonic>(
+        &self,
+        buf: &mut [u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<usize, ReceiveError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.read(&mut buf[filled..]);
+            if filled < buf.len() && deadline.is_expired(clock) {
+                break;
+            }
+        }
+        if filled == 0 && !buf.is_empty() {
+            Err(ReceiveError::Timeout)
+        } else {
+            Ok(filled)
+        }
+    }
+
+    /// Write all of `buf`, giving up with `TransmitError::Timeout` if `timeout_us` microseconds
+    /// pass on `clock` before everything has been handed to the uart
+    pub fn write_timeout<M: Monotonic>(
+        &self,
+        buf: &[u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        let mut written = 0;
+        while written < buf.len() {
+            written += self.write(&buf[written..]);
+            if written < buf.len() && deadline.is_expired(clock) {
+                return Err(TransmitError::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until the tx FIFO is empty, giving up with `TransmitError::Timeout` after
+    /// `timeout_us` microseconds on `clock`
+    ///
+    /// The uart has no status bit for an empty FIFO, so the tx watermark is temporarily set to 1
+    /// and `ip.txwm` is polled. The last character may still be on the wire when this returns.
+    pub fn flush_timeout<M: Monotonic>(
+        &self,
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        if self.wait_tx_empty(|| deadline.is_expired(clock)) {
+            Ok(())
+        } else {
+            Err(TransmitError::Timeout)
+        }
+    }
+
+    /// Wait until the tx FIFO is empty
+    ///
+    /// Polls `ip.txwm` like `flush_timeout`, without a timeout. The last character may still be
+    /// on the wire when this returns.
+    pub fn flush(&self) {
+        self.wait_tx_empty(|| false);
+    }
+
+    /// Poll `ip.txwm` with the tx watermark set to 1 until it is pending or `give_up` returns
+    /// true, then put the watermark back. Return whether the tx FIFO emptied
+    fn wait_tx_empty(&self, mut give_up: impl FnMut() -> bool) -> bool {
+        let txctrl = self.read_txctrl();
+        self.write_txctrl((txctrl & !(0b111 << 16)) | (1 << 16));
+
+        let empty = loop {
+            if self.read_ip().contains(InterruptRegister::TXWM) {
+                break true;
+            }
+            if give_up() {
+                break false;
+            }
+        };
+
+        self.write_txctrl(txctrl);
+        empty
+    }
+}
+
+/// Dump the registers without reading rxdata, see `MmioUartSifive::dump`
+impl fmt::Debug for MmioUartSifive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MmioUartSifive")
+            .field("registers", &self.dump(false))
+            .finish()
+    }
+}
+
+/// ## fmt::Write
+///
+/// Waits for room in the tx FIFO before each byte. Output is translated as set by
+/// [`MmioUartSifive::set_output_flags`]
+#[cfg(feature = "fmt")]
+impl fmt::Write for MmioUartSifive {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.output.write(s.as_bytes(), |bytes| {
+            for c in bytes {
+                while self.is_tx_fifo_full() {}
+                self.write_byte(*c);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    // These tests treat normal memory as device memory, which is not necessarily guaranteed to
+    // work, but it seems to for now.
+
+    struct FakeClock(u64);
+
+    impl Monotonic for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.0 += 1;
+            self.0
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        // txdata full, rxdata empty, no interrupt pending
+        let mut fake_registers: [u32; 7] = [1 << 31, 1 << 31, 0, 0, 0, 0, 0];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartSifive::new(base as usize);
+        let mut clock = FakeClock(0);
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            uart.read_timeout(&mut buf, &mut clock, 100),
+            Err(ReceiveError::Timeout)
+        );
+        assert_eq!(
+            uart.write_timeout(b"hello", &mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        assert_eq!(
+            uart.flush_timeout(&mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        // The tx watermark is put back
+        assert_eq!(fake_registers[2], 0);
+
+        // Data available, room in the tx FIFO, tx watermark pending
+        unsafe {
+            base.write_volatile(0);
+            base.add(1).write_volatile(0xab);
+            base.add(5).write_volatile(InterruptRegister::TXWM.bits());
+        }
+        assert_eq!(uart.read_timeout(&mut buf, &mut clock, 100), Ok(4));
+        assert_eq!(buf, [0xab; 4]);
+        assert_eq!(uart.write_timeout(b"hello", &mut clock, 100), Ok(()));
+        assert_eq!(uart.flush_timeout(&mut clock, 100), Ok(()));
+    }
+
+    #[test]
+    fn flush() {
+        // txctrl: watermark 4, enabled. ip: tx watermark pending
+        let mut fake_registers: [u32; 7] = [0, 0, 0x0004_0001, 0, 0, 0b01, 0];
+        let uart = MmioUartSifive::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.flush();
+        // The tx watermark is put back
+        assert_eq!(fake_registers[2], 0x0004_0001);
+    }
+
+    #[test]
+    fn save_restore() {
+        let mut fake_registers: [u32; 7] = [0; 7];
+        let uart = MmioUartSifive::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.write_div(138);
+        uart.write_txctrl((1 << 16) | TxControl::ENABLE.bits());
+        uart.write_rxctrl((2 << 16) | RxControl::ENABLE.bits());
+        uart.write_ie(InterruptRegister::RXWM.bits());
+        let state = uart.save();
+        assert_eq!(
+            state,
+            UartState {
+                txctrl: 0x0001_0001,
+                rxctrl: 0x0002_0001,
+                ie: 0b10,
+                div: 138,
+            }
+        );
+
+        // ip is read-only and not part of the state
+        let mut other_registers: [u32; 7] = [0xffff_ffff; 7];
+        let other = MmioUartSifive::new(&mut other_registers as *mut u32 as usize);
+        other.restore(&state);
+        assert_eq!(other.save(), state);
+        assert_eq!(
+            other_registers[2..],
+            [0x0001_0001, 0x0002_0001, 0b10, 0xffff_ffff, 138]
+        );
+    }
+
+    #[test]
+    fn baud_rate() {
+        let mut fake_registers: [u32; 7] = [0; 7];
+        let uart = MmioUartSifive::new(&mut fake_registers as *mut u32 as usize);
+
+        assert_eq!(uart.reapply_baud_rate(&16_000_000), None);
+        uart.set_baud_rate(&16_000_000, BaudRate::B115200);
+        assert_eq!(uart.read_div(), 137);
+        assert_eq!(uart.reapply_baud_rate(&32_000_000), Some(BaudRate::B115200));
+        assert_eq!(uart.read_div(), 276);
+
+        // A clock slower than the baud rate gives the smallest div
+        uart.set_baud_rate(&1_000, BaudRate::B9600);
+        assert_eq!(uart.read_div(), 0);
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B9600));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        // rxdata holds 0x41
+        let mut fake_registers: [u32; 7] = [0, 0x41, 0, 0, 0, 0, 0];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartSifive::new(base as usize);
+
+        uart.write_byte(0x42);
+        assert_eq!(uart.read_byte(), Some(0x41));
+        // rxdata empty
+        unsafe { base.add(1).write_volatile(1 << 31) };
+        assert_eq!(uart.read_byte(), None);
+        assert_eq!(
+            uart.stats(),
+            UartStats {
+                tx: 1,
+                rx: 1,
+                ..UartStats::new()
+            }
+        );
+        assert_eq!(std::format!("{}", uart.stats()), "tx:1 rx:1");
+
+        uart.reset_stats();
+        assert_eq!(uart.stats(), UartStats::new());
+    }
 }
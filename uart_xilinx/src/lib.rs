@@ -14,8 +14,22 @@ This crate provide a struct with many methods to operate uarts in Xilinx's FPGA:
 #[macro_use]
 extern crate bitflags;
 
+pub use uart_common::baud;
+#[cfg(feature = "fmt")]
+pub use uart_common::output;
+#[cfg(feature = "stats")]
+pub use uart_common::stats;
+pub mod timeout;
 pub mod uart_16550;
 pub mod uart_lite;
 
+pub use baud::{BaudRate, ClockSource};
+#[cfg(feature = "fmt")]
+pub use output::OutputFlags;
+#[cfg(feature = "stats")]
+pub use stats::UartStats;
+#[cfg(feature = "embedded")]
+pub use timeout::DelayMonotonic;
+pub use timeout::{Monotonic, ReceiveError, TransmitError};
 pub use uart_16550::MmioUartAxi16550;
 pub use uart_lite::{MmioUartAxiLite, MmioUartXpsLite};
@@ -0,0 +1,10 @@
+/*!
+# timeout
+
+Clocks and errors for the `*_timeout` methods of the uarts in this crate
+*/
+
+pub(crate) use uart_common::Deadline;
+#[cfg(feature = "embedded")]
+pub use uart_common::DelayMonotonic;
+pub use uart_common::{Monotonic, ReceiveError, TransmitError};
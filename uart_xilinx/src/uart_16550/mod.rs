@@ -8,4 +8,7 @@ This mod provide structures with many methods to operate AXI Uart 16550
 pub mod registers;
 pub mod uart;
 
-pub use uart::{InterruptType, MmioUartAxi16550, Parity};
+pub use uart::{
+    DataBits, DumpFlags, FrameFormat, Iir, InterruptType, InvalidFrameFormat, MmioUartAxi16550,
+    Parity, RegisterDump, StopBits, UartState, IER, LSR, MCR, MSR,
+};
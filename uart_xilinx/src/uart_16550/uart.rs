@@ -1,10 +1,17 @@
-#[cfg(feature = "fmt")]
+use core::cell::Cell;
 use core::fmt;
 
 use super::registers::Registers;
+use crate::timeout::{Deadline, Monotonic, ReceiveError, TransmitError};
+use uart_common::{BaudRate, ClockSource};
+#[cfg(feature = "fmt")]
+use uart_common::{Output, OutputFlags};
+#[cfg(feature = "stats")]
+use uart_common::{StatsCounter, UartStats};
 
 bitflags! {
     /// Interrupt Enable Register (bitflags)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct IER: u8 {
         /// Enable Received Data Available Interrupt
         const RDAI  = 0b0000_0001;
@@ -19,6 +26,7 @@ bitflags! {
 
 bitflags! {
     /// Line Status Register (bitflags)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct LSR: u8 {
         /// Data Ready
         const DR   = 0b0000_0001;
@@ -41,6 +49,7 @@ bitflags! {
 
 bitflags! {
     /// Modem Status Register (bitflags)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct MSR: u8 {
         /// Delta Clear To Send
         const DCTS = 0b0000_0001;
@@ -61,7 +70,41 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+bitflags! {
+    /// Modem Control Register (bitflags)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MCR: u8 {
+        /// Data Terminal Ready
+        const DTR  = 0b0000_0001;
+        /// Request To Send
+        const RTS  = 0b0000_0010;
+        /// Auxiliary Output 1
+        const OUT1 = 0b0000_0100;
+        /// Auxiliary Output 2
+        const OUT2 = 0b0000_1000;
+        /// Loopback Mode
+        const LOOP = 0b0001_0000;
+    }
+}
+
+bitflags! {
+    /// Registers `dump` reads even though reading them has side effects
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DumpFlags: u8 {
+        /// Set DLAB briefly to read the divisor latch and FCR
+        const DIVISOR = 0b0000_0001;
+        /// Read IIR, which acknowledges a pending THRE interrupt
+        const IIR = 0b0000_0010;
+        /// Read LSR, which clears the error bits
+        const LSR = 0b0000_0100;
+        /// Read MSR, which clears the delta bits
+        const MSR = 0b0000_1000;
+        /// Read RBR, which consumes a received byte
+        const RBR = 0b0001_0000;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InterruptType {
     ModemStatus,
     TransmitterHoldingRegisterEmpty,
@@ -71,7 +114,27 @@ pub enum InterruptType {
     Reserved,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl InterruptType {
+    /// Decode IIR\[3:0\], `None` if no interrupt is pending
+    fn from_iir(iir: u32) -> Option<Self> {
+        let irq = iir & 0b0000_1111;
+        if irq & 1 != 0 {
+            None
+        } else {
+            match irq {
+                0b0000 => Some(InterruptType::ModemStatus),
+                0b0010 => Some(InterruptType::TransmitterHoldingRegisterEmpty),
+                0b0100 => Some(InterruptType::ReceivedDataAvailable),
+                0b0110 => Some(InterruptType::ReceiverLineStatus),
+                0b1100 => Some(InterruptType::Timeout),
+                0b1000 | 0b1010 | 0b1110 => Some(InterruptType::Reserved),
+                _ => panic!("Can't reached"),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Parity {
     No,
     Odd,
@@ -80,11 +143,216 @@ pub enum Parity {
     Space,
 }
 
+impl Parity {
+    /// Decode LCR\[5:3\], the other bits are ignored when parity is disabled
+    fn from_lcr(lcr: u32) -> Self {
+        if lcr & 0b0000_1000 == 0 {
+            return Parity::No;
+        }
+        match lcr & 0b0011_0000 {
+            0b0000_0000 => Parity::Odd,
+            0b0001_0000 => Parity::Even,
+            0b0010_0000 => Parity::Mark,
+            _ => Parity::Space,
+        }
+    }
+
+    /// Encode into LCR\[5:3\]
+    fn to_lcr(self) -> u32 {
+        match self {
+            Parity::No => 0b0000_0000,
+            Parity::Odd => 0b0000_1000,
+            Parity::Even => 0b0001_1000,
+            Parity::Mark => 0b0010_1000,
+            Parity::Space => 0b0011_1000,
+        }
+    }
+}
+
+/// Number of data bits per character (LCR\[1:0\])
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+impl DataBits {
+    /// Get the number of bits
+    pub fn bits(self) -> u32 {
+        self.to_lcr() + 5
+    }
+
+    /// Get the variant for `bits` data bits, or `None` if it isn't 5 to 8
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            5 => Some(DataBits::Five),
+            6 => Some(DataBits::Six),
+            7 => Some(DataBits::Seven),
+            8 => Some(DataBits::Eight),
+            _ => None,
+        }
+    }
+
+    fn to_lcr(self) -> u32 {
+        match self {
+            DataBits::Five => 0b00,
+            DataBits::Six => 0b01,
+            DataBits::Seven => 0b10,
+            DataBits::Eight => 0b11,
+        }
+    }
+
+    fn from_lcr(lcr: u32) -> Self {
+        match lcr & 0b11 {
+            0b00 => DataBits::Five,
+            0b01 => DataBits::Six,
+            0b10 => DataBits::Seven,
+            _ => DataBits::Eight,
+        }
+    }
+}
+
+/// Number of stop bits (LCR\[2\])
+///
+/// LCR\[2\] selects 1.5 stop bits with 5 data bits and 2 stop bits otherwise
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopBits {
+    One,
+    OnePointFive,
+    Two,
+}
+
+/// Character format: data bits, parity and stop bits (LCR\[5:0\])
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FrameFormat {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl FrameFormat {
+    /// Create a frame format
+    pub const fn new(data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Self {
+        Self {
+            data_bits,
+            parity,
+            stop_bits,
+        }
+    }
+
+    /// Encode into LCR\[5:0\], `None` if the stop bits can't be used with the data bits
+    fn to_lcr(self) -> Option<u32> {
+        let stop = match (self.stop_bits, self.data_bits) {
+            (StopBits::One, _) => 0,
+            (StopBits::OnePointFive, DataBits::Five) => 0b100,
+            (StopBits::Two, DataBits::Five) | (StopBits::OnePointFive, _) => return None,
+            (StopBits::Two, _) => 0b100,
+        };
+        Some(self.data_bits.to_lcr() | stop | self.parity.to_lcr())
+    }
+
+    /// Decode LCR\[5:0\]
+    fn from_lcr(lcr: u32) -> Self {
+        let data_bits = DataBits::from_lcr(lcr);
+        let stop_bits = match (lcr & 0b100 != 0, data_bits) {
+            (false, _) => StopBits::One,
+            (true, DataBits::Five) => StopBits::OnePointFive,
+            (true, _) => StopBits::Two,
+        };
+        Self::new(data_bits, Parity::from_lcr(lcr), stop_bits)
+    }
+}
+
+impl Default for FrameFormat {
+    /// 8 data bits, no parity, 1 stop bit
+    fn default() -> Self {
+        Self::new(DataBits::Eight, Parity::No, StopBits::One)
+    }
+}
+
+/// The stop bits of a [`FrameFormat`] can't be used with its data bits: 1.5 stop bits need 5
+/// data bits, 2 stop bits need 6 or more
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct InvalidFrameFormat;
+
+impl fmt::Display for InvalidFrameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("stop bits not supported with this number of data bits")
+    }
+}
+
+/// Interrupt Identification Register decoded into named fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Iir {
+    /// Pending interrupt with the highest priority (IIR\[3:0\])
+    pub interrupt: Option<InterruptType>,
+    /// Whether the FIFOs are enabled (IIR\[7:6\])
+    pub fifo_enabled: bool,
+}
+
+/// Every register of the uart decoded into named fields, as returned by `dump`
+///
+/// Registers whose read has side effects are `None` unless asked for with `DumpFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDump {
+    /// Divisor latch (DLH:DLL), read with `DumpFlags::DIVISOR`
+    pub divisor: Option<u16>,
+    /// Data bits, parity and stop bits (LCR\[5:0\])
+    pub frame_format: FrameFormat,
+    /// Break Control (LCR\[6\])
+    pub break_enabled: bool,
+    /// Divisor Latch Access Bit (LCR\[7\])
+    pub divisor_latch_accessible: bool,
+    /// Enabled interrupts, `None` if DLAB is set and `DumpFlags::DIVISOR` not given
+    pub ier: Option<IER>,
+    /// Interrupt identification, read with `DumpFlags::IIR`
+    pub iir: Option<Iir>,
+    /// FIFO Control Register, read with `DumpFlags::DIVISOR`
+    pub fcr: Option<u32>,
+    /// Modem control lines and loopback
+    pub mcr: MCR,
+    /// Line status, read with `DumpFlags::LSR`
+    pub lsr: Option<LSR>,
+    /// Modem status, read with `DumpFlags::MSR`
+    pub msr: Option<MSR>,
+    /// Scratch Register
+    pub scratch: u32,
+    /// Receiver Buffer, read with `DumpFlags::RBR`
+    pub rbr: Option<u8>,
+}
+
+/// A snapshot of every programmable register of the UART.
+///
+/// Produced by [`MmioUartAxi16550::save`] and written back by [`MmioUartAxi16550::restore`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UartState {
+    /// Divisor latch (DLH:DLL)
+    pub divisor: u16,
+    /// Interrupt Enable Register
+    pub ier: u32,
+    /// Line Control Register, DLAB included
+    pub lcr: u32,
+    /// Modem Control Register
+    pub mcr: u32,
+    /// FIFO Control Register
+    pub fcr: u32,
+    /// Scratch Register
+    pub scratch: u32,
+}
+
 /// # MMIO version of AXI UART 16550
 ///
 /// **Noticed** This hasn't been tested.
 pub struct MmioUartAxi16550<'a> {
     reg: &'a mut Registers,
+    /// Baud rate the divisor was last computed for
+    baud_rate: Cell<Option<BaudRate>>,
+    #[cfg(feature = "fmt")]
+    output: Output,
+    #[cfg(feature = "stats")]
+    stats: StatsCounter,
 }
 
 impl<'a> MmioUartAxi16550<'a> {
@@ -92,6 +360,11 @@ impl<'a> MmioUartAxi16550<'a> {
     pub fn new(base_address: usize) -> Self {
         Self {
             reg: cast!(base_address),
+            baud_rate: Cell::new(None),
+            #[cfg(feature = "fmt")]
+            output: Output::new(),
+            #[cfg(feature = "stats")]
+            stats: StatsCounter::new(),
         }
     }
 
@@ -101,7 +374,17 @@ impl<'a> MmioUartAxi16550<'a> {
     pub fn init(&self, clock: usize, baud_rate: usize) {
         // Enable DLAB and Set divisor
         self.set_divisor(clock, baud_rate);
+        self.init_line();
+    }
 
+    /// Init the uart like `init`, for `baud_rate` at the current frequency of `clock`
+    pub fn init_with_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C, baud_rate: BaudRate) {
+        self.set_baud_rate(clock, baud_rate);
+        self.init_line();
+    }
+
+    /// Everything `init` does after setting the divisor
+    fn init_line(&self) {
         // Disable DLAB and set word length 8 bits, no parity, 1 stop bit
         self.write_lcr(3);
         // Enable FIFO
@@ -124,19 +407,234 @@ impl<'a> MmioUartAxi16550<'a> {
     /// Return `None` when data is not ready (RBR\[0\] != 1)
     pub fn read_byte(&self) -> Option<u8> {
         if self.is_data_ready() {
+            #[cfg(feature = "stats")]
+            self.stats.count_rx(1);
             Some(self.read_rbr() as u8)
         } else {
             None
         }
     }
 
+    /// Set the translation the `fmt::Write` impl applies to its output, none by default
+    #[cfg(feature = "fmt")]
+    pub fn set_output_flags(&self, flags: OutputFlags) {
+        self.output.set_flags(flags)
+    }
+
+    /// Get the translation the `fmt::Write` impl applies to its output
+    #[cfg(feature = "fmt")]
+    pub fn output_flags(&self) -> OutputFlags {
+        self.output.flags()
+    }
+
+    /// Read a byte from uart along with the LSR it was received with
+    ///
+    /// Reading LSR clears its error bits, so this is the only way to tell which byte a parity or
+    /// framing error, or a break, belongs to. Return `None` when data is not ready.
+    pub fn read_byte_with_status(&self) -> Option<(u8, LSR)> {
+        let lsr = self.lsr();
+        if lsr.contains(LSR::DR) {
+            #[cfg(feature = "stats")]
+            self.stats.count_rx(1);
+            Some((self.read_rbr() as u8, lsr))
+        } else {
+            None
+        }
+    }
+
     /// Write a byte to uart
     ///
     /// Error are not concerned now **MAYBE TODO**
     pub fn write_byte(&self, byte: u8) {
+        #[cfg(feature = "stats")]
+        self.stats.count_tx(1);
         self.write_thr(byte as u32);
     }
 
+    /// Get a snapshot of the traffic and line error counters
+    ///
+    /// Line errors are counted whenever LSR is read through this driver, so they are kept even
+    /// though the read clears the error bits.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> UartStats {
+        self.stats.get()
+    }
+
+    /// Reset all counters to zero
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
+
+    /// Read bytes until `buf` is full or `timeout_us` microseconds have passed on `clock`
+    ///
+    /// Return how many bytes were read, or `ReceiveError::Timeout` if none arrived in time
+    pub fn read_timeout<M: Monotonic>(
+        &self,
+        buf: &mut [u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<usize, ReceiveError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        let mut filled = 0;
+        while filled < buf.len() {
+            if let Some(ch) = self.read_byte() {
+                buf[filled] = ch;
+                filled += 1;
+            } else if deadline.is_expired(clock) {
+                break;
+            }
+        }
+        if filled == 0 && !buf.is_empty() {
+            Err(ReceiveError::Timeout)
+        } else {
+            Ok(filled)
+        }
+    }
+
+    /// Write all of `buf`, giving up with `TransmitError::Timeout` if `timeout_us` microseconds
+    /// pass on `clock` before everything has been handed to the uart
+    pub fn write_timeout<M: Monotonic>(
+        &self,
+        buf: &[u8],
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        for byte in buf {
+            while !self.is_transmitter_holding_register_empty() {
+                if deadline.is_expired(clock) {
+                    return Err(TransmitError::Timeout);
+                }
+            }
+            self.write_byte(*byte);
+        }
+        Ok(())
+    }
+
+    /// Wait until the uart has finished transmitting, giving up with `TransmitError::Timeout`
+    /// after `timeout_us` microseconds on `clock`
+    pub fn flush_timeout<M: Monotonic>(
+        &self,
+        clock: &mut M,
+        timeout_us: u64,
+    ) -> Result<(), TransmitError> {
+        let deadline = Deadline::new(clock, timeout_us);
+        while !self.is_data_holding_registers_empty() {
+            if deadline.is_expired(clock) {
+                return Err(TransmitError::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the registers and decode them into a `RegisterDump`
+    ///
+    /// Only registers that can be read without side effects are read, plus those selected by
+    /// `flags`. With `DumpFlags::empty()` the uart is left exactly as it was found.
+    pub fn dump(&self, flags: DumpFlags) -> RegisterDump {
+        let lcr = self.read_lcr();
+        let dlab = lcr & 0b1000_0000 != 0;
+
+        let latched = if flags.contains(DumpFlags::DIVISOR) {
+            self.write_lcr(lcr | 0b1000_0000);
+            let divisor = ((self.read_dlh() & 0xff) << 8 | (self.read_dll() & 0xff)) as u16;
+            let fcr = unsafe { self.read_fcr() };
+            self.write_lcr(lcr & !0b1000_0000);
+            Some((divisor, fcr))
+        } else {
+            None
+        };
+        // With DLAB set, IER and RBR read as DLH and DLL
+        let dlab_clear = !dlab || latched.is_some();
+        let ier = if dlab_clear { Some(self.ier()) } else { None };
+        let rbr = if dlab_clear && flags.contains(DumpFlags::RBR) {
+            Some(self.read_rbr() as u8)
+        } else {
+            None
+        };
+        if latched.is_some() {
+            self.write_lcr(lcr);
+        }
+        let iir = if flags.contains(DumpFlags::IIR) {
+            let iir = self.read_iir();
+            Some(Iir {
+                interrupt: InterruptType::from_iir(iir),
+                fifo_enabled: iir & 0b1100_0000 != 0,
+            })
+        } else {
+            None
+        };
+
+        RegisterDump {
+            divisor: latched.map(|(divisor, _)| divisor),
+            frame_format: FrameFormat::from_lcr(lcr),
+            break_enabled: lcr & 0b0100_0000 != 0,
+            divisor_latch_accessible: dlab,
+            ier,
+            iir,
+            fcr: latched.map(|(_, fcr)| fcr),
+            mcr: MCR::from_bits_truncate(self.read_mcr() as u8),
+            lsr: if flags.contains(DumpFlags::LSR) {
+                Some(self.lsr())
+            } else {
+                None
+            },
+            msr: if flags.contains(DumpFlags::MSR) {
+                Some(self.msr())
+            } else {
+                None
+            },
+            scratch: self.read_sr(),
+            rbr,
+        }
+    }
+
+    /// Save the state of the uart so that it can be reprogrammed later by `restore`
+    ///
+    /// The divisor latch and FCR are read with DLAB set, then LCR is put back as it was found.
+    pub fn save(&self) -> UartState {
+        let lcr = self.read_lcr();
+
+        self.write_lcr(lcr | 0b1000_0000);
+        let divisor = ((self.read_dlh() & 0xff) << 8 | (self.read_dll() & 0xff)) as u16;
+        // Clearing the FIFOs (FCR[2:1]) is a one-shot action, don't replay it on restore
+        let fcr = unsafe { self.read_fcr() } & 0b1111_1001;
+        self.write_lcr(lcr & !0b1000_0000);
+        let ier = self.read_ier();
+        self.write_lcr(lcr);
+
+        UartState {
+            divisor,
+            ier,
+            lcr,
+            mcr: self.read_mcr(),
+            fcr,
+            scratch: self.read_sr(),
+        }
+    }
+
+    /// Reprogram the uart with a state returned by `save`
+    ///
+    /// Interrupts are masked until every other register has been written.
+    pub fn restore(&self, state: &UartState) {
+        self.write_lcr(state.lcr & !0b1000_0000);
+        self.write_ier(0);
+
+        self.write_lcr(state.lcr | 0b1000_0000);
+        // The saved divisor may be for another rate than the one remembered
+        self.baud_rate.set(None);
+        self.write_dll((state.divisor & 0xff) as u32);
+        self.write_dlh((state.divisor >> 8) as u32);
+        self.write_lcr(state.lcr & !0b1000_0000);
+
+        self.write_fcr(state.fcr);
+        self.write_mcr(state.mcr);
+        self.write_sr(state.scratch);
+        self.write_ier(state.ier);
+        self.write_lcr(state.lcr);
+    }
+
     /// write THR (offset + 0x0)
     ///
     /// Write Transmitter Holding Buffer to send data
@@ -186,13 +684,42 @@ impl<'a> MmioUartAxi16550<'a> {
     }
 
     /// Set divisor latch according to clock and baud_rate, then set DLAB to false
+    ///
+    /// Do nothing if `baud_rate` is 0. When `clock` is too slow or too fast for `baud_rate`, the
+    /// divisor is clamped to what the latch holds, 1 to 65535
     #[inline]
     pub fn set_divisor(&self, clock: usize, baud_rate: usize) {
+        let divisor = match clock.checked_div(baud_rate.saturating_mul(16)) {
+            Some(divisor) => divisor.clamp(1, u16::MAX as usize),
+            None => return,
+        };
         self.enable_divisor_latch_accessible();
-        let divisor = clock / (16 * baud_rate);
         self.write_dll((divisor & 0b1111_1111) as u32);
         self.write_dlh(((divisor >> 8) & 0b1111_1111) as u32);
         self.disable_divisor_latch_accessible();
+        self.baud_rate.set(BaudRate::from_bps(baud_rate));
+    }
+
+    /// Set the divisor for `baud_rate` at the current frequency of `clock`
+    pub fn set_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C, baud_rate: BaudRate) {
+        self.set_divisor(clock.frequency_hz(), baud_rate.bps())
+    }
+
+    /// Get the baud rate the divisor was last set for, by `init`, `set_divisor` or `set_baud_rate`
+    ///
+    /// This is the rate asked for, not the one the divisor actually gives
+    pub fn baud_rate(&self) -> Option<BaudRate> {
+        self.baud_rate.get()
+    }
+
+    /// Recompute and write the divisor after the frequency of `clock` changed, e.g. on a DVFS
+    /// transition, so the uart keeps its baud rate
+    ///
+    /// Return the baud rate, or `None` without touching the uart if none was set
+    pub fn reapply_baud_rate<C: ClockSource + ?Sized>(&self, clock: &C) -> Option<BaudRate> {
+        let baud_rate = self.baud_rate.get()?;
+        self.set_baud_rate(clock, baud_rate);
+        Some(baud_rate)
     }
 
     /// Read IER (offset + 0x4)
@@ -316,20 +843,7 @@ impl<'a> MmioUartAxi16550<'a> {
 
     /// Read IIR\[3:1\] to get interrupt type
     pub fn read_interrupt_type(&self) -> Option<InterruptType> {
-        let irq = self.reg.rw[2].read() & 0b0000_1111;
-        if irq & 1 != 0 {
-            None
-        } else {
-            match irq {
-                0b0000 => Some(InterruptType::ModemStatus),
-                0b0010 => Some(InterruptType::TransmitterHoldingRegisterEmpty),
-                0b0100 => Some(InterruptType::ReceivedDataAvailable),
-                0b0110 => Some(InterruptType::ReceiverLineStatus),
-                0b1100 => Some(InterruptType::Timeout),
-                0b1000 | 0b1010 | 0b1110 => Some(InterruptType::Reserved),
-                _ => panic!("Can't reached"),
-            }
-        }
+        InterruptType::from_iir(self.reg.rw[2].read())
     }
 
     /// get whether interrupt is pending (IIR\[0\])
@@ -358,6 +872,31 @@ impl<'a> MmioUartAxi16550<'a> {
         unsafe { self.reg.rw[2].write(value) }
     }
 
+    /// Read FCR with DLAB set, then put LCR back as it was found
+    fn fcr(&self) -> u32 {
+        let lcr = self.read_lcr();
+        self.write_lcr(lcr | 0b1000_0000);
+        let fcr = unsafe { self.read_fcr() };
+        self.write_lcr(lcr);
+        fcr
+    }
+
+    /// Select DMA mode 1 (FCR\[3\]) or mode 0 and enable the FIFOs
+    ///
+    /// In mode 1 TXRDY stays active until the tx FIFO is full and RXRDY until the rx FIFO drops
+    /// below the trigger level, so a DMA engine can move several bytes per request. The other FCR
+    /// bits, such as the rx trigger level, are kept
+    pub fn set_dma_mode(&self, mode1: bool) {
+        // Clearing the FIFOs (FCR[2:1]) is a one-shot action, don't repeat it
+        let fcr = self.fcr() & 0b1111_0001;
+        self.write_fcr(fcr | (mode1 as u32) << 3 | 1)
+    }
+
+    /// Get whether DMA mode 1 is selected in FCR\[3\]
+    pub fn is_dma_mode(&self) -> bool {
+        self.fcr() & 0b0000_1000 != 0
+    }
+
     /// Read LCR (offset + 0xc)
     ///
     /// Read Line Control Register to get the data protocol and DLAB
@@ -398,55 +937,68 @@ impl<'a> MmioUartAxi16550<'a> {
 
     /// get parity of used data protocol
     pub fn get_parity(&self) -> Parity {
-        match self.reg.rw[3].read() & 0b0011_1000 {
-            0b0000_0000 => Parity::No,
-            0b0000_1000 => Parity::Odd,
-            0b0001_1000 => Parity::Even,
-            0b0010_1000 => Parity::Mark,
-            0b0011_1000 => Parity::Space,
-            _ => panic!("Invalid Parity! Please check your uart"),
-        }
+        Parity::from_lcr(self.reg.rw[3].read())
     }
 
     /// set parity
     pub fn set_parity(&self, parity: Parity) {
-        match parity {
-            Parity::No => unsafe { self.reg.rw[3].modify(|v| (v & 0b1100_0111)) },
-            Parity::Odd => unsafe { self.reg.rw[3].modify(|v| (v & 0b1100_0111) | 0b0000_1000) },
-            Parity::Even => unsafe { self.reg.rw[3].modify(|v| (v & 0b1100_0111) | 0b0001_1000) },
-            Parity::Mark => unsafe { self.reg.rw[3].modify(|v| (v & 0b1100_0111) | 0b0010_1000) },
-            Parity::Space => unsafe { self.reg.rw[3].modify(|v| v | 0b0011_1000) },
-        }
+        unsafe { self.reg.rw[3].modify(|v| (v & !0b0011_1000) | parity.to_lcr()) }
     }
 
     /// get stop bit of used data protocol
     ///
-    /// Simply return a u8 to indicate 1 or 1.5/2 bits
+    /// Simply return a u32 to indicate 1 or 1.5/2 bits
+    #[deprecated(note = "use `frame_format().stop_bits`, which tells 1.5 from 2 stop bits")]
     pub fn get_stop_bit(&self) -> u32 {
-        ((self.reg.rw[3].read() & 0b100) >> 2) + 1
+        match self.frame_format().stop_bits {
+            StopBits::One => 1,
+            StopBits::OnePointFive | StopBits::Two => 2,
+        }
     }
 
     /// set stop bit, only 1 and 2 can be used as `stop_bit`
+    ///
+    /// 2 gives 1.5 stop bits with 5 data bits
+    #[deprecated(note = "use `set_frame_format`")]
     pub fn set_stop_bit(&self, stop_bit: u32) {
-        match stop_bit {
-            1 => unsafe { self.reg.rw[3].modify(|v| v & 0b1111_1011) },
-            2 => unsafe { self.reg.rw[3].modify(|v| v | 0b0000_0100) },
+        let stop = match stop_bit {
+            1 => 0,
+            2 => 0b100,
             _ => panic!("Invalid stop bit"),
-        }
+        };
+        self.set_frame_format_from_lcr((self.read_lcr() & !0b100) | stop)
     }
 
     /// get word length of used data protocol
+    #[deprecated(note = "use `frame_format().data_bits`")]
     pub fn get_word_length(&self) -> u32 {
-        (self.reg.rw[3].read() & 0b11) + 5
+        self.frame_format().data_bits.bits()
     }
 
     /// set word length, only 5..=8 can be used as `length`
+    #[deprecated(note = "use `set_frame_format`")]
     pub fn set_word_length(&self, length: u32) {
-        if (5..=8).contains(&length) {
-            unsafe { self.reg.rw[3].modify(|v| v | (length - 5)) }
-        } else {
-            panic!("Invalid word length")
-        }
+        let data_bits = DataBits::from_bits(length).expect("Invalid word length");
+        self.set_frame_format_from_lcr((self.read_lcr() & !0b11) | data_bits.to_lcr())
+    }
+
+    /// Set the character format decoded from `lcr`, so LCR\[2\] keeps meaning 1.5 or 2 stop bits
+    /// whatever the number of data bits
+    fn set_frame_format_from_lcr(&self, lcr: u32) {
+        // A format decoded from LCR can always be encoded back
+        let _ = self.set_frame_format(FrameFormat::from_lcr(lcr));
+    }
+
+    /// Get the character format (LCR\[5:0\])
+    pub fn frame_format(&self) -> FrameFormat {
+        FrameFormat::from_lcr(self.reg.rw[3].read())
+    }
+
+    /// Set the character format (LCR\[5:0\]), keeping the break and DLAB bits
+    pub fn set_frame_format(&self, format: FrameFormat) -> Result<(), InvalidFrameFormat> {
+        let lcr = format.to_lcr().ok_or(InvalidFrameFormat)?;
+        unsafe { self.reg.rw[3].modify(|v| (v & 0b1100_0000) | lcr) };
+        Ok(())
     }
 
     /// Read MCR (offset + 0x10)
@@ -474,7 +1026,16 @@ impl<'a> MmioUartAxi16550<'a> {
     /// Get LSR bitflags
     #[inline]
     pub fn lsr(&self) -> LSR {
-        LSR::from_bits_truncate(self.read_lsr() as u8)
+        let lsr = LSR::from_bits_truncate(self.read_lsr() as u8);
+        // The error bits are cleared by this read, so count them before they are lost
+        #[cfg(feature = "stats")]
+        self.stats.count_line_errors(
+            lsr.contains(LSR::FE),
+            lsr.contains(LSR::PE),
+            lsr.contains(LSR::BI),
+            lsr.contains(LSR::OE),
+        );
+        lsr
     }
 
     /// get whether there is an error in received FIFO
@@ -567,15 +1128,281 @@ impl<'a> MmioUartAxi16550<'a> {
     }
 }
 
+/// Dump the registers that can be read without side effects, see `MmioUartAxi16550::dump`
+impl fmt::Debug for MmioUartAxi16550<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MmioUartAxi16550")
+            .field("registers", &self.dump(DumpFlags::empty()))
+            .finish()
+    }
+}
+
 /// ## fmt::Write
 ///
-/// A simple implementation, may be changed in the future
+/// Waits for THR to be empty before each byte. Output is translated as set by
+/// [`MmioUartAxi16550::set_output_flags`]
 #[cfg(feature = "fmt")]
 impl<'a> fmt::Write for MmioUartAxi16550<'a> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.as_bytes() {
-            self.write_thr((*c) as u32);
-        }
+        self.output.write(s.as_bytes(), |bytes| {
+            for c in bytes {
+                while !self.is_transmitter_holding_register_empty() {}
+                self.write_byte(*c);
+            }
+        });
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These tests treat normal memory as device memory, which is not necessarily guaranteed to
+    // work, but it seems to for now. DLL and DLH share their address with THR/RBR and IER, as
+    // they do in the hardware, so only the last of them written is kept.
+
+    struct FakeClock(u64);
+
+    impl Monotonic for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.0 += 1;
+            self.0
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartAxi16550::new(base as usize);
+        let mut clock = FakeClock(0);
+
+        // Nothing to read, transmitter stuck
+        let mut buf = [0; 4];
+        assert_eq!(
+            uart.read_timeout(&mut buf, &mut clock, 100),
+            Err(ReceiveError::Timeout)
+        );
+        assert_eq!(
+            uart.write_timeout(b"hello", &mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        assert_eq!(
+            uart.flush_timeout(&mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+
+        // Data available and transmitter idle
+        unsafe {
+            base.write_volatile(0xab);
+            base.add(5).write_volatile(0b0110_0001);
+        }
+        assert_eq!(uart.read_timeout(&mut buf, &mut clock, 100), Ok(4));
+        assert_eq!(buf, [0xab; 4]);
+        assert_eq!(uart.write_timeout(b"hello", &mut clock, 100), Ok(()));
+        assert_eq!(uart.flush_timeout(&mut clock, 100), Ok(()));
+    }
+
+    #[test]
+    fn dump() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.init(11_059_200, 115200);
+        let lcr = fake_registers[3];
+        let dump = uart.dump(DumpFlags::empty());
+        assert_eq!(dump.ier, Some(IER::RDAI));
+        assert_eq!((dump.divisor, dump.fcr), (None, None));
+        assert_eq!(
+            (dump.iir, dump.lsr, dump.msr, dump.rbr),
+            (None, None, None, None)
+        );
+        // LCR hasn't been written to set DLAB
+        assert_eq!(fake_registers[3], lcr);
+
+        let dump = uart.dump(DumpFlags::DIVISOR | DumpFlags::LSR);
+        assert_eq!(dump.frame_format, FrameFormat::default());
+        assert_eq!(dump.fcr, Some(1));
+        assert!(dump.lsr.is_some());
+        assert!(!dump.divisor_latch_accessible);
+    }
+
+    #[test]
+    fn save_restore() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.init(11_059_200, 115200);
+        uart.write_sr(0x5a);
+        let state = uart.save();
+        assert_eq!(state.divisor & 0xff, 6);
+        assert_eq!(state.ier, 1);
+        assert_eq!(state.lcr, 0b0000_0011);
+        assert_eq!(state.fcr, 1);
+        assert_eq!(state.mcr, 0);
+        assert_eq!(state.scratch, 0x5a);
+
+        let mut other_registers: [u32; 8] = [0xff; 8];
+        let other = MmioUartAxi16550::new(&mut other_registers as *mut u32 as usize);
+        other.restore(&state);
+        assert_eq!(other.save(), state);
+        // DLAB is cleared last, after IER is written back over DLH
+        assert_eq!(other_registers[1], 1);
+        assert_eq!(other_registers[3], 0b0000_0011);
+        assert_eq!(other_registers[4], 0);
+        assert_eq!(other_registers[7], 0x5a);
+    }
+
+    #[cfg(feature = "fmt")]
+    #[test]
+    fn fmt_write() {
+        use core::fmt::Write;
+
+        // LSR always reports THR empty, so every byte goes through
+        let mut fake_registers: [u32; 8] = [0, 0, 0, 0, 0, LSR::THRE.bits() as u32, 0, 0];
+        let mut uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.set_output_flags(OutputFlags::ONLCR);
+        writeln!(uart, "a").unwrap();
+        assert_eq!(fake_registers[0], b'\n' as u32);
+    }
+
+    #[test]
+    fn dma_mode() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.write_lcr(0b0000_0011);
+        uart.write_fcr(0b1100_0111);
+        uart.set_dma_mode(true);
+        assert!(uart.is_dma_mode());
+        // The rx trigger level is kept, the FIFOs aren't cleared again
+        assert_eq!(fake_registers[2], 0b1100_1001);
+        assert_eq!(fake_registers[3], 0b0000_0011);
+
+        uart.set_dma_mode(false);
+        assert!(!uart.is_dma_mode());
+        assert_eq!(fake_registers[2], 0b1100_0001);
+    }
+
+    #[test]
+    fn set_divisor() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        uart.set_baud_rate(&11_059_200, BaudRate::B115200);
+        assert_eq!(fake_registers[..2], [6, 0]);
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B115200));
+
+        // A zero baud rate is ignored, and the divisor clamped to the latch
+        uart.set_divisor(11_059_200, 0);
+        assert_eq!(fake_registers[..2], [6, 0]);
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B115200));
+        uart.set_divisor(11_059_200, 921_600);
+        assert_eq!(fake_registers[..2], [1, 0]);
+        uart.set_divisor(usize::MAX, 300);
+        assert_eq!(fake_registers[..2], [0xff, 0xff]);
+
+        uart.init_with_baud_rate(&11_059_200, BaudRate::B9600);
+        assert_eq!(uart.baud_rate(), Some(BaudRate::B9600));
+        // 8N1, DLAB cleared, and DLH overwritten by IER
+        assert_eq!(fake_registers[..4], [72, 1, 1, 3]);
+    }
+
+    const DATA_BITS: [DataBits; 4] = [
+        DataBits::Five,
+        DataBits::Six,
+        DataBits::Seven,
+        DataBits::Eight,
+    ];
+    const PARITIES: [Parity; 5] = [
+        Parity::No,
+        Parity::Odd,
+        Parity::Even,
+        Parity::Mark,
+        Parity::Space,
+    ];
+    const STOP_BITS: [StopBits; 3] = [StopBits::One, StopBits::OnePointFive, StopBits::Two];
+
+    #[test]
+    fn frame_format() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        for data_bits in DATA_BITS {
+            for parity in PARITIES {
+                for stop_bits in STOP_BITS {
+                    let format = FrameFormat::new(data_bits, parity, stop_bits);
+                    let valid = match stop_bits {
+                        StopBits::One => true,
+                        StopBits::OnePointFive => data_bits == DataBits::Five,
+                        StopBits::Two => data_bits != DataBits::Five,
+                    };
+                    // Start from every other format, with break and DLAB set or not
+                    for previous in 0..=0b1111_1111 {
+                        uart.write_lcr(previous);
+                        if valid {
+                            assert_eq!(uart.set_frame_format(format), Ok(()));
+                            assert_eq!(uart.frame_format(), format);
+                            assert_eq!(uart.read_lcr() & 0b1100_0000, previous & 0b1100_0000);
+                            assert_eq!(uart.get_parity(), parity);
+                        } else {
+                            assert_eq!(uart.set_frame_format(format), Err(InvalidFrameFormat));
+                            assert_eq!(uart.read_lcr(), previous);
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            FrameFormat::default(),
+            FrameFormat::new(DataBits::Eight, Parity::No, StopBits::One)
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn frame_format_setters() {
+        let mut fake_registers: [u32; 8] = [0; 8];
+        let uart = MmioUartAxi16550::new(&mut fake_registers as *mut u32 as usize);
+
+        for lcr in 0..=0b1111_1111 {
+            uart.write_lcr(lcr);
+            // Every encoding decodes, including parity bits set with parity disabled
+            let format = uart.frame_format();
+            assert_eq!(uart.get_parity(), format.parity);
+            assert_eq!(uart.get_word_length(), format.data_bits.bits());
+            if lcr & 0b1000 == 0 {
+                assert_eq!(format.parity, Parity::No);
+            }
+
+            for data_bits in DATA_BITS {
+                uart.write_lcr(lcr);
+                uart.set_word_length(data_bits.bits());
+                assert_eq!(uart.get_word_length(), data_bits.bits());
+                assert_eq!(
+                    uart.frame_format(),
+                    FrameFormat::from_lcr((lcr & !0b11) | data_bits.to_lcr())
+                );
+                assert_eq!(uart.read_lcr() & 0b1100_0000, lcr & 0b1100_0000);
+            }
+            for parity in PARITIES {
+                uart.write_lcr(lcr);
+                uart.set_parity(parity);
+                assert_eq!(uart.get_parity(), parity);
+                assert_eq!(uart.read_lcr() & !0b0011_1000, lcr & !0b0011_1000);
+            }
+            for stop_bit in 1..=2 {
+                uart.write_lcr(lcr);
+                uart.set_stop_bit(stop_bit);
+                assert_eq!(uart.get_stop_bit(), stop_bit);
+                assert_eq!(
+                    uart.frame_format(),
+                    FrameFormat::from_lcr((lcr & !0b100) | (stop_bit - 1) << 2)
+                );
+                assert_eq!(uart.read_lcr() & 0b1100_0000, lcr & 0b1100_0000);
+            }
+        }
+    }
+}
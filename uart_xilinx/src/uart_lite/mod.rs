@@ -8,4 +8,4 @@ This mod provide structures with many methods to operate XPS Uart Lite and AXI U
 pub mod registers;
 pub mod uart;
 
-pub use uart::{MmioUartAxiLite, MmioUartXpsLite, Status};
+pub use uart::{DumpFlags, MmioUartAxiLite, MmioUartXpsLite, RegisterDump, Status};
@@ -1,9 +1,14 @@
 use super::registers::Registers;
-#[cfg(feature = "fmt")]
+use crate::timeout::{Deadline, Monotonic, ReceiveError, TransmitError};
 use core::fmt;
+#[cfg(feature = "fmt")]
+use uart_common::{Output, OutputFlags};
+#[cfg(feature = "stats")]
+use uart_common::{StatsCounter, UartStats};
 
 bitflags! {
     /// Status Register Bit Definitions
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Status: u8 {
         const RX_FIFO_VALID     = 0b0000_0001;
         const RX_FIFO_FULL      = 0b0000_0010;
@@ -18,6 +23,7 @@ bitflags! {
 
 bitflags! {
     /// Control Register Bit Definitions
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     struct Control: u8 {
         const ENABLE_INTERRUPT  = 0b0001_0000;
         const REST_RX_FIFO      = 0b0000_0010;
@@ -25,194 +31,361 @@ bitflags! {
     }
 }
 
-/// # MMIO version of XPS UART Lite
+bitflags! {
+    /// Registers `dump` reads, none by default since reading either of them has side effects
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DumpFlags: u8 {
+        /// Read the Status Register, which clears the error bits
+        const STATUS = 0b0000_0001;
+        /// Read the Rx FIFO if it holds data, which consumes a received byte. The Status Register
+        /// is read too, to know whether there is data
+        const RX = 0b0000_0010;
+    }
+}
+
+/// The readable registers of the uart decoded into named fields, as returned by `dump`
 ///
-/// **Noticed** This hasn't been tested.
-pub struct MmioUartXpsLite {
-    reg_pointer: *mut Registers,
+/// The Tx FIFO and Control registers are write-only and can't be dumped. The others are `None`
+/// unless asked for with `DumpFlags`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDump {
+    /// Status Register, read with `DumpFlags::STATUS` or `DumpFlags::RX`
+    pub status: Option<Status>,
+    /// Rx FIFO, read with `DumpFlags::RX`
+    pub rx: Option<u8>,
+}
+
+/// XPS UART Lite registers are accessed with their bits reversed
+#[inline]
+fn xps_order(value: u32) -> u32 {
+    value.reverse_bits()
 }
 
-impl MmioUartXpsLite {
-    /// New a uart
-    pub const fn new(base_address: usize) -> Self {
-        Self {
-            reg_pointer: base_address as _,
+/// AXI UART Lite registers are accessed as they are
+#[inline]
+fn axi_order(value: u32) -> u32 {
+    value
+}
+
+/// Define a UART Lite driver, `$order` maps a register value to and from the bit order of the core
+macro_rules! uart_lite {
+    ($(#[$attr:meta])* $name:ident, $order:path) => {
+        $(#[$attr])*
+        pub struct $name {
+            reg_pointer: *mut Registers,
+            #[cfg(feature = "fmt")]
+            output: Output,
+            #[cfg(feature = "stats")]
+            stats: StatsCounter,
         }
-    }
 
-    #[allow(clippy::mut_from_ref)]
-    fn reg(&self) -> &mut Registers {
-        unsafe { &mut *self.reg_pointer }
-    }
+        impl $name {
+            /// New a uart
+            pub const fn new(base_address: usize) -> Self {
+                Self {
+                    reg_pointer: base_address as _,
+                    #[cfg(feature = "fmt")]
+                    output: Output::new(),
+                    #[cfg(feature = "stats")]
+                    stats: StatsCounter::new(),
+                }
+            }
 
-    /// Set a new base_address
-    pub fn set_base_address(&mut self, base_address: usize) {
-        self.reg_pointer = base_address as _;
-    }
+            /// Set the translation the `fmt::Write` impl applies to its output, none by default
+            #[cfg(feature = "fmt")]
+            pub fn set_output_flags(&self, flags: OutputFlags) {
+                self.output.set_flags(flags)
+            }
 
-    /// Read a byte
-    pub fn read_byte(&self) -> Option<u8> {
-        if self.is_rx_fifo_valid() {
-            Some(self.read_rx().reverse_bits() as u8)
-        } else {
-            None
-        }
-    }
+            /// Get the translation the `fmt::Write` impl applies to its output
+            #[cfg(feature = "fmt")]
+            pub fn output_flags(&self) -> OutputFlags {
+                self.output.flags()
+            }
 
-    /// Write a byte
-    pub fn write_byte(&self, value: u8) {
-        self.write_tx((value as u32).reverse_bits())
-    }
+            #[allow(clippy::mut_from_ref)]
+            fn reg(&self) -> &mut Registers {
+                unsafe { &mut *self.reg_pointer }
+            }
 
-    /// Read Rx FIFO
-    #[inline]
-    pub fn read_rx(&self) -> u32 {
-        self.reg().rx.read()
-    }
+            /// Set a new base_address
+            pub fn set_base_address(&mut self, base_address: usize) {
+                self.reg_pointer = base_address as _;
+            }
 
-    /// Write Tx FIFO
-    #[inline]
-    pub fn write_tx(&self, value: u32) {
-        unsafe { self.reg().tx.write(value) }
-    }
+            /// Read a byte
+            pub fn read_byte(&self) -> Option<u8> {
+                if self.is_rx_fifo_valid() {
+                    #[cfg(feature = "stats")]
+                    self.stats.count_rx(1);
+                    Some($order(self.read_rx()) as u8)
+                } else {
+                    None
+                }
+            }
 
-    /// Read Uart Lite Status Register
-    #[inline]
-    pub fn read_stat(&self) -> u32 {
-        self.reg().stat.read()
-    }
+            /// Write a byte
+            pub fn write_byte(&self, value: u8) {
+                #[cfg(feature = "stats")]
+                self.stats.count_tx(1);
+                self.write_tx($order(value as u32))
+            }
 
-    /// Get Uart Lite Status
-    #[inline]
-    pub fn status(&self) -> Status {
-        Status::from_bits_truncate(self.reg().stat.read().reverse_bits() as u8)
-    }
+            /// Get a snapshot of the traffic and line error counters
+            ///
+            /// Line errors are counted whenever the status register is read through this driver,
+            /// so they are kept even though the read clears the error bits.
+            #[cfg(feature = "stats")]
+            pub fn stats(&self) -> UartStats {
+                self.stats.get()
+            }
 
-    pub fn is_rx_fifo_valid(&self) -> bool {
-        self.status().contains(Status::RX_FIFO_VALID)
-    }
+            /// Reset all counters to zero
+            #[cfg(feature = "stats")]
+            pub fn reset_stats(&self) {
+                self.stats.reset()
+            }
 
-    pub fn is_rx_fifo_full(&self) -> bool {
-        self.status().contains(Status::RX_FIFO_FULL)
-    }
+            /// Read bytes until `buf` is full or `timeout_us` microseconds have passed on `clock`
+            ///
+            /// Return how many bytes were read, or `ReceiveError::Timeout` if none arrived in time
+            pub fn read_timeout<M: Monotonic>(
+                &self,
+                buf: &mut [u8],
+                clock: &mut M,
+                timeout_us: u64,
+            ) -> Result<usize, ReceiveError> {
+                let deadline = Deadline::new(clock, timeout_us);
+                let mut filled = 0;
+                while filled < buf.len() {
+                    if let Some(ch) = self.read_byte() {
+                        buf[filled] = ch;
+                        filled += 1;
+                    } else if deadline.is_expired(clock) {
+                        break;
+                    }
+                }
+                if filled == 0 && !buf.is_empty() {
+                    Err(ReceiveError::Timeout)
+                } else {
+                    Ok(filled)
+                }
+            }
 
-    pub fn is_tx_fifo_empty(&self) -> bool {
-        self.status().contains(Status::TX_FIFO_EMPTY)
-    }
+            /// Write all of `buf`, giving up with `TransmitError::Timeout` if `timeout_us`
+            /// microseconds pass on `clock` before everything has been handed to the uart
+            pub fn write_timeout<M: Monotonic>(
+                &self,
+                buf: &[u8],
+                clock: &mut M,
+                timeout_us: u64,
+            ) -> Result<(), TransmitError> {
+                let deadline = Deadline::new(clock, timeout_us);
+                for byte in buf {
+                    while self.is_tx_fifo_full() {
+                        if deadline.is_expired(clock) {
+                            return Err(TransmitError::Timeout);
+                        }
+                    }
+                    self.write_byte(*byte);
+                }
+                Ok(())
+            }
 
-    pub fn is_tx_fifo_full(&self) -> bool {
-        self.status().contains(Status::TX_FIFO_FULL)
-    }
+            /// Wait until the tx FIFO is empty, giving up with `TransmitError::Timeout` after
+            /// `timeout_us` microseconds on `clock`
+            pub fn flush_timeout<M: Monotonic>(
+                &self,
+                clock: &mut M,
+                timeout_us: u64,
+            ) -> Result<(), TransmitError> {
+                let deadline = Deadline::new(clock, timeout_us);
+                while !self.is_tx_fifo_empty() {
+                    if deadline.is_expired(clock) {
+                        return Err(TransmitError::Timeout);
+                    }
+                }
+                Ok(())
+            }
 
-    pub fn is_interrupt_enabled(&self) -> bool {
-        self.status().contains(Status::INTERRUPT_ENABLED)
-    }
+            /// Read the registers selected by `flags` and decode them into a `RegisterDump`
+            ///
+            /// With `DumpFlags::empty()` no register is read and the uart is left exactly as it
+            /// was found.
+            pub fn dump(&self, flags: DumpFlags) -> RegisterDump {
+                let status = if flags.intersects(DumpFlags::STATUS | DumpFlags::RX) {
+                    Some(self.status())
+                } else {
+                    None
+                };
+                let rx = match status {
+                    Some(status)
+                        if flags.contains(DumpFlags::RX)
+                            && status.contains(Status::RX_FIFO_VALID) =>
+                    {
+                        #[cfg(feature = "stats")]
+                        self.stats.count_rx(1);
+                        Some($order(self.read_rx()) as u8)
+                    }
+                    _ => None,
+                };
+                RegisterDump { status, rx }
+            }
 
-    pub fn is_overrun_error(&self) -> bool {
-        self.status().contains(Status::OVERRUN_ERROR)
-    }
+            /// Read Rx FIFO
+            #[inline]
+            pub fn read_rx(&self) -> u32 {
+                self.reg().rx.read()
+            }
 
-    pub fn is_frame_error(&self) -> bool {
-        self.status().contains(Status::FRAME_ERROR)
-    }
+            /// Write Tx FIFO
+            #[inline]
+            pub fn write_tx(&self, value: u32) {
+                unsafe { self.reg().tx.write(value) }
+            }
 
-    pub fn is_parity_error(&self) -> bool {
-        self.status().contains(Status::PARITY_ERROR)
-    }
+            /// Read Uart Lite Status Register
+            #[inline]
+            pub fn read_stat(&self) -> u32 {
+                self.reg().stat.read()
+            }
 
-    /// Write Uart Lite Control Register
-    #[inline]
-    pub fn write_ctrl(&self, value: u32) {
-        unsafe { self.reg().ctrl.write(value) }
-    }
+            /// Get Uart Lite Status
+            #[inline]
+            pub fn status(&self) -> Status {
+                let status = Status::from_bits_truncate($order(self.reg().stat.read()) as u8);
+                // The error bits are cleared by this read, so count them before they are lost
+                #[cfg(feature = "stats")]
+                self.stats.count_line_errors(
+                    status.contains(Status::FRAME_ERROR),
+                    status.contains(Status::PARITY_ERROR),
+                    false,
+                    status.contains(Status::OVERRUN_ERROR),
+                );
+                status
+            }
 
-    pub fn enable_interrupt(&self) {
-        self.write_ctrl((Control::ENABLE_INTERRUPT.bits() as u32).reverse_bits());
-    }
+            pub fn is_rx_fifo_valid(&self) -> bool {
+                self.status().contains(Status::RX_FIFO_VALID)
+            }
 
-    pub fn disable_interrupt(&self) {
-        self.write_ctrl((Control::ENABLE_INTERRUPT.bits() as u32).reverse_bits());
-    }
+            pub fn is_rx_fifo_full(&self) -> bool {
+                self.status().contains(Status::RX_FIFO_FULL)
+            }
 
-    pub fn clear_rx_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            ((Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO).bits() as u32).reverse_bits()
-        } else {
-            (Control::REST_RX_FIFO.bits() as u32).reverse_bits()
-        });
-    }
+            pub fn is_tx_fifo_empty(&self) -> bool {
+                self.status().contains(Status::TX_FIFO_EMPTY)
+            }
 
-    pub fn clear_tx_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            ((Control::ENABLE_INTERRUPT | Control::REST_TX_FIFO).bits() as u32).reverse_bits()
-        } else {
-            (Control::REST_TX_FIFO.bits() as u32).reverse_bits()
-        });
-    }
+            pub fn is_tx_fifo_full(&self) -> bool {
+                self.status().contains(Status::TX_FIFO_FULL)
+            }
 
-    pub fn clear_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            ((Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO | Control::REST_TX_FIFO).bits()
-                as u32)
-                .reverse_bits()
-        } else {
-            ((Control::REST_RX_FIFO | Control::REST_TX_FIFO).bits() as u32).reverse_bits()
-        });
-    }
-}
+            pub fn is_interrupt_enabled(&self) -> bool {
+                self.status().contains(Status::INTERRUPT_ENABLED)
+            }
 
-/// ## fmt::Write
-///
-/// A simple implementation, may be changed in the future
-#[cfg(feature = "fmt")]
-impl fmt::Write for MmioUartXpsLite {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.as_bytes() {
-            self.write_byte(*c);
-        }
-        Ok(())
-    }
-}
+            pub fn is_overrun_error(&self) -> bool {
+                self.status().contains(Status::OVERRUN_ERROR)
+            }
 
-/// # MMIO version of AXI UART Lite
-///
-/// **Noticed** This hasn't been tested.
-pub struct MmioUartAxiLite {
-    reg_pointer: *mut Registers,
-}
+            pub fn is_frame_error(&self) -> bool {
+                self.status().contains(Status::FRAME_ERROR)
+            }
 
-impl MmioUartAxiLite {
-    /// New a uart
-    pub const fn new(base_address: usize) -> Self {
-        Self {
-            reg_pointer: base_address as _,
-        }
-    }
+            pub fn is_parity_error(&self) -> bool {
+                self.status().contains(Status::PARITY_ERROR)
+            }
 
-    #[allow(clippy::mut_from_ref)]
-    fn reg(&self) -> &mut Registers {
-        unsafe { &mut *self.reg_pointer }
-    }
+            /// Write Uart Lite Control Register
+            #[inline]
+            pub fn write_ctrl(&self, value: u32) {
+                unsafe { self.reg().ctrl.write(value) }
+            }
 
-    /// Set a new base_address
-    pub fn set_base_address(&mut self, base_address: usize) {
-        self.reg_pointer = base_address as _;
-    }
+            pub fn enable_interrupt(&self) {
+                self.write_ctrl($order(Control::ENABLE_INTERRUPT.bits() as u32));
+            }
 
-    /// Read a byte
-    pub fn read_byte(&self) -> Option<u8> {
-        if self.is_rx_fifo_valid() {
-            Some(self.read_rx() as u8)
-        } else {
-            None
+            pub fn disable_interrupt(&self) {
+                self.write_ctrl($order(Control::ENABLE_INTERRUPT.bits() as u32));
+            }
+
+            pub fn clear_rx_fifo(&self, enable_interrupt: bool) {
+                self.write_ctrl($order(if enable_interrupt {
+                    (Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO).bits() as u32
+                } else {
+                    Control::REST_RX_FIFO.bits() as u32
+                }));
+            }
+
+            pub fn clear_tx_fifo(&self, enable_interrupt: bool) {
+                self.write_ctrl($order(if enable_interrupt {
+                    (Control::ENABLE_INTERRUPT | Control::REST_TX_FIFO).bits() as u32
+                } else {
+                    Control::REST_TX_FIFO.bits() as u32
+                }));
+            }
+
+            pub fn clear_fifo(&self, enable_interrupt: bool) {
+                self.write_ctrl($order(if enable_interrupt {
+                    (Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO | Control::REST_TX_FIFO)
+                        .bits() as u32
+                } else {
+                    (Control::REST_RX_FIFO | Control::REST_TX_FIFO).bits() as u32
+                }));
+            }
         }
-    }
 
-    /// Write a byte
-    pub fn write_byte(&self, value: u8) {
-        self.write_tx(value as u32)
-    }
+        #[doc = concat!(
+            "Show the base address only: reading the status register clears its error bits, use\n`",
+            stringify!($name),
+            "::dump` to see it"
+        )]
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("base_address", &self.reg_pointer)
+                    .finish_non_exhaustive()
+            }
+        }
+
+        /// ## fmt::Write
+        ///
+        /// Waits for room in the tx FIFO before each byte. Output is translated as set by
+        #[doc = concat!("[`", stringify!($name), "::set_output_flags`]")]
+        #[cfg(feature = "fmt")]
+        impl fmt::Write for $name {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.output.write(s.as_bytes(), |bytes| {
+                    for c in bytes {
+                        while self.is_tx_fifo_full() {}
+                        self.write_byte(*c);
+                    }
+                });
+                Ok(())
+            }
+        }
+    };
+}
+
+uart_lite!(
+    /// # MMIO version of XPS UART Lite
+    ///
+    /// **Noticed** This hasn't been tested.
+    MmioUartXpsLite,
+    xps_order
+);
+
+uart_lite!(
+    /// # MMIO version of AXI UART Lite
+    ///
+    /// **Noticed** This hasn't been tested.
+    MmioUartAxiLite,
+    axi_order
+);
 
+impl MmioUartAxiLite {
     /// Read a slice
     pub fn read(&self, buf: &mut [u8]) -> usize {
         let mut count = 0;
@@ -239,112 +412,214 @@ impl MmioUartAxiLite {
         }
         count
     }
+}
 
-    /// Read Rx FIFO
-    #[inline]
-    pub fn read_rx(&self) -> u32 {
-        self.reg().rx.read()
-    }
-
-    /// Write Tx FIFO
-    #[inline]
-    pub fn write_tx(&self, value: u32) {
-        unsafe { self.reg().tx.write(value) }
-    }
-
-    /// Read Uart Lite Status Register
-    #[inline]
-    pub fn read_stat(&self) -> u32 {
-        self.reg().stat.read()
-    }
-
-    /// Get Uart Lite Status
-    #[inline]
-    pub fn status(&self) -> Status {
-        Status::from_bits_truncate(self.reg().stat.read() as u8)
-    }
-
-    pub fn is_rx_fifo_valid(&self) -> bool {
-        self.status().contains(Status::RX_FIFO_VALID)
-    }
-
-    pub fn is_rx_fifo_full(&self) -> bool {
-        self.status().contains(Status::RX_FIFO_FULL)
-    }
-
-    pub fn is_tx_fifo_empty(&self) -> bool {
-        self.status().contains(Status::TX_FIFO_EMPTY)
-    }
-
-    pub fn is_tx_fifo_full(&self) -> bool {
-        self.status().contains(Status::TX_FIFO_FULL)
-    }
-
-    pub fn is_interrupt_enabled(&self) -> bool {
-        self.status().contains(Status::INTERRUPT_ENABLED)
-    }
-
-    pub fn is_overrun_error(&self) -> bool {
-        self.status().contains(Status::OVERRUN_ERROR)
-    }
-
-    pub fn is_frame_error(&self) -> bool {
-        self.status().contains(Status::FRAME_ERROR)
-    }
-
-    pub fn is_parity_error(&self) -> bool {
-        self.status().contains(Status::PARITY_ERROR)
-    }
-
-    /// Write Uart Lite Control Register
-    #[inline]
-    pub fn write_ctrl(&self, value: u32) {
-        unsafe { self.reg().ctrl.write(value) }
-    }
-
-    pub fn enable_interrupt(&self) {
-        self.write_ctrl(Control::ENABLE_INTERRUPT.bits() as u32);
-    }
-
-    pub fn disable_interrupt(&self) {
-        self.write_ctrl(Control::ENABLE_INTERRUPT.bits() as u32);
-    }
+#[cfg(test)]
+mod tests {
+    extern crate std;
 
-    pub fn clear_rx_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            (Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO).bits() as u32
-        } else {
-            Control::REST_RX_FIFO.bits() as u32
-        });
-    }
+    use super::*;
 
-    pub fn clear_tx_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            (Control::ENABLE_INTERRUPT | Control::REST_TX_FIFO).bits() as u32
-        } else {
-            Control::REST_TX_FIFO.bits() as u32
-        });
-    }
+    struct FakeClock(u64);
 
-    pub fn clear_fifo(&self, enable_interrupt: bool) {
-        self.write_ctrl(if enable_interrupt {
-            (Control::ENABLE_INTERRUPT | Control::REST_RX_FIFO | Control::REST_TX_FIFO).bits()
-                as u32
-        } else {
-            (Control::REST_RX_FIFO | Control::REST_TX_FIFO).bits() as u32
-        });
+    impl Monotonic for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.0 += 1;
+            self.0
+        }
     }
-}
 
-/// ## fmt::Write
-///
-/// A simple implementation, may be changed in the future
-#[cfg(feature = "fmt")]
-impl fmt::Write for MmioUartAxiLite {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.as_bytes() {
-            self.write_byte(*c);
+    #[test]
+    fn xps_timeout() {
+        // status: tx FIFO full
+        let status = Status::TX_FIFO_FULL;
+        let mut fake_registers: [u32; 4] = [0, 0, (status.bits() as u32).reverse_bits(), 0];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartXpsLite::new(base as usize);
+        let mut clock = FakeClock(0);
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            uart.read_timeout(&mut buf, &mut clock, 100),
+            Err(ReceiveError::Timeout)
+        );
+        assert_eq!(
+            uart.write_timeout(b"hello", &mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        assert_eq!(
+            uart.flush_timeout(&mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+
+        // rx holds 0xab, status: valid data, tx FIFO empty
+        let status = Status::RX_FIFO_VALID | Status::TX_FIFO_EMPTY;
+        unsafe {
+            base.write_volatile(0xabu32.reverse_bits());
+            base.add(2)
+                .write_volatile((status.bits() as u32).reverse_bits());
+        }
+        assert_eq!(uart.read_timeout(&mut buf, &mut clock, 100), Ok(4));
+        assert_eq!(buf, [0xab; 4]);
+        assert_eq!(uart.write_timeout(b"hello", &mut clock, 100), Ok(()));
+        assert_eq!(uart.flush_timeout(&mut clock, 100), Ok(()));
+    }
+
+    #[test]
+    fn axi_timeout() {
+        // status: tx FIFO full
+        let mut fake_registers: [u32; 4] = [0, 0, Status::TX_FIFO_FULL.bits() as u32, 0];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartAxiLite::new(base as usize);
+        let mut clock = FakeClock(0);
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            uart.read_timeout(&mut buf, &mut clock, 100),
+            Err(ReceiveError::Timeout)
+        );
+        assert_eq!(
+            uart.write_timeout(b"hello", &mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+        assert_eq!(
+            uart.flush_timeout(&mut clock, 100),
+            Err(TransmitError::Timeout)
+        );
+
+        // rx holds 0xab, status: valid data, tx FIFO empty
+        let status = Status::RX_FIFO_VALID | Status::TX_FIFO_EMPTY;
+        unsafe {
+            base.write_volatile(0xab);
+            base.add(2).write_volatile(status.bits() as u32);
         }
-        Ok(())
+        assert_eq!(uart.read_timeout(&mut buf, &mut clock, 100), Ok(4));
+        assert_eq!(buf, [0xab; 4]);
+        assert_eq!(uart.write_timeout(b"hello", &mut clock, 100), Ok(()));
+        assert_eq!(uart.flush_timeout(&mut clock, 100), Ok(()));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn xps_stats() {
+        // rx holds 0x41, status: valid data with a framing and an overrun error
+        let status = Status::RX_FIFO_VALID | Status::FRAME_ERROR | Status::OVERRUN_ERROR;
+        let mut fake_registers: [u32; 4] = [
+            0x41u32.reverse_bits(),
+            0,
+            (status.bits() as u32).reverse_bits(),
+            0,
+        ];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartXpsLite::new(base as usize);
+
+        uart.write_byte(0x42);
+        assert_eq!(uart.read_byte(), Some(0x41));
+        assert_eq!(
+            uart.stats(),
+            UartStats {
+                tx: 1,
+                rx: 1,
+                frame: 1,
+                overrun: 1,
+                ..UartStats::new()
+            }
+        );
+
+        unsafe {
+            base.add(2)
+                .write_volatile((Status::PARITY_ERROR.bits() as u32).reverse_bits())
+        };
+        assert_eq!(uart.read_byte(), None);
+        assert_eq!(std::format!("{}", uart.stats()), "tx:1 rx:1 fe:1 pe:1 oe:1");
+
+        uart.reset_stats();
+        assert_eq!(uart.stats(), UartStats::new());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn axi_stats() {
+        // rx holds 0x41, status: valid data with a framing and an overrun error
+        let status = Status::RX_FIFO_VALID | Status::FRAME_ERROR | Status::OVERRUN_ERROR;
+        let mut fake_registers: [u32; 4] = [0x41, 0, status.bits() as u32, 0];
+        let base = &mut fake_registers as *mut u32;
+        let uart = MmioUartAxiLite::new(base as usize);
+
+        uart.write_byte(0x42);
+        assert_eq!(uart.read_byte(), Some(0x41));
+        assert_eq!(
+            uart.stats(),
+            UartStats {
+                tx: 1,
+                rx: 1,
+                frame: 1,
+                overrun: 1,
+                ..UartStats::new()
+            }
+        );
+
+        unsafe {
+            base.add(2)
+                .write_volatile(Status::PARITY_ERROR.bits() as u32)
+        };
+        assert_eq!(uart.read_byte(), None);
+        assert_eq!(std::format!("{}", uart.stats()), "tx:1 rx:1 fe:1 pe:1 oe:1");
+
+        uart.reset_stats();
+        assert_eq!(uart.stats(), UartStats::new());
+    }
+
+    #[test]
+    fn xps_dump() {
+        // rx holds 0x41, status: valid data with a parity error
+        let status = Status::RX_FIFO_VALID | Status::PARITY_ERROR;
+        let mut fake_registers: [u32; 4] = [
+            0x41u32.reverse_bits(),
+            0,
+            (status.bits() as u32).reverse_bits(),
+            0,
+        ];
+        let uart = MmioUartXpsLite::new(&mut fake_registers as *mut u32 as usize);
+
+        let dump = RegisterDump {
+            status: None,
+            rx: None,
+        };
+        assert_eq!(uart.dump(DumpFlags::empty()), dump);
+        #[cfg(feature = "stats")]
+        assert_eq!(uart.stats(), UartStats::new());
+
+        let dump = RegisterDump {
+            status: Some(status),
+            rx: None,
+        };
+        assert_eq!(uart.dump(DumpFlags::STATUS), dump);
+        let dump = RegisterDump {
+            status: Some(status),
+            rx: Some(0x41),
+        };
+        assert_eq!(uart.dump(DumpFlags::RX), dump);
+        #[cfg(feature = "stats")]
+        assert_eq!(std::format!("{}", uart.stats()), "tx:0 rx:1 pe:2");
+    }
+
+    #[test]
+    fn axi_dump() {
+        // rx holds 0x41, status: tx FIFO empty
+        let mut fake_registers: [u32; 4] = [0x41, 0, Status::TX_FIFO_EMPTY.bits() as u32, 0];
+        let uart = MmioUartAxiLite::new(&mut fake_registers as *mut u32 as usize);
+
+        let dump = RegisterDump {
+            status: None,
+            rx: None,
+        };
+        assert_eq!(uart.dump(DumpFlags::empty()), dump);
+        // Nothing is read from the Rx FIFO without valid data
+        let dump = RegisterDump {
+            status: Some(Status::TX_FIFO_EMPTY),
+            rx: None,
+        };
+        assert_eq!(uart.dump(DumpFlags::STATUS | DumpFlags::RX), dump);
     }
 }